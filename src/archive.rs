@@ -0,0 +1,284 @@
+use anyhow::{anyhow, Context, Result};
+use flate2::read::GzDecoder;
+use serde::{Serialize, Deserialize};
+use std::io::{Cursor, Read};
+use std::{fs, path::{Path, PathBuf}};
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::mail::mbox_messages;
+use crate::roots::DEFAULT_MAX_FILE_SIZE;
+
+/// Separates an archive's path from the path of a member inside it, e.g. `release.zip!/docs/README.md`.
+pub const ARCHIVE_SEP: &str = "!/";
+
+/// File names we look inside; registered as the `archive` file type so roots pick them up.
+pub const ARCHIVE_GLOBS: &[&str] = &["*.zip", "*.jar", "*.war", "*.ear", "*.tar", "*.tgz", "*.tar.gz"];
+
+/// Bounds on the work a single archive can cause, so a zip bomb can't stall indexing.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct ArchiveLimits {
+    /// how many archives deep to look, the outermost one included; 0 disables archive search,
+    /// though mailboxes are still split into messages
+    pub max_depth: usize,
+    /// members read per archive, counting those of nested archives
+    pub max_members: usize,
+    /// skip members larger than this many uncompressed bytes
+    pub max_member_size: u64,
+    /// stop reading an archive once this many uncompressed bytes were taken from it
+    pub max_total_size: u64,
+}
+
+impl Default for ArchiveLimits {
+    fn default() -> Self {
+        ArchiveLimits {
+            max_depth: 2,
+            max_members: 10_000,
+            max_member_size: DEFAULT_MAX_FILE_SIZE,
+            max_total_size: 256 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveKind { Zip, Tar, TarGz, Mbox }
+
+/// Which archive format a file name suggests, if any.
+pub fn archive_kind(name: &str) -> Option<ArchiveKind> {
+    let n = name.to_ascii_lowercase();
+    if n.ends_with(".tar.gz") || n.ends_with(".tgz") {
+        Some(ArchiveKind::TarGz)
+    } else if n.ends_with(".tar") {
+        Some(ArchiveKind::Tar)
+    } else if [".zip", ".jar", ".war", ".ear", ".epub"].iter().any(|e| n.ends_with(e)) {
+        Some(ArchiveKind::Zip)
+    } else if n.ends_with(".mbox") || n.ends_with(".mbx") {
+        Some(ArchiveKind::Mbox)
+    } else {
+        None
+    }
+}
+
+/// Split a virtual path like `a.zip!/b.tar!/c.txt` into the archive on disk and the member
+/// path inside it. Plain paths, even ones that happen to contain `!/`, give `None`.
+pub fn split_virtual(path: &str) -> Option<(&str, &str)> {
+    path.match_indices(ARCHIVE_SEP)
+        .map(|(i, _)| i)
+        .find(|&i| archive_kind(&path[..i]).is_some())
+        .map(|i| (&path[..i], &path[i + ARCHIVE_SEP.len()..]))
+}
+
+/// A file read out of an archive. `path` is relative to the outermost archive and contains
+/// further `!/` separators for members of nested archives.
+pub struct Member {
+    pub path: String,
+    pub data: Vec<u8>,
+}
+
+struct Budget {
+    members: usize,
+    bytes: u64,
+}
+
+/// Visit the file members of the archive in `bytes`, descending into nested archives,
+/// until `limits` run out. Unreadable or corrupt parts are skipped.
+pub fn for_each_member(bytes: &[u8], kind: ArchiveKind, limits: &ArchiveLimits, mut f: impl FnMut(Member)) {
+    let mut budget = Budget { members: limits.max_members, bytes: limits.max_total_size };
+    let _ = walk(bytes, kind, "", 1, limits, &mut budget, &mut f);
+}
+
+fn walk(bytes: &[u8], kind: ArchiveKind, prefix: &str, depth: usize, limits: &ArchiveLimits, budget: &mut Budget, f: &mut dyn FnMut(Member)) -> Result<()> {
+    entries(bytes, kind, &mut |name, size, r| {
+        if budget.members == 0 || budget.bytes == 0 { return false; }
+        if size > limits.max_member_size { return true; }
+        budget.members -= 1;
+        let mut data = Vec::new();
+        if r.take(size.min(budget.bytes)).read_to_end(&mut data).is_err() { return true; }
+        budget.bytes -= data.len() as u64;
+        let path = format!("{}{}", prefix, name);
+        if let Some(inner) = archive_kind(name).filter(|_| depth < limits.max_depth) {
+            let _ = walk(&data, inner, &format!("{}{}", path, ARCHIVE_SEP), depth + 1, limits, budget, f);
+        }
+        f(Member { path, data });
+        true
+    })
+}
+
+/// Call `visit(name, size, reader)` for every regular file in the archive until it returns false.
+fn entries(bytes: &[u8], kind: ArchiveKind, visit: &mut dyn FnMut(&str, u64, &mut dyn Read) -> bool) -> Result<()> {
+    match kind {
+        ArchiveKind::Zip => {
+            let mut zip = zip::ZipArchive::new(Cursor::new(bytes))?;
+            for i in 0..zip.len() {
+                // encrypted or unsupported entries fail individually
+                let Ok(mut file) = zip.by_index(i) else { continue };
+                if !file.is_file() { continue; }
+                let name = file.name().to_string();
+                if !visit(&name, file.size(), &mut file) { break; }
+            }
+            Ok(())
+        }
+        ArchiveKind::Tar => tar_entries(bytes, visit),
+        ArchiveKind::TarGz => tar_entries(GzDecoder::new(bytes), visit),
+        // messages are numbered from 1, in mailbox order
+        ArchiveKind::Mbox => {
+            for (i, msg) in mbox_messages(bytes).iter().enumerate() {
+                if !visit(&format!("{}.eml", i + 1), msg.len() as u64, &mut &msg[..]) { break; }
+            }
+            Ok(())
+        }
+    }
+}
+
+fn tar_entries(r: impl Read, visit: &mut dyn FnMut(&str, u64, &mut dyn Read) -> bool) -> Result<()> {
+    let mut ar = tar::Archive::new(r);
+    for entry in ar.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() { continue; }
+        let name = entry.path()?.to_string_lossy().trim_start_matches("./").to_string();
+        let size = entry.size();
+        if !visit(&name, size, &mut entry) { break; }
+    }
+    Ok(())
+}
+
+/// The bytes of a file on disk or of an archive member given by its virtual path.
+pub fn read_path(path: &str) -> Result<Vec<u8>> {
+    let Some((outer, mut rest)) = split_virtual(path) else { return fs::read(path).with_context(|| format!("read {}", path)) };
+    let mut kind = archive_kind(outer).ok_or_else(|| anyhow!("{} is not an archive", outer))?;
+    let mut data = fs::read(outer).with_context(|| format!("read {}", outer))?;
+    loop {
+        let (name, next) = match rest.split_once(ARCHIVE_SEP) {
+            Some((n, r)) if archive_kind(n).is_some() => (n, Some(r)),
+            _ => (rest, None),
+        };
+        let mut found = None;
+        entries(&data, kind, &mut |n, size, r| {
+            if n != name { return true; }
+            let mut buf = Vec::new();
+            if r.take(size).read_to_end(&mut buf).is_ok() { found = Some(buf); }
+            false
+        })?;
+        data = found.ok_or_else(|| anyhow!("{} has no member {:?}", outer, name))?;
+        match next {
+            Some(r) => {
+                kind = archive_kind(name).ok_or_else(|| anyhow!("{} is not an archive", name))?;
+                rest = r;
+            }
+            None => return Ok(data),
+        }
+    }
+}
+
+/// Copy an archive member to a temp file, named like the member, so other programs can open it.
+pub fn extract_member(path: &str) -> Result<PathBuf> {
+    let data = read_path(path)?;
+    let name = Path::new(path).file_name().ok_or_else(|| anyhow!("{} does not name a file", path))?;
+    let dir = std::env::temp_dir().join("warpseek").join(format!("{:016x}", xxh3_64(path.as_bytes())));
+    fs::create_dir_all(&dir)?;
+    let out = dir.join(name);
+    fs::write(&out, data)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn zip_of(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut w = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in files {
+            w.start_file(*name, zip::write::SimpleFileOptions::default()).unwrap();
+            w.write_all(data).unwrap();
+        }
+        w.finish().unwrap().into_inner()
+    }
+
+    fn tar_of(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut b = tar::Builder::new(Vec::new());
+        for (name, data) in files {
+            let mut h = tar::Header::new_gnu();
+            h.set_size(data.len() as u64);
+            h.set_mode(0o644);
+            h.set_cksum();
+            b.append_data(&mut h, name, *data).unwrap();
+        }
+        b.into_inner().unwrap()
+    }
+
+    fn members(bytes: &[u8], kind: ArchiveKind, limits: &ArchiveLimits) -> Vec<(String, usize)> {
+        let mut out = Vec::new();
+        for_each_member(bytes, kind, limits, |m| out.push((m.path, m.data.len())));
+        out
+    }
+
+    #[test]
+    fn kinds_and_virtual_paths() {
+        assert_eq!(archive_kind("a.TAR.GZ"), Some(ArchiveKind::TarGz));
+        assert_eq!(archive_kind("lib.jar"), Some(ArchiveKind::Zip));
+        assert_eq!(archive_kind("inbox.mbox"), Some(ArchiveKind::Mbox));
+        assert_eq!(archive_kind("notes.gz"), None);
+        assert_eq!(split_virtual("a.zip!/b.tar!/c.txt"), Some(("a.zip", "b.tar!/c.txt")));
+        assert_eq!(split_virtual("wow!/c.txt"), None);
+        assert_eq!(split_virtual("plain.txt"), None);
+    }
+
+    #[test]
+    fn nested_archives_up_to_max_depth() {
+        let inner = zip_of(&[("deep.txt", b"deep")]);
+        let outer = tar_of(&[("top.txt", b"top"), ("inner.zip", &inner)]);
+        let limits = ArchiveLimits::default();
+        assert_eq!(members(&outer, ArchiveKind::Tar, &limits), [
+            ("top.txt".to_string(), 3),
+            ("inner.zip!/deep.txt".to_string(), 4),
+            ("inner.zip".to_string(), inner.len()),
+        ]);
+        let shallow = ArchiveLimits { max_depth: 1, ..limits };
+        assert_eq!(members(&outer, ArchiveKind::Tar, &shallow), [("top.txt".to_string(), 3), ("inner.zip".to_string(), inner.len())]);
+    }
+
+    #[test]
+    fn member_count_and_size_budgets() {
+        let zip = zip_of(&[("a", b"aaaa"), ("big", &[b'b'; 100]), ("c", b"cccc"), ("d", b"dddd")]);
+        let limits = ArchiveLimits { max_member_size: 10, ..ArchiveLimits::default() };
+        assert_eq!(members(&zip, ArchiveKind::Zip, &limits).len(), 3);
+        let few = ArchiveLimits { max_members: 2, ..limits.clone() };
+        assert_eq!(members(&zip, ArchiveKind::Zip, &few), [("a".to_string(), 4), ("c".to_string(), 4)]);
+        // the last member read is cut short once the total runs out
+        let small = ArchiveLimits { max_total_size: 6, ..limits };
+        assert_eq!(members(&zip, ArchiveKind::Zip, &small), [("a".to_string(), 4), ("c".to_string(), 2)]);
+    }
+
+    #[test]
+    fn nested_members_share_the_outer_budget() {
+        let inner = tar_of(&[("x", b"x"), ("y", b"y"), ("z", b"z")]);
+        let outer = zip_of(&[("inner.tar", &inner), ("after", b"after")]);
+        let limits = ArchiveLimits { max_members: 3, ..ArchiveLimits::default() };
+        let got: Vec<String> = members(&outer, ArchiveKind::Zip, &limits).into_iter().map(|(p, _)| p).collect();
+        assert_eq!(got, ["inner.tar!/x", "inner.tar!/y", "inner.tar"]);
+    }
+
+    #[test]
+    fn corrupt_empty_and_truncated_archives_yield_nothing_or_what_was_readable() {
+        let limits = ArchiveLimits::default();
+        assert!(members(b"", ArchiveKind::Zip, &limits).is_empty());
+        assert!(members(b"PK\x03\x04 not really", ArchiveKind::Zip, &limits).is_empty());
+        assert!(members(b"", ArchiveKind::TarGz, &limits).is_empty());
+        let zip = zip_of(&[("a.txt", b"hello")]);
+        assert!(members(&zip[..zip.len() - 10], ArchiveKind::Zip, &limits).is_empty());
+        // a tar cut off inside its second entry still gives the first
+        let tar = tar_of(&[("a.txt", b"hello"), ("b.txt", &[b'b'; 2000])]);
+        assert_eq!(members(&tar[..1200], ArchiveKind::Tar, &limits), [("a.txt".to_string(), 5)]);
+    }
+
+    #[test]
+    fn read_path_reaches_into_nested_members() {
+        let dir = tempfile::tempdir().unwrap();
+        let outer = dir.path().join("outer.zip");
+        fs::write(&outer, zip_of(&[("inner.tar", &tar_of(&[("docs/readme.md", b"# hi")]))])).unwrap();
+        let outer = outer.to_string_lossy();
+        assert_eq!(read_path(&format!("{}!/inner.tar!/docs/readme.md", outer)).unwrap(), b"# hi");
+        assert!(read_path(&format!("{}!/inner.tar!/missing.md", outer)).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+use std::io::{self, Read, Write};
+
+use crate::encoding::trim_cut_char;
+
+/// Default cap on how many bytes a single compressed file may expand to.
+pub const DEFAULT_MAX_DECOMPRESSED_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Single-file compression formats we can see through.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression { Gzip, Zstd, Xz, Bzip2 }
+
+impl Compression {
+    /// Sniff the format from the leading magic bytes; file names are not consulted.
+    pub fn detect(bytes: &[u8]) -> Option<Compression> {
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            Some(Compression::Gzip)
+        } else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else if bytes.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(Compression::Xz)
+        } else if bytes.starts_with(b"BZh") && bytes.get(3).is_some_and(|b| (b'1'..=b'9').contains(b)) {
+            Some(Compression::Bzip2)
+        } else {
+            None
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+            Compression::Xz => "xz",
+            Compression::Bzip2 => "bzip2",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Compression::Gzip => "application/gzip",
+            Compression::Zstd => "application/zstd",
+            Compression::Xz => "application/x-xz",
+            Compression::Bzip2 => "application/x-bzip2",
+        }
+    }
+}
+
+/// Expand `bytes` if they are compressed, keeping at most `budget` bytes of output, cut back
+/// to a whole char when the budget ends inside UTF-8 text.
+/// A stream that is corrupt or truncated part way still yields what decoded before the error.
+/// `None` for data that isn't compressed, can't be decoded at all, or when `budget` is 0.
+pub fn decompress(bytes: &[u8], budget: u64) -> Option<(Compression, Vec<u8>)> {
+    let kind = Compression::detect(bytes)?;
+    if budget == 0 { return None; }
+    let mut out = Vec::new();
+    match kind {
+        Compression::Gzip => { let _ = MultiGzDecoder::new(bytes).take(budget).read_to_end(&mut out); }
+        Compression::Bzip2 => { let _ = MultiBzDecoder::new(bytes).take(budget).read_to_end(&mut out); }
+        Compression::Zstd => {
+            let decoder = ruzstd::decoding::StreamingDecoder::new(bytes).ok()?;
+            let _ = decoder.take(budget).read_to_end(&mut out);
+        }
+        Compression::Xz => {
+            // lzma-rs only decodes into a writer, which refuses further output once the budget is spent
+            let mut w = Capped { out: &mut out, left: budget };
+            let _ = lzma_rs::xz_decompress(&mut io::BufReader::new(bytes), &mut w);
+        }
+    }
+    if out.len() as u64 == budget { trim_cut_char(&mut out); }
+    if out.is_empty() { return None; }
+    Some((kind, out))
+}
+
+struct Capped<'a> {
+    out: &'a mut Vec<u8>,
+    left: u64,
+}
+
+impl Write for Capped<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.left == 0 { return Err(io::Error::new(io::ErrorKind::WriteZero, "decompression budget exhausted")); }
+        let n = buf.len().min(self.left as usize);
+        self.out.extend_from_slice(&buf[..n]);
+        self.left -= n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut e = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        e.write_all(data).unwrap();
+        e.finish().unwrap()
+    }
+
+    fn compressed(kind: Compression, data: &[u8]) -> Vec<u8> {
+        match kind {
+            Compression::Gzip => gzip(data),
+            Compression::Bzip2 => {
+                let mut e = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+                e.write_all(data).unwrap();
+                e.finish().unwrap()
+            }
+            Compression::Xz => {
+                let mut out = Vec::new();
+                lzma_rs::xz_compress(&mut &data[..], &mut out).unwrap();
+                out
+            }
+            Compression::Zstd => ruzstd::encoding::compress_to_vec(data, ruzstd::encoding::CompressionLevel::Fastest),
+        }
+    }
+
+    const ALL: [Compression; 4] = [Compression::Gzip, Compression::Zstd, Compression::Xz, Compression::Bzip2];
+
+    #[test]
+    fn every_format_round_trips_and_is_detected() {
+        let text = b"the quick brown fox jumps over the lazy dog\n".repeat(50);
+        for kind in ALL {
+            assert_eq!(decompress(&compressed(kind, &text), 1 << 20), Some((kind, text.clone())), "{}", kind.name());
+        }
+    }
+
+    #[test]
+    fn output_stops_at_the_budget() {
+        let zeros = vec![0u8; 1 << 20];
+        for kind in ALL {
+            let (_, out) = decompress(&compressed(kind, &zeros), 1000).unwrap();
+            assert_eq!(out.len(), 1000, "{}", kind.name());
+        }
+        assert_eq!(decompress(&gzip(b"hello"), 0), None);
+    }
+
+    #[test]
+    fn budget_inside_a_char_keeps_whole_chars() {
+        // 3-byte chars after two ASCII bytes, so a budget of 1000 ends inside one
+        let text = format!("xx{}", "€".repeat(1000));
+        for kind in ALL {
+            let (_, out) = decompress(&compressed(kind, text.as_bytes()), 1000).unwrap();
+            assert_eq!(out.len(), 998, "{}", kind.name());
+            let decoded = crate::encoding::decode_text(&out).unwrap();
+            assert_eq!((decoded.text.chars().count(), decoded.encoding), (334, "UTF-8"));
+        }
+        // text that isn't UTF-8 is not cut
+        let latin1 = b"caf\xe9 ".repeat(400);
+        assert_eq!(decompress(&gzip(&latin1), 1000).map(|(_, out)| out.len()), Some(1000));
+    }
+
+    #[test]
+    fn plain_and_header_only_input() {
+        assert_eq!(Compression::detect(b"hello"), None);
+        assert_eq!(Compression::detect(b""), None);
+        assert_eq!(Compression::detect(b"BZh"), None);
+        assert_eq!(Compression::detect(b"BZhx"), None);
+        assert_eq!(decompress(b"hello", 1000), None);
+        for kind in ALL {
+            let c = compressed(kind, b"hello");
+            assert_eq!(Compression::detect(&c), Some(kind));
+            assert_eq!(decompress(&c[..6.min(c.len())], 1000), None, "{}", kind.name());
+        }
+    }
+
+    #[test]
+    fn truncated_streams_keep_what_decoded() {
+        let text: Vec<u8> = (0..200_000u32).flat_map(|i| format!("line {}\n", i).into_bytes()).collect();
+        let c = gzip(&text);
+        let (_, out) = decompress(&c[..c.len() / 2], 1 << 24).unwrap();
+        assert!(!out.is_empty() && out.len() < text.len());
+        assert!(text.starts_with(&out));
+    }
+
+    #[test]
+    fn concatenated_gzip_members_are_all_read() {
+        let mut c = gzip(b"first ");
+        c.extend(gzip(b"second"));
+        assert_eq!(decompress(&c, 1000).map(|(_, out)| out), Some(b"first second".to_vec()));
+    }
+}
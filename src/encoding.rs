@@ -0,0 +1,122 @@
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+
+/// Text decoded to UTF-8, along with the name of the encoding it was stored in.
+pub struct Decoded {
+    pub text: String,
+    pub encoding: &'static str,
+}
+
+/// Only look at this much of a file when guessing its charset.
+const DETECT_BYTES: usize = 64 * 1024;
+
+/// Decode `bytes` as text: honour a BOM, accept plain UTF-8, recognise BOM-less UTF-16
+/// by its NUL pattern, and otherwise let chardetng guess a legacy charset.
+/// Returns `None` for anything that looks binary.
+pub fn decode_text(bytes: &[u8]) -> Option<Decoded> {
+    if let Some((enc, bom)) = Encoding::for_bom(bytes) {
+        return transcode(enc, &bytes[bom..]);
+    }
+    if let Ok(s) = std::str::from_utf8(bytes) {
+        if looks_like_text(s) { return Some(Decoded { text: s.to_string(), encoding: UTF_8.name() }); }
+        // ASCII in BOM-less UTF-16 is valid UTF-8 too, only full of NULs
+        return sniff_utf16(bytes).and_then(|enc| transcode(enc, bytes));
+    }
+    if let Some(enc) = sniff_utf16(bytes) {
+        return transcode(enc, bytes);
+    }
+    if memchr::memchr(0, bytes).is_some() { return None; }
+    let mut detector = EncodingDetector::new();
+    let sample = &bytes[..bytes.len().min(DETECT_BYTES)];
+    detector.feed(sample, sample.len() == bytes.len());
+    transcode(detector.guess(None, true), bytes)
+}
+
+fn transcode(enc: &'static Encoding, bytes: &[u8]) -> Option<Decoded> {
+    let text = enc.decode_without_bom_handling_and_without_replacement(bytes)?;
+    looks_like_text(&text).then(|| Decoded { text: text.into_owned(), encoding: enc.name() })
+}
+
+/// BOM-less UTF-16 from Windows tools: mostly-ASCII text leaves every other byte NUL.
+fn sniff_utf16(bytes: &[u8]) -> Option<&'static Encoding> {
+    let sample = &bytes[..bytes.len().min(DETECT_BYTES) & !1];
+    if sample.len() < 4 { return None; }
+    let pairs = sample.len() / 2;
+    let even = sample.iter().step_by(2).filter(|&&b| b == 0).count();
+    let odd = sample.iter().skip(1).step_by(2).filter(|&&b| b == 0).count();
+    // high bytes are zero for ASCII code units, low bytes almost never are
+    if odd * 10 >= pairs * 7 && even * 10 <= pairs { return Some(UTF_16LE); }
+    if even * 10 >= pairs * 7 && odd * 10 <= pairs { return Some(UTF_16BE); }
+    None
+}
+
+/// Drop a UTF-8 char cut off at the end of `bytes`, as output capped at a byte budget can end
+/// in one. Bytes that aren't UTF-8 before that are left for `decode_text` to make sense of.
+pub fn trim_cut_char(bytes: &mut Vec<u8>) {
+    if let Err(e) = std::str::from_utf8(bytes) {
+        if e.error_len().is_none() { bytes.truncate(e.valid_up_to()); }
+    }
+}
+
+/// Same rule as `is_probably_text`, applied after decoding: no control chars below tab.
+pub fn looks_like_text(s: &str) -> bool {
+    !s.chars().any(|c| c < '\u{09}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decoded(bytes: &[u8]) -> Option<(String, &'static str)> {
+        decode_text(bytes).map(|d| (d.text, d.encoding))
+    }
+
+    fn utf16(s: &str, le: bool) -> Vec<u8> {
+        s.encode_utf16().flat_map(|u| if le { u.to_le_bytes() } else { u.to_be_bytes() }).collect()
+    }
+
+    #[test]
+    fn utf8_with_and_without_bom() {
+        assert_eq!(decoded("naïve café".as_bytes()), Some(("naïve café".to_string(), "UTF-8")));
+        assert_eq!(decoded(b"\xEF\xBB\xBFhello"), Some(("hello".to_string(), "UTF-8")));
+        assert_eq!(decoded(b""), Some((String::new(), "UTF-8")));
+    }
+
+    #[test]
+    fn utf16_by_bom_and_by_nul_pattern() {
+        let mut le = vec![0xFF, 0xFE];
+        le.extend(utf16("hello world", true));
+        assert_eq!(decoded(&le), Some(("hello world".to_string(), "UTF-16LE")));
+        assert_eq!(decoded(&utf16("hello world", true)), Some(("hello world".to_string(), "UTF-16LE")));
+        assert_eq!(decoded(&utf16("hello world", false)), Some(("hello world".to_string(), "UTF-16BE")));
+    }
+
+    #[test]
+    fn truncated_utf16_is_rejected() {
+        let mut le = vec![0xFF, 0xFE];
+        le.extend(utf16("hello", true));
+        le.pop();
+        assert_eq!(decoded(&le), None);
+    }
+
+    #[test]
+    fn legacy_charsets_are_guessed() {
+        let latin1 = b"Le caf\xE9 est pr\xE8s de la gare, \xE0 c\xF4t\xE9 du th\xE9\xE2tre o\xF9 l'on joue des pi\xE8ces.";
+        let (text, encoding) = decoded(latin1).unwrap();
+        assert_eq!(encoding, "windows-1252");
+        assert!(text.starts_with("Le café est près"));
+    }
+
+    #[test]
+    fn binary_is_rejected() {
+        assert_eq!(decoded(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), None);
+        assert_eq!(decoded(b"abc\x01def"), None);
+        assert_eq!(decoded(b"\0"), None);
+    }
+
+    #[test]
+    fn short_input_is_not_taken_for_utf16() {
+        assert_eq!(sniff_utf16(b"a\0b"), None);
+        assert_eq!(sniff_utf16(b"abcdef"), None);
+    }
+}
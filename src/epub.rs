@@ -0,0 +1,138 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::io::Cursor;
+
+use crate::encoding::decode_text;
+use crate::extract::Extracted;
+use crate::markup::{extract_markup, MarkupKind};
+use crate::office::read_part;
+use crate::xml::{attr, resolve, walk_xml, Tag};
+
+/// EPUB books; registered as the `ebook` file type.
+pub const EPUB_GLOBS: &[&str] = &["*.epub"];
+
+pub fn is_epub(name: &str) -> bool {
+    name.to_ascii_lowercase().ends_with(".epub")
+}
+
+/// The chapters of a book in reading order, as their path inside the package and their text.
+/// Every chapter carries the book's title and author; a chapter's own title and headings go
+/// in `headings`. `budget` caps how many bytes are read from any one part.
+pub fn epub_chapters(bytes: &[u8], budget: u64) -> Result<Vec<(String, Extracted)>> {
+    let mut zip = zip::ZipArchive::new(Cursor::new(bytes))?;
+    let container = read_part(&mut zip, "META-INF/container.xml", budget).ok_or_else(|| anyhow!("no container.xml"))?;
+    let mut opf_path = None;
+    walk_xml(&container, |tag| {
+        if let Tag::Open(e) = tag {
+            if e.local_name().as_ref() == b"rootfile" && opf_path.is_none() { opf_path = attr(e, b"full-path"); }
+        }
+    });
+    let opf_path = opf_path.ok_or_else(|| anyhow!("no rootfile in container.xml"))?;
+    let opf = read_part(&mut zip, &opf_path, budget).ok_or_else(|| anyhow!("missing {}", opf_path))?;
+    let opf_dir = opf_path.rsplit_once('/').map_or("", |(d, _)| d);
+
+    let (mut title, mut author, mut field) = (None, None, None);
+    let (mut items, mut spine) = (HashMap::new(), Vec::new());
+    walk_xml(&opf, |tag| match tag {
+        Tag::Open(e) => {
+            field = None;
+            match e.local_name().as_ref() {
+                b"title" if title.is_none() => field = Some(b"title".as_slice()),
+                b"creator" if author.is_none() => field = Some(b"creator".as_slice()),
+                b"item" => if let (Some(id), Some(href)) = (attr(e, b"id"), attr(e, b"href")) { items.insert(id, href); },
+                b"itemref" => if let Some(id) = attr(e, b"idref") { spine.push(id); },
+                _ => {}
+            }
+        }
+        Tag::Close(_) => field = None,
+        Tag::Text(s) if !s.trim().is_empty() => match field {
+            Some(b"title") => title = Some(s.trim().to_string()),
+            Some(b"creator") => author = Some(s.trim().to_string()),
+            _ => {}
+        },
+        _ => {}
+    });
+
+    let mut chapters = Vec::new();
+    for id in spine {
+        let Some(href) = items.get(&id) else { continue };
+        let path = resolve(opf_dir, &percent_decode(href.split('#').next().unwrap_or_default()));
+        let Some(text) = read_part(&mut zip, &path, budget).and_then(|b| decode_text(&b)) else { continue };
+        let mut x = extract_markup(&text.text, MarkupKind::Html);
+        if let Some(t) = x.title.take().filter(|t| Some(t) != title.as_ref()) { x.headings.insert(0, t); }
+        x.title = title.clone();
+        x.author = author.clone();
+        chapters.push((path, x));
+    }
+    Ok(chapters)
+}
+
+/// Manifest hrefs are URLs, so names with spaces and the like arrive %-encoded.
+fn percent_decode(s: &str) -> String {
+    let b = s.as_bytes();
+    let mut out = Vec::with_capacity(b.len());
+    let mut i = 0;
+    while i < b.len() {
+        let hex = (b[i] == b'%').then(|| s.get(i + 1..i + 3)).flatten().and_then(|h| u8::from_str_radix(h, 16).ok());
+        match hex {
+            Some(c) => { out.push(c); i += 3; }
+            None => { out.push(b[i]); i += 1; }
+        }
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn zip_of(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut w = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in files {
+            w.start_file(*name, zip::write::SimpleFileOptions::default()).unwrap();
+            w.write_all(data.as_bytes()).unwrap();
+        }
+        w.finish().unwrap().into_inner()
+    }
+
+    const CONTAINER: &str = r#"<container><rootfiles><rootfile full-path="OEBPS/content.opf"/><rootfile full-path="other.opf"/></rootfiles></container>"#;
+    const OPF: &str = r#"<package xmlns:dc="http://purl.org/dc/elements/1.1/"><metadata><dc:title>The Book</dc:title><dc:creator>Mary</dc:creator><dc:creator>Percy</dc:creator></metadata>
+        <manifest><item id="c1" href="Chapter%201.xhtml"/><item id="c2" href="text/two.xhtml#start"/><item id="css" href="style.css"/></manifest>
+        <spine><itemref idref="c2"/><itemref idref="missing"/><itemref idref="c1"/></spine></package>"#;
+
+    #[test]
+    fn chapters_follow_the_spine_and_carry_the_book_title() {
+        let book = zip_of(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("OEBPS/content.opf", OPF),
+            ("OEBPS/Chapter 1.xhtml", "<html><head><title>The Book</title></head><body><h1>One</h1><p>It begins.</p></body></html>"),
+            ("OEBPS/text/two.xhtml", "<html><head><title>Interlude</title></head><body><p>Meanwhile.</p></body></html>"),
+        ]);
+        let chapters = epub_chapters(&book, 1 << 20).unwrap();
+        let paths: Vec<&str> = chapters.iter().map(|(p, _)| p.as_str()).collect();
+        assert_eq!(paths, ["OEBPS/text/two.xhtml", "OEBPS/Chapter 1.xhtml"]);
+        let (two, one) = (&chapters[0].1, &chapters[1].1);
+        assert_eq!(two.text, "Interlude\nMeanwhile.");
+        // a chapter's own title is one of its headings, unless it just repeats the book's
+        assert_eq!((two.headings.clone(), one.headings.clone()), (vec!["Interlude".to_string()], vec!["One".to_string()]));
+        for (_, x) in &chapters {
+            assert_eq!((x.title.as_deref(), x.author.as_deref()), (Some("The Book"), Some("Mary")));
+        }
+    }
+
+    #[test]
+    fn a_book_without_its_package_document() {
+        assert!(epub_chapters(&zip_of(&[("mimetype", "application/epub+zip")]), 1 << 20).is_err());
+        assert!(epub_chapters(&zip_of(&[("META-INF/container.xml", CONTAINER)]), 1 << 20).is_err());
+        assert!(epub_chapters(&zip_of(&[("META-INF/container.xml", "<container/>")]), 1 << 20).is_err());
+    }
+
+    #[test]
+    fn hrefs_are_percent_decoded() {
+        assert_eq!(percent_decode("Chapter%201%2Exhtml"), "Chapter 1.xhtml");
+        assert_eq!(percent_decode("caf%C3%A9.xhtml"), "café.xhtml");
+        assert_eq!(percent_decode("100%25 %zz %4"), "100% %zz %4");
+        assert_eq!(percent_decode("%"), "%");
+    }
+}
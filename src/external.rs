@@ -0,0 +1,174 @@
+use serde::{Serialize, Deserialize};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use std::{fs, thread};
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::archive::split_virtual;
+use crate::encoding::{decode_text, trim_cut_char};
+use crate::extract::{Extracted, Extractor};
+
+fn default_timeout_secs() -> u64 { 30 }
+
+/// A local program that turns files into text on its stdout, configured under `extractors`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ExternalCommand {
+    /// program and arguments, split on whitespace unless quoted; `{path}` is replaced by the
+    /// file to extract, or the path is appended if it doesn't appear
+    pub command: String,
+    /// file extensions (without the dot) and MIME types to run it for
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    #[serde(default)]
+    pub mime_types: Vec<String>,
+    /// kill the program and fall back to the built-in extractors after this long
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+/// Runs an `ExternalCommand`. Archive members are written to a temp file first, since the
+/// program needs a path it can open.
+pub struct ExternalExtractor {
+    cmd: ExternalCommand,
+    argv: Vec<String>,
+    /// most stdout that is kept
+    budget: u64,
+    version: OnceLock<String>,
+}
+
+impl ExternalExtractor {
+    pub fn new(cmd: ExternalCommand, budget: u64) -> ExternalExtractor {
+        let argv = split_command(&cmd.command);
+        ExternalExtractor { cmd, argv, budget, version: OnceLock::new() }
+    }
+
+    fn run(&self, path: &Path) -> Option<Vec<u8>> {
+        let (program, args) = self.argv.split_first()?;
+        let path = path.to_string_lossy();
+        let mut args: Vec<String> = args.iter().map(|a| a.replace("{path}", &path)).collect();
+        if !self.argv.iter().any(|a| a.contains("{path}")) { args.push(path.to_string()); }
+        let child = Command::new(program).args(&args).stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::null()).spawn().ok()?;
+        let (ok, out) = wait_with_output(child, Duration::from_secs(self.cmd.timeout_secs), self.budget)?;
+        ok.then_some(out)
+    }
+}
+
+impl Extractor for ExternalExtractor {
+    fn name(&self) -> &str {
+        let program = self.argv.first().map_or("", |p| p.as_str());
+        Path::new(program).file_name().and_then(|n| n.to_str()).unwrap_or(program)
+    }
+
+    /// The first line `--version` prints, e.g. `pandoc 3.1.3`, or `unknown`.
+    fn version(&self) -> String {
+        self.version.get_or_init(|| {
+            let out = self.argv.first().and_then(|p| {
+                let child = Command::new(p).arg("--version").stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::null()).spawn().ok()?;
+                wait_with_output(child, Duration::from_secs(5), 64 * 1024)
+            });
+            out.and_then(|(_, out)| String::from_utf8_lossy(&out).lines().map(str::trim).find(|l| !l.is_empty()).map(String::from))
+                .unwrap_or_else(|| "unknown".to_string())
+        }).clone()
+    }
+
+    fn extract(&self, path: &str, bytes: &[u8]) -> Option<Extracted> {
+        let on_disk = split_virtual(path).is_none() && Path::new(path).is_file();
+        let out = if on_disk {
+            self.run(Path::new(path))
+        } else {
+            let tmp = temp_copy(path, bytes)?;
+            let out = self.run(&tmp);
+            let _ = fs::remove_file(&tmp);
+            out
+        }?;
+        decode_text(&out).map(Extracted::from)
+    }
+}
+
+/// Wait for `child` to exit, killing it after `timeout`. Returns whether it exited
+/// successfully and up to `budget` bytes of what it wrote to stdout, ending on a whole char.
+fn wait_with_output(mut child: Child, timeout: Duration, budget: u64) -> Option<(bool, Vec<u8>)> {
+    let mut stdout = child.stdout.take()?;
+    // read on another thread so a chatty program can't block on a full pipe
+    let reader = thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = (&mut stdout).take(budget).read_to_end(&mut buf);
+        if buf.len() as u64 == budget { trim_cut_char(&mut buf); }
+        let _ = io::copy(&mut stdout, &mut io::sink());
+        buf
+    });
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => return Some((status.success(), reader.join().ok()?)),
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+            _ => {
+                // anything the program started may still hold stdout open, so the reader is left
+                // to finish on its own
+                let _ = child.kill();
+                let _ = child.wait();
+                return Some((false, Vec::new()));
+            }
+        }
+    }
+}
+
+/// Write `bytes` to a temp file named like `path`, so the program can tell what it is.
+fn temp_copy(path: &str, bytes: &[u8]) -> Option<PathBuf> {
+    let name = Path::new(path).file_name()?;
+    let dir = std::env::temp_dir().join("warpseek").join(format!("{:016x}", xxh3_64(path.as_bytes())));
+    fs::create_dir_all(&dir).ok()?;
+    let out = dir.join(name);
+    fs::write(&out, bytes).ok()?;
+    Some(out)
+}
+
+/// Split a command line on whitespace, keeping single- or double-quoted parts together.
+fn split_command(s: &str) -> Vec<String> {
+    let (mut args, mut cur, mut quote, mut any) = (Vec::new(), String::new(), None, false);
+    for c in s.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => cur.push(c),
+            (None, '"') | (None, '\'') => { quote = Some(c); any = true; }
+            (None, c) if c.is_whitespace() => {
+                if any || !cur.is_empty() { args.push(std::mem::take(&mut cur)); }
+                any = false;
+            }
+            (None, c) => cur.push(c),
+        }
+    }
+    if any || !cur.is_empty() { args.push(cur); }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    fn sh(script: &str) -> Child {
+        Command::new("sh").args(["-c", script]).stdout(Stdio::piped()).spawn().unwrap()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn output_is_capped_on_a_char_boundary() {
+        let (ok, out) = wait_with_output(sh("printf 'x€€€'"), Duration::from_secs(5), 6).unwrap();
+        assert!(ok);
+        assert_eq!(String::from_utf8(out).unwrap(), "x€");
+        let (_, out) = wait_with_output(sh("printf 'x€€€'"), Duration::from_secs(5), 1024).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "x€€€");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_hung_program_is_killed() {
+        let started = Instant::now();
+        assert_eq!(wait_with_output(sh("exec sleep 10"), Duration::from_millis(100), 1024), Some((false, Vec::new())));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,372 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::archive::{archive_kind, for_each_member, ArchiveKind, ArchiveLimits, ARCHIVE_SEP};
+use crate::compress::{decompress, Compression};
+use crate::encoding::{decode_text, Decoded};
+use crate::epub::{epub_chapters, is_epub};
+use crate::external::ExternalExtractor;
+use crate::mail::{extract_message, is_message, MailHeaders};
+use crate::markup::{extract_markup, markup_kind};
+use crate::media::{extract_media, MediaInfo};
+use crate::office::{extract_office, office_kind};
+use crate::notebook::extract_notebook;
+use crate::pdf::{extract_pdf, is_pdf};
+use crate::search::Config;
+
+/// Text and metadata pulled out of one file.
+#[derive(Debug, Clone, Default)]
+pub struct Extracted {
+    pub text: String,
+    /// charset the text was decoded from
+    pub encoding: &'static str,
+    pub compression: Option<Compression>,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    /// section headings, for markup documents
+    pub headings: Vec<String>,
+    /// for mail messages, the headers they can be searched by
+    pub mail: Option<MailHeaders>,
+    /// for photos, audio and video, their EXIF data or tags
+    pub media: Option<MediaInfo>,
+    /// for paged documents, the number of pages and the 1-based line each page starts on
+    pub page_count: Option<u64>,
+    pub pages: Vec<usize>,
+    /// for notebooks, the 1-based line each cell starts on and the cell's type
+    pub cells: Vec<(usize, &'static str)>,
+    /// why only the name and metadata could be indexed, e.g. `encrypted`
+    pub skipped: Option<String>,
+    /// name and version of the extractor that produced this, filled in by the `Registry`
+    pub extractor: String,
+    pub extractor_version: String,
+}
+
+impl From<Decoded> for Extracted {
+    fn from(d: Decoded) -> Self {
+        Extracted { text: d.text, encoding: d.encoding, ..Default::default() }
+    }
+}
+
+/// Turns the bytes of one kind of file into searchable text.
+pub trait Extractor: Send + Sync {
+    /// Short name recorded with every document this extractor produced, e.g. `pdf`.
+    fn name(&self) -> &str;
+
+    /// Recorded alongside the name; built-in extractors share the crate version.
+    fn version(&self) -> String { env!("CARGO_PKG_VERSION").to_string() }
+
+    /// The text of `bytes`, read from `path` (which may be an archive member). `None` hands the
+    /// file on to the next extractor that claims it, and finally to plain text decoding.
+    fn extract(&self, path: &str, bytes: &[u8]) -> Option<Extracted>;
+}
+
+/// One document inside a container: its path there, the bytes it was read from, and its text
+/// if it has any.
+pub struct Inner<'a> {
+    pub path: String,
+    pub bytes: &'a [u8],
+    pub text: Option<Extracted>,
+}
+
+/// Opens files that hold other documents, such as archives and EPUB books, which are indexed
+/// as their documents instead of as one.
+pub trait Container: Send + Sync {
+    /// Whether `path` is a container of this kind, judged by its name.
+    fn handles(&self, path: &str) -> bool;
+
+    /// Hand every document in `bytes`, read from `path`, to `found`; `registry` extracts
+    /// whatever isn't the container's own format.
+    fn for_each_document(&self, path: &str, bytes: &[u8], registry: &Registry, found: &mut dyn FnMut(Inner));
+}
+
+/// Extractors by the file extensions and MIME types they handle. Commands from the config
+/// are tried before the built-in extractors, extension matches before MIME type matches,
+/// and plain text decoding comes last. Containers are looked up before any of them.
+pub struct Registry {
+    containers: Vec<Box<dyn Container>>,
+    extractors: Vec<Box<dyn Extractor>>,
+    by_ext: HashMap<String, Vec<usize>>,
+    by_mime: HashMap<String, Vec<usize>>,
+    text: Text,
+}
+
+impl Registry {
+    pub fn new(cfg: &Config) -> Registry {
+        let budget = cfg.max_decompressed_size;
+        let mut r = Registry { containers: Vec::new(), extractors: Vec::new(), by_ext: HashMap::new(), by_mime: HashMap::new(), text: Text { budget } };
+        for c in &cfg.extractors {
+            r.register(Box::new(ExternalExtractor::new(c.clone(), budget)), &c.extensions, &c.mime_types);
+        }
+        r.register(Box::new(Office { budget }), &["docx", "xlsx", "pptx", "odt", "ods"], &[
+            "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+            "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+            "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+            "application/vnd.oasis.opendocument.text",
+            "application/vnd.oasis.opendocument.spreadsheet",
+        ]);
+        r.register(Box::new(Notebook { keep_outputs: cfg.notebook_outputs }), &["ipynb"], &["application/x-ipynb+json"]);
+        r.register(Box::new(Mail), &["eml"], &["message/rfc822"]);
+        r.register(Box::new(Pdf), &["pdf"], &["application/pdf"]);
+        r.register(Box::new(Media), &["jpg", "jpeg", "png", "tif", "tiff", "mp3", "flac", "ogg", "oga", "opus", "m4a", "mp4", "m4v", "mov"], &[
+            "image/jpeg", "image/png", "image/tiff", "audio/mpeg", "audio/flac", "audio/ogg", "audio/mp4", "video/mp4", "video/quicktime",
+        ]);
+        r.register(Box::new(Markup), &["html", "htm", "xhtml", "xml"], &["text/html", "application/xhtml+xml", "application/xml"]);
+        // books before archives, which would otherwise take them for plain zips; mailboxes are
+        // split into messages even with archive search off
+        r.register_container(Box::new(Epub { budget }));
+        r.register_container(Box::new(Archive { kinds: &[ArchiveKind::Mbox], limits: ArchiveLimits { max_depth: 1, ..cfg.archives.clone() } }));
+        if cfg.archives.max_depth > 0 {
+            r.register_container(Box::new(Archive { kinds: &[ArchiveKind::Zip, ArchiveKind::Tar, ArchiveKind::TarGz], limits: cfg.archives.clone() }));
+        }
+        r
+    }
+
+    /// Add `c`, after every container already registered.
+    pub fn register_container(&mut self, c: Box<dyn Container>) {
+        self.containers.push(c);
+    }
+
+    /// The first registered container that handles `path`, if it is one.
+    pub fn container(&self, path: &str) -> Option<&dyn Container> {
+        self.containers.iter().find(|c| c.handles(path)).map(|c| c.as_ref())
+    }
+
+    /// Add `ex` for files with one of `exts` (without the dot) or one of `mimes`, after every
+    /// extractor already registered for them.
+    pub fn register<S: AsRef<str>>(&mut self, ex: Box<dyn Extractor>, exts: &[S], mimes: &[S]) {
+        let i = self.extractors.len();
+        self.extractors.push(ex);
+        for e in exts { self.by_ext.entry(e.as_ref().trim_start_matches('.').to_ascii_lowercase()).or_default().push(i); }
+        for m in mimes { self.by_mime.entry(m.as_ref().to_ascii_lowercase()).or_default().push(i); }
+    }
+
+    /// Get the searchable text out of `bytes`, read from `path`, noting which extractor
+    /// produced it. `None` means there is nothing to index beyond the name.
+    pub fn extract(&self, path: &str, bytes: &[u8]) -> Option<Extracted> {
+        let ext = Path::new(path).extension().map(|e| e.to_string_lossy().to_ascii_lowercase()).unwrap_or_default();
+        let by_mime = mime_type(path, bytes).and_then(|m| self.by_mime.get(m));
+        let mut tried = Vec::new();
+        for &i in self.by_ext.get(&ext).into_iter().chain(by_mime).flatten() {
+            if tried.contains(&i) { continue; }
+            tried.push(i);
+            let ex = self.extractors[i].as_ref();
+            if let Some(x) = ex.extract(path, bytes) { return Some(stamped(x, ex)); }
+        }
+        self.text.extract(path, bytes).map(|x| stamped(x, &self.text))
+    }
+}
+
+fn stamped(x: Extracted, ex: &dyn Extractor) -> Extracted {
+    Extracted { extractor: ex.name().to_string(), extractor_version: ex.version(), ..x }
+}
+
+/// The MIME type of a file, from its leading bytes where they are telling and otherwise from
+/// its name. Only types an extractor is likely to be registered for are known.
+pub fn mime_type(path: &str, bytes: &[u8]) -> Option<&'static str> {
+    if is_pdf(bytes) { return Some("application/pdf"); }
+    if let Some(c) = Compression::detect(bytes) { return Some(c.mime_type()); }
+    if is_message(path) { return Some("message/rfc822"); }
+    let ext = Path::new(path).extension()?.to_string_lossy().to_ascii_lowercase();
+    let mime = match ext.as_str() {
+        "txt" | "text" | "log" => "text/plain",
+        "md" | "markdown" => "text/markdown",
+        "rst" => "text/x-rst",
+        "org" => "text/x-org",
+        "tex" => "application/x-tex",
+        "csv" => "text/csv",
+        "json" => "application/json",
+        "html" | "htm" => "text/html",
+        "xhtml" => "application/xhtml+xml",
+        "xml" => "application/xml",
+        "ipynb" => "application/x-ipynb+json",
+        "eml" => "message/rfc822",
+        "epub" => "application/epub+zip",
+        "rtf" => "application/rtf",
+        "doc" => "application/msword",
+        "xls" => "application/vnd.ms-excel",
+        "ppt" => "application/vnd.ms-powerpoint",
+        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "pptx" => "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        "odt" => "application/vnd.oasis.opendocument.text",
+        "ods" => "application/vnd.oasis.opendocument.spreadsheet",
+        "odp" => "application/vnd.oasis.opendocument.presentation",
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "tif" | "tiff" => "image/tiff",
+        "mp3" => "audio/mpeg",
+        "flac" => "audio/flac",
+        "ogg" | "oga" | "opus" => "audio/ogg",
+        "m4a" => "audio/mp4",
+        "mp4" | "m4v" => "video/mp4",
+        "mov" => "video/quicktime",
+        _ => return None,
+    };
+    Some(mime)
+}
+
+/// Extensions of source code, as opposed to prose or data.
+const CODE_EXTS: &[&str] = &[
+    "rs", "py", "pyi", "js", "jsx", "mjs", "cjs", "ts", "tsx", "go", "c", "h", "cc", "cpp", "cxx", "hh", "hpp", "hxx",
+    "m", "mm", "java", "kt", "kts", "scala", "cs", "fs", "rb", "php", "swift", "dart", "lua", "pl", "pm", "r", "jl",
+    "hs", "ml", "mli", "ex", "exs", "erl", "clj", "el", "lisp", "scm", "zig", "nim", "sh", "bash", "zsh", "fish", "ps1",
+    "sql", "vue", "svelte", "css", "scss", "less", "proto", "cmake", "gradle", "groovy", "tf",
+];
+
+/// The broad kind of a document, for filtering: `code`, `text`, `markup`, `document`, `notebook`,
+/// `mail`, `image`, `audio` or `video`, judged by its name and the extractor that read it.
+pub fn file_kind(path: &str, bytes: &[u8], extractor: &str) -> &'static str {
+    match extractor {
+        "office" | "pdf" | "epub" => return "document",
+        "notebook" => return "notebook",
+        "mail" => return "mail",
+        "markup" => return "markup",
+        _ => {}
+    }
+    let mime = mime_type(path, bytes).unwrap_or("");
+    for kind in ["image", "audio", "video"] {
+        if mime.starts_with(kind) { return kind; }
+    }
+    let ext = Path::new(path).extension().map(|e| e.to_string_lossy().to_ascii_lowercase()).unwrap_or_default();
+    match ext.as_str() {
+        e if CODE_EXTS.contains(&e) => "code",
+        "html" | "htm" | "xhtml" | "xml" => "markup",
+        _ if extractor == "text" => "text",
+        // an external command: whatever it read, it made a document of it
+        _ => "document",
+    }
+}
+
+/// Anything that decodes as text, after undoing gzip, zstd, xz or bzip2 compression.
+struct Text { budget: u64 }
+
+impl Extractor for Text {
+    fn name(&self) -> &str { "text" }
+
+    fn extract(&self, _path: &str, bytes: &[u8]) -> Option<Extracted> {
+        match decompress(bytes, self.budget) {
+            Some((c, expanded)) => decode_text(&expanded).map(|d| Extracted { compression: Some(c), ..d.into() }),
+            None => decode_text(bytes).map(Extracted::from),
+        }
+    }
+}
+
+struct Office { budget: u64 }
+
+impl Extractor for Office {
+    fn name(&self) -> &str { "office" }
+
+    fn extract(&self, path: &str, bytes: &[u8]) -> Option<Extracted> {
+        extract_office(bytes, office_kind(path)?, self.budget).ok()
+    }
+}
+
+struct Notebook { keep_outputs: bool }
+
+impl Extractor for Notebook {
+    fn name(&self) -> &str { "notebook" }
+
+    fn extract(&self, _path: &str, bytes: &[u8]) -> Option<Extracted> {
+        extract_notebook(bytes, self.keep_outputs).ok()
+    }
+}
+
+struct Mail;
+
+impl Extractor for Mail {
+    fn name(&self) -> &str { "mail" }
+
+    fn extract(&self, _path: &str, bytes: &[u8]) -> Option<Extracted> {
+        extract_message(bytes).ok()
+    }
+}
+
+struct Pdf;
+
+impl Extractor for Pdf {
+    fn name(&self) -> &str { "pdf" }
+
+    fn extract(&self, _path: &str, bytes: &[u8]) -> Option<Extracted> {
+        is_pdf(bytes).then(|| extract_pdf(bytes))
+    }
+}
+
+struct Markup;
+
+impl Extractor for Markup {
+    fn name(&self) -> &str { "markup" }
+
+    fn extract(&self, path: &str, bytes: &[u8]) -> Option<Extracted> {
+        let kind = markup_kind(path)?;
+        decode_text(bytes).map(|d| Extracted { encoding: d.encoding, ..extract_markup(&d.text, kind) })
+    }
+}
+
+struct Media;
+
+impl Extractor for Media {
+    fn name(&self) -> &str { "media" }
+
+    fn extract(&self, _path: &str, bytes: &[u8]) -> Option<Extracted> {
+        extract_media(bytes)
+    }
+}
+
+/// EPUB books, one document per chapter. Every chapter is read from the whole book, so it
+/// takes the book's bytes.
+struct Epub { budget: u64 }
+
+impl Container for Epub {
+    fn handles(&self, path: &str) -> bool { is_epub(path) }
+
+    fn for_each_document(&self, _path: &str, bytes: &[u8], _registry: &Registry, found: &mut dyn FnMut(Inner)) {
+        // a book that can't be unpacked is indexed by name
+        for (path, x) in epub_chapters(bytes, self.budget).unwrap_or_default() {
+            let text = Extracted { extractor: "epub".to_string(), extractor_version: env!("CARGO_PKG_VERSION").to_string(), ..x };
+            found(Inner { path, bytes, text: Some(text) });
+        }
+    }
+}
+
+/// Archives of some of the kinds zip, tar and mbox, nested ones included, within `limits`.
+struct Archive { kinds: &'static [ArchiveKind], limits: ArchiveLimits }
+
+impl Container for Archive {
+    fn handles(&self, path: &str) -> bool { archive_kind(path).is_some_and(|k| self.kinds.contains(&k)) }
+
+    fn for_each_document(&self, path: &str, bytes: &[u8], registry: &Registry, found: &mut dyn FnMut(Inner)) {
+        let Some(kind) = archive_kind(path).filter(|k| self.kinds.contains(k)) else { return };
+        for_each_member(bytes, kind, &self.limits, |m| {
+            let text = registry.extract(&format!("{}{}{}", path, ARCHIVE_SEP, m.path), &m.data);
+            found(Inner { path: m.path, bytes: &m.data, text });
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MBOX: &[u8] = b"From a@x Mon Jan 1 00:00:00 2024\nSubject: one\n\nfirst\n\nFrom b@x Tue Jan 2 00:00:00 2024\nSubject: two\n\nsecond\n";
+
+    fn documents(registry: &Registry, path: &str, bytes: &[u8]) -> Option<Vec<(String, String, String)>> {
+        let mut docs = Vec::new();
+        registry.container(path)?.for_each_document(path, bytes, registry, &mut |d| {
+            let x = d.text.unwrap_or_default();
+            docs.push((d.path, x.extractor, x.text.trim().to_string()));
+        });
+        Some(docs)
+    }
+
+    #[test]
+    fn mailboxes_are_split_with_archive_search_off() {
+        let off = Config { archives: ArchiveLimits { max_depth: 0, ..ArchiveLimits::default() }, ..Config::default() };
+        for cfg in [Config::default(), off] {
+            let registry = Registry::new(&cfg);
+            let docs = documents(&registry, "/m/inbox.mbox", MBOX).unwrap();
+            assert_eq!(docs.iter().map(|d| (d.0.as_str(), d.1.as_str())).collect::<Vec<_>>(), [("1.eml", "mail"), ("2.eml", "mail")]);
+            assert!(docs[1].2.contains("second"));
+            assert_eq!(registry.container("/m/release.zip").is_some(), cfg.archives.max_depth > 0);
+        }
+    }
+}
@@ -0,0 +1,351 @@
+use anyhow::{anyhow, Result};
+use chrono::{Datelike, Local, Months, NaiveDate, TimeZone};
+use std::ops::Bound;
+use tantivy::{Term, query::{BooleanQuery, ConstScoreQuery, Occur, Query, RangeQuery, RegexQuery, TermQuery}, schema::IndexRecordOption};
+
+use crate::roots::parse_size;
+use crate::search::{Config, Fields};
+
+/// Fields with filter syntax of their own; anything else is left to tantivy's `QueryParser`.
+pub const FILTER_FIELDS: &[&str] = &["ext", "size", "modified", "created", "path", "root", "kind"];
+/// Values of the `kind` field, and narrower kinds matched by the extractor that read the file.
+const KINDS: &[&str] = &["code", "text", "markup", "document", "notebook", "mail", "image", "audio", "video"];
+const EXTRACTOR_KINDS: &[&str] = &["pdf", "office"];
+
+/// A query with its filters taken out: the clauses they compiled to, and the free text that
+/// is left for the query parser.
+pub struct Filtered {
+    pub text: String,
+    pub clauses: Vec<(Occur, Box<dyn Query>)>,
+}
+
+/// Pull filters like `ext:rs`, `size:>1MB`, `modified:<7d`, `path:src/**`, `root:work` and
+/// `kind:pdf` out of `q`. Only whole words outside quotes and parentheses are filters; a
+/// leading `-` excludes what it matches. Tantivy ranges like `size:[1 TO 5]` are left alone.
+pub fn parse_filters(q: &str, fields: &Fields, cfg: &Config) -> Result<Filtered> {
+    let mut text = q.to_string();
+    let mut clauses = Vec::new();
+    for (start, end) in words(q) {
+        let word = &q[start..end];
+        let (negated, filter) = match word.strip_prefix('-') { Some(w) => (true, w), None => (false, word) };
+        let Some((name, value)) = filter.split_once(':') else { continue };
+        if !FILTER_FIELDS.contains(&name) || value.starts_with(['[', '{']) { continue; }
+        let value = value.trim_matches('"');
+        let query = compile(name, value, fields, cfg).map_err(|e| token_error(q, start, end, &e))?;
+        clauses.push((if negated { Occur::MustNot } else { Occur::Must }, Box::new(ConstScoreQuery::new(query, 0.0)) as Box<dyn Query>));
+        text.replace_range(start..end, &" ".repeat(end - start));
+    }
+    Ok(Filtered { text: text.trim().to_string(), clauses })
+}
+
+/// Byte ranges of the whitespace-separated words of `q` that are outside quotes and parentheses.
+fn words(q: &str) -> Vec<(usize, usize)> {
+    let (mut out, mut start, mut depth, mut quoted) = (Vec::new(), None, 0usize, false);
+    // a word that started inside parentheses is part of a group, not a top-level filter
+    let mut grouped = false;
+    for (i, c) in q.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '(' if !quoted => depth += 1,
+            ')' if !quoted => depth = depth.saturating_sub(1),
+            c if c.is_whitespace() && !quoted => {
+                if let Some(s) = start.take() { if !grouped { out.push((s, i)); } }
+                continue;
+            }
+            _ => {}
+        }
+        if start.is_none() {
+            start = Some(i);
+            grouped = depth > 0 || c == '(';
+        }
+    }
+    if let Some(s) = start { if !grouped && depth == 0 && !quoted { out.push((s, q.len())); } }
+    out
+}
+
+fn token_error(q: &str, start: usize, end: usize, msg: &str) -> anyhow::Error {
+    let pad = q[..start].chars().count();
+    let width = q[start..end].chars().count().max(1);
+    anyhow!("invalid filter `{}`: {}\n  {}\n  {}{}", &q[start..end], msg, q, " ".repeat(pad), "^".repeat(width))
+}
+
+fn compile(name: &str, value: &str, fields: &Fields, cfg: &Config) -> Result<Box<dyn Query>, String> {
+    if value.is_empty() { return Err(format!("`{}:` needs a value", name)); }
+    match name {
+        "ext" => Ok(any_of(value.split(',').filter(|e| !e.is_empty()).map(|e| term(fields.ext, &e.trim_start_matches('.').to_lowercase())).collect())),
+        "size" => {
+            let (lo, hi) = range(value, |v| parse_size(v).map(|n| (n, n + 1)).map_err(|e| e.to_string()))
+                .map_err(|e| format!("{}; sizes look like >1MB, <=500KB, 10KB..2MB or 0", e))?;
+            Ok(Box::new(RangeQuery::new_u64_bounds("size".to_string(), lo, hi)))
+        }
+        "modified" | "created" => {
+            let now = Local::now().timestamp();
+            let (lo, hi) = range(&flip_age(value), |v| time_span(v, now))
+                .map_err(|e| format!("{}; times look like <7d, >2h, 2024-07, >=2024-07-15 or 30d..7d", e))?;
+            Ok(Box::new(RangeQuery::new_i64_bounds(name.to_string(), lo, hi)))
+        }
+        "path" => {
+            let re = glob_regex(value);
+            RegexQuery::from_pattern(&re, fields.path).map(|q| Box::new(q) as Box<dyn Query>).map_err(|e| format!("bad glob: {}", e))
+        }
+        "root" => {
+            let ids: Vec<u64> = cfg.roots.iter().filter(|r| {
+                let p = r.path.to_string_lossy();
+                p == value || p.trim_end_matches('/').ends_with(&format!("/{}", value.trim_matches('/')))
+            }).map(|r| r.id()).collect();
+            if ids.is_empty() {
+                let names: Vec<String> = cfg.roots.iter().map(|r| r.path.file_name().map_or_else(|| r.path.display().to_string(), |n| n.to_string_lossy().to_string())).collect();
+                return Err(format!("no configured root is called {:?}; roots are {}", value, names.join(", ")));
+            }
+            Ok(any_of(ids.into_iter().map(|id| Box::new(TermQuery::new(Term::from_field_u64(fields.root, id), IndexRecordOption::Basic)) as Box<dyn Query>).collect()))
+        }
+        "kind" => {
+            let mut qs = Vec::new();
+            for k in value.split(',').filter(|k| !k.is_empty()) {
+                let k = k.to_lowercase();
+                if KINDS.contains(&k.as_str()) {
+                    qs.push(term(fields.kind, &k));
+                } else if EXTRACTOR_KINDS.contains(&k.as_str()) {
+                    qs.push(term(fields.extractor, &k));
+                } else {
+                    return Err(format!("unknown kind {:?}; kinds are {}, {}", k, KINDS.join(", "), EXTRACTOR_KINDS.join(", ")));
+                }
+            }
+            Ok(any_of(qs))
+        }
+        _ => Err(format!("`{}` is not a filter", name)),
+    }
+}
+
+fn term(field: tantivy::schema::Field, value: &str) -> Box<dyn Query> {
+    Box::new(TermQuery::new(Term::from_field_text(field, value), IndexRecordOption::Basic))
+}
+
+fn any_of(mut qs: Vec<Box<dyn Query>>) -> Box<dyn Query> {
+    if qs.len() == 1 { return qs.remove(0); }
+    Box::new(BooleanQuery::new(qs.into_iter().map(|q| (Occur::Should, q)).collect()))
+}
+
+/// Bounds for a comparison like `>X`, `<=X`, a range `A..B` or a bare `X`, where `point` turns
+/// `X` into the half-open span `[start, end)` it stands for.
+fn range<T: Copy + Ord>(value: &str, point: impl Fn(&str) -> Result<(T, T), String>) -> Result<(Bound<T>, Bound<T>), String> {
+    use Bound::*;
+    if let Some((a, b)) = value.split_once("..") {
+        let (a, b) = (point(a)?, point(b)?);
+        return Ok((Included(a.0.min(b.0)), Excluded(a.1.max(b.1))));
+    }
+    let v = value.trim_start_matches(['<', '>', '=']);
+    let (s, e) = point(v)?;
+    match &value[..value.len() - v.len()] {
+        "" => Ok((Included(s), Excluded(e))),
+        ">=" => Ok((Included(s), Unbounded)),
+        "<=" => Ok((Unbounded, Excluded(e))),
+        ">" => Ok((Included(e), Unbounded)),
+        "<" => Ok((Unbounded, Excluded(s))),
+        op => Err(format!("unknown comparison {:?}", op)),
+    }
+}
+
+/// Ages compare the other way round from the times they stand for: `<7d` is after 7 days
+/// ago, and a bare `7d` means within the last 7 days.
+fn flip_age(value: &str) -> String {
+    let v = value.trim_start_matches(['<', '>', '=']);
+    if value.contains("..") || age(v).is_none() { return value.to_string(); }
+    let op = match &value[..value.len() - v.len()] { "" | "<" => ">", "<=" => ">=", ">" => "<", ">=" => "<=", op => op };
+    format!("{}{}", op, v)
+}
+
+/// The span of seconds since the epoch that `v` covers: the second `v` ago for an age like
+/// `7d`, or a whole day, month or year in local time for a date.
+fn time_span(v: &str, now: i64) -> Result<(i64, i64), String> {
+    if let Some(secs) = age(v) { return Ok((now - secs, now - secs + 1)); }
+    let (start, next) = match v.len() {
+        4 => {
+            let d = NaiveDate::from_ymd_opt(v.parse().map_err(|_| format!("invalid year {:?}", v))?, 1, 1).ok_or("invalid year")?;
+            (d, d.with_year(d.year() + 1).ok_or("invalid year")?)
+        }
+        7 => {
+            let d = NaiveDate::parse_from_str(&format!("{}-01", v), "%Y-%m-%d").map_err(|_| format!("invalid month {:?}", v))?;
+            (d, d.checked_add_months(Months::new(1)).ok_or("invalid month")?)
+        }
+        _ => {
+            let d = match v {
+                "today" => Local::now().date_naive(),
+                "yesterday" => Local::now().date_naive().pred_opt().ok_or("invalid date")?,
+                _ => NaiveDate::parse_from_str(v, "%Y-%m-%d").map_err(|_| format!("invalid time {:?}", v))?,
+            };
+            (d, d.succ_opt().ok_or("invalid date")?)
+        }
+    };
+    let secs = |d: NaiveDate| d.and_hms_opt(0, 0, 0).and_then(|t| Local.from_local_datetime(&t).earliest()).map(|t| t.timestamp()).ok_or("invalid date");
+    Ok((secs(start)?, secs(next)?))
+}
+
+/// Seconds in an age like `90s`, `15min`, `2h`, `7d`, `2w`, `3mo` or `1y`.
+fn age(v: &str) -> Option<i64> {
+    let split = v.find(|c: char| !c.is_ascii_digit()).filter(|&i| i > 0)?;
+    let n: i64 = v[..split].parse().ok()?;
+    let unit = match &v[split..] {
+        "s" => 1,
+        "m" | "min" => 60,
+        "h" => 3600,
+        "d" => 86_400,
+        "w" => 7 * 86_400,
+        "mo" => 30 * 86_400,
+        "y" => 365 * 86_400,
+        _ => return None,
+    };
+    n.checked_mul(unit)
+}
+
+/// An anchored regex for the paths matching `glob`. `**` crosses directories, `*` and `?` don't.
+/// A glob that doesn't start with `/` may match from any directory down, and matching a
+/// directory matches everything below it.
+fn glob_regex(glob: &str) -> String {
+    let mut re = String::from(if glob.starts_with('/') { "" } else { "(.*/)?" });
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                re.push_str(".*");
+            }
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            c => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    re.push_str("(/.*)?");
+    re
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::roots::RootConfig;
+    use crate::search::{build_schema, with_tokenizers};
+    use tantivy::{collector::Count, doc, Index};
+
+    /// Paths of the documents in a small in-RAM index that match the filters in `q`.
+    fn matching(q: &str, cfg: &Config) -> Vec<String> {
+        let (schema, f) = build_schema();
+        let index = with_tokenizers(Index::create_in_ram(schema));
+        let mut writer = index.writer_with_num_threads(1, 15_000_000).unwrap();
+        let work = RootConfig::new("/home/me/work".into()).id();
+        let docs = [
+            ("/home/me/work/src/main.rs", "rs", "code", "", 1_200u64),
+            ("/home/me/work/docs/guide.pdf", "pdf", "document", "pdf", 3_000_000),
+            ("/home/me/notes/todo.md", "md", "markup", "", 0),
+        ];
+        for (path, ext, kind, extractor, size) in docs {
+            let root = if path.starts_with("/home/me/work") { work } else { 0 };
+            writer.add_document(doc!(f.path => path, f.ext => ext, f.kind => kind, f.extractor => extractor, f.size => size, f.root => root)).unwrap();
+        }
+        writer.commit().unwrap();
+        let searcher = index.reader().unwrap().searcher();
+        let filtered = parse_filters(q, &f, cfg).unwrap();
+        docs.iter().map(|d| d.0).filter(|path| {
+            let mut clauses = filtered.clauses.iter().map(|(o, q)| (*o, q.box_clone())).collect::<Vec<_>>();
+            clauses.push((Occur::Must, term(f.path, path)));
+            searcher.search(&BooleanQuery::new(clauses), &Count).unwrap() == 1
+        }).map(String::from).collect()
+    }
+
+    fn error(q: &str) -> String {
+        let (_, f) = build_schema();
+        parse_filters(q, &f, &Config::default()).err().expect("an invalid filter").to_string()
+    }
+
+    #[test]
+    fn filters_leave_the_free_text() {
+        let (_, f) = build_schema();
+        let cfg = Config::default();
+        let filtered = parse_filters("ext:rs  parser -kind:mail size:<1MB", &f, &cfg).unwrap();
+        assert_eq!(filtered.text, "parser");
+        assert_eq!(filtered.clauses.iter().map(|(o, _)| *o).collect::<Vec<_>>(), [Occur::Must, Occur::MustNot, Occur::Must]);
+        // quoted, grouped, tantivy ranges and other fields stay in the text
+        for q in ["\"ext:rs\"", "(ext:rs OR ext:go)", "size:[1 TO 5]", "title:ext", "http://example.com", ""] {
+            let filtered = parse_filters(q, &f, &cfg).unwrap();
+            assert_eq!((filtered.text.as_str(), filtered.clauses.len()), (q, 0), "{}", q);
+        }
+    }
+
+    #[test]
+    fn filters_match_documents() {
+        let cfg = Config { roots: vec![RootConfig::new("/home/me/work".into()), RootConfig::new("/home/me/notes".into())], ..Config::default() };
+        let all = ["/home/me/work/src/main.rs", "/home/me/work/docs/guide.pdf", "/home/me/notes/todo.md"];
+        assert_eq!(matching("", &cfg), all);
+        assert_eq!(matching("ext:rs", &cfg), [all[0]]);
+        assert_eq!(matching("ext:.RS,md", &cfg), [all[0], all[2]]);
+        assert_eq!(matching("-ext:rs", &cfg), [all[1], all[2]]);
+        assert_eq!(matching("kind:pdf", &cfg), [all[1]]);
+        assert_eq!(matching("kind:code,markup", &cfg), [all[0], all[2]]);
+        assert_eq!(matching("size:>1MB", &cfg), [all[1]]);
+        assert_eq!(matching("size:0", &cfg), [all[2]]);
+        assert_eq!(matching("size:1KB..2KB", &cfg), [all[0]]);
+        assert_eq!(matching("path:src", &cfg), [all[0]]);
+        assert_eq!(matching("path:*.md", &cfg), [all[2]]);
+        assert_eq!(matching("path:/home/**/*.pdf", &cfg), [all[1]]);
+        assert_eq!(matching("path:/src", &cfg), Vec::<String>::new());
+        assert_eq!(matching("root:work", &cfg), [all[0], all[1]]);
+        assert_eq!(matching("root:work ext:pdf", &cfg), [all[1]]);
+    }
+
+    #[test]
+    fn errors_point_at_the_filter() {
+        assert_eq!(error("todo ext: main"), "invalid filter `ext:`: `ext:` needs a value\n  todo ext: main\n       ^^^^");
+        let e = error("café kind:pdfx");
+        assert!(e.starts_with("invalid filter `kind:pdfx`: unknown kind \"pdfx\"; kinds are code,"), "{}", e);
+        assert!(e.ends_with("\n  café kind:pdfx\n       ^^^^^^^^^"), "{}", e);
+        let e = error("-size:>1XB x");
+        assert!(e.contains("sizes look like >1MB") && e.ends_with("\n  -size:>1XB x\n  ^^^^^^^^^^"), "{}", e);
+        assert!(error("modified:tomorrow").contains("invalid time \"tomorrow\"; times look like"));
+        assert!(error("modified:soon").contains("invalid year \"soon\""));
+        assert!(error("modified:2024-13").contains("invalid month \"2024-13\""));
+        assert!(error("created:=>7d").contains("unknown comparison \"=>\""));
+        assert!(error("root:nowhere").contains("no configured root is called \"nowhere\"; roots are "));
+    }
+
+    #[test]
+    fn comparisons_and_ages() {
+        use Bound::*;
+        let point = |v: &str| v.parse::<u64>().map(|n| (n, n + 1)).map_err(|e| e.to_string());
+        assert_eq!(range("5", point), Ok((Included(5), Excluded(6))));
+        assert_eq!(range(">5", point), Ok((Included(6), Unbounded)));
+        assert_eq!(range(">=5", point), Ok((Included(5), Unbounded)));
+        assert_eq!(range("<5", point), Ok((Unbounded, Excluded(5))));
+        assert_eq!(range("<=5", point), Ok((Unbounded, Excluded(6))));
+        assert_eq!(range("9..2", point), Ok((Included(2), Excluded(10))));
+        assert!(range("..2", point).is_err());
+        assert_eq!(flip_age("<7d"), ">7d");
+        assert_eq!(flip_age("7d"), ">7d");
+        assert_eq!(flip_age(">=2h"), "<=2h");
+        assert_eq!(flip_age("<2024-07"), "<2024-07");
+        assert_eq!(flip_age("30d..7d"), "30d..7d");
+        assert_eq!(age("90s"), Some(90));
+        assert_eq!(age("15min"), Some(900));
+        assert_eq!(age("2w"), Some(14 * 86_400));
+        assert_eq!(age("d"), None);
+        assert_eq!(age("7x"), None);
+        assert_eq!(age("99999999999999999y"), None);
+        assert_eq!(time_span("7d", 1_000_000), Ok((395_200, 395_201)));
+        let (start, end) = time_span("2024", 0).unwrap();
+        assert!((365 * 86_400..=367 * 86_400).contains(&(end - start)));
+        let (start, end) = time_span("2024-02", 0).unwrap();
+        assert!((28 * 86_400..=30 * 86_400).contains(&(end - start)));
+        assert!(time_span("2024-02-30", 0).is_err());
+    }
+
+    #[test]
+    fn globs() {
+        let matches = |glob: &str, path: &str| regex::Regex::new(&format!("^(?:{})$", glob_regex(glob))).unwrap().is_match(path);
+        assert!(matches("src", "/w/src/a/b.rs"));
+        assert!(matches("*.rs", "/w/src/b.rs"));
+        assert!(!matches("/*.rs", "/w/src/b.rs"));
+        assert!(matches("/w/**/b.rs", "/w/src/a/b.rs"));
+        assert!(!matches("/w/*/b.rs", "/w/src/a/b.rs"));
+        assert!(matches("b?.rs", "/w/b1.rs"));
+        assert!(!matches("b?.rs", "/w/b/.rs"));
+        assert!(!matches("a.rs", "/w/aXrs"));
+    }
+}
@@ -0,0 +1,300 @@
+use anyhow::Result;
+use rayon::prelude::*;
+use serde::{Serialize, Deserialize};
+use std::{collections::HashSet, path::Path};
+
+use crate::names::NameIndex;
+use crate::workspace::Workspace;
+
+// Scoring follows fzf's v2 algorithm: every matched char earns SCORE_MATCH plus a bonus
+// depending on where it sits, gaps between matched chars are penalized affinely.
+const SCORE_MATCH: i64 = 16;
+const GAP_START: i64 = -3;
+const GAP_EXTENSION: i64 = -1;
+const BONUS_START: i64 = 10;
+const BONUS_PATH_SEP: i64 = 9;
+const BONUS_BOUNDARY: i64 = 8;
+const BONUS_CAMEL: i64 = 7;
+const BONUS_CONSECUTIVE: i64 = 4;
+const FIRST_CHAR_MULTIPLIER: i64 = 2;
+const BONUS_EXACT_BASENAME: i64 = 64;
+const BONUS_EXACT_STEM: i64 = 32;
+
+/// A ranked fuzzy match. `positions` are char indices into `path` that matched the pattern.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FuzzyHit {
+    pub path: String,
+    pub score: i64,
+    pub positions: Vec<usize>,
+}
+
+fn bonus(prev: Option<char>, cur: char) -> i64 {
+    match prev {
+        None => BONUS_START,
+        Some('/') | Some('\\') => BONUS_PATH_SEP,
+        Some(p) if !p.is_alphanumeric() && cur.is_alphanumeric() => BONUS_BOUNDARY,
+        Some(p) if !p.is_alphanumeric() => 0,
+        Some(p) if p.is_lowercase() && cur.is_uppercase() => BONUS_CAMEL,
+        Some(p) if !p.is_numeric() && cur.is_numeric() => BONUS_CAMEL,
+        _ => 0,
+    }
+}
+
+/// Score one pattern token against `text` as an ordered subsequence.
+/// The token is matched case-insensitively unless it contains an uppercase char (smart case).
+/// Returns the best score and the matched char indices, or `None` if it does not match.
+pub fn fuzzy_score(token: &str, text: &str) -> Option<(i64, Vec<usize>)> {
+    let case_sensitive = token.chars().any(|c| c.is_uppercase());
+    let fold = |c: char| if case_sensitive { c } else { c.to_lowercase().next().unwrap_or(c) };
+    let pat: Vec<char> = token.chars().map(fold).collect();
+    let orig: Vec<char> = text.chars().collect();
+    let txt: Vec<char> = orig.iter().map(|&c| fold(c)).collect();
+    let (m, n) = (pat.len(), txt.len());
+    if m == 0 { return Some((0, Vec::new())); }
+
+    // quick reject, and narrow the window to [first, last] possible match positions
+    let mut it = 0;
+    let mut first = None;
+    for (j, &c) in txt.iter().enumerate() {
+        if it < m && c == pat[it] {
+            if it == 0 { first = Some(j); }
+            it += 1;
+        }
+    }
+    if it < m { return None; }
+    let first = first?;
+    let last = txt.iter().rposition(|&c| c == pat[m - 1])?;
+
+    let bonuses: Vec<i64> = (0..n).map(|j| bonus(if j == 0 { None } else { Some(orig[j - 1]) }, orig[j])).collect();
+    const NONE: i64 = i64::MIN;
+    // score[i][j]: best score with pat[i] matched at txt[j]; from[i][j]: where pat[i-1] matched
+    // run[i][j]: bonus carried along the consecutive run ending at (i, j)
+    let mut score = vec![vec![NONE; n]; m];
+    let mut from = vec![vec![usize::MAX; n]; m];
+    let mut run = vec![vec![0i64; n]; m];
+    for j in first..=last {
+        if txt[j] == pat[0] {
+            score[0][j] = SCORE_MATCH + bonuses[j] * FIRST_CHAR_MULTIPLIER;
+            run[0][j] = bonuses[j];
+        }
+    }
+    for i in 1..m {
+        // best (score, position) of pat[i-1] at least two chars back, gap penalty included
+        let mut gap: Option<(i64, usize)> = None;
+        for j in first..=last {
+            if txt[j] == pat[i] {
+                let b = bonuses[j];
+                let mut best: Option<(i64, usize, i64)> = gap.map(|(g, k)| (g + SCORE_MATCH + b, k, b));
+                if j > 0 && score[i - 1][j - 1] > NONE {
+                    let rb = run[i - 1][j - 1].max(b).max(BONUS_CONSECUTIVE);
+                    let consecutive = score[i - 1][j - 1] + SCORE_MATCH + rb;
+                    if best.is_none_or(|(s, _, _)| consecutive >= s) { best = Some((consecutive, j - 1, rb)); }
+                }
+                if let Some((s, k, r)) = best {
+                    score[i][j] = s;
+                    from[i][j] = k;
+                    run[i][j] = r;
+                }
+            }
+            // widen the gap by one char before moving on to j + 1
+            let extended = gap.map(|(g, k)| (g + GAP_EXTENSION, k));
+            let opened = (j > 0 && score[i - 1][j - 1] > NONE).then(|| (score[i - 1][j - 1] + GAP_START, j - 1));
+            gap = match (extended, opened) {
+                (Some(e), Some(o)) => Some(if o.0 >= e.0 { o } else { e }),
+                (e, o) => e.or(o),
+            };
+        }
+    }
+
+    let (best_j, best) = (first..=last).map(|j| (j, score[m - 1][j])).max_by_key(|&(j, s)| (s, std::cmp::Reverse(j)))?;
+    if best == NONE { return None; }
+    let mut positions = vec![0; m];
+    let mut j = best_j;
+    for i in (0..m).rev() {
+        positions[i] = j;
+        if i > 0 { j = from[i][j]; }
+    }
+    Some((best, positions))
+}
+
+/// Score a whitespace-separated pattern against `text`; every token has to match, in any order.
+pub fn fuzzy_match(pattern: &str, text: &str) -> Option<(i64, Vec<usize>)> {
+    let mut total = 0;
+    let mut positions = Vec::new();
+    for token in pattern.split_whitespace() {
+        let (s, p) = fuzzy_score(token, text)?;
+        total += s;
+        positions.extend(p);
+    }
+    positions.sort_unstable();
+    positions.dedup();
+    Some((total, positions))
+}
+
+fn basename_bonus(pattern: &str, path: &str) -> i64 {
+    let p = Path::new(path);
+    let name = p.file_name().map(|s| s.to_string_lossy().to_lowercase()).unwrap_or_default();
+    let stem = p.file_stem().map(|s| s.to_string_lossy().to_lowercase()).unwrap_or_default();
+    pattern.split_whitespace().map(|t| {
+        let t = t.to_lowercase();
+        if t == name { BONUS_EXACT_BASENAME } else if t == stem { BONUS_EXACT_STEM } else { 0 }
+    }).max().unwrap_or(0)
+}
+
+/// Most file names looked up in the filename index for one token.
+const MAX_NAMES: usize = 10_000;
+
+/// Edits a token may be away from the start of a file name: none for short tokens, which a
+/// typo would let match almost anything, more for longer ones.
+fn typos(token: &str) -> u32 {
+    match token.chars().count() {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }
+}
+
+/// Rank indexed files by fuzzy-matching `pattern` against their paths relative to their root.
+/// The filename index picks the candidates: files whose name starts with one of the tokens, or
+/// does within a few typos (see `typos`). Only when fewer than `top` of them match is every path
+/// scored. Files found by a typo alone come last.
+pub fn fuzzy_paths(ws: &Workspace, pattern: &str, top: usize) -> Result<Vec<FuzzyHit>> {
+    let names = NameIndex::open(&ws.index_dir)?;
+    let mut named = Vec::new();
+    // a token with a path separator says something about directories, not the name
+    for token in pattern.split_whitespace().filter(|t| !t.contains(['/', '\\'])) {
+        let found = match typos(token) {
+            0 => names.prefix(token, MAX_NAMES),
+            // a long token can need more states than the automaton allows; then it has to be exact
+            n => names.fuzzy_prefix(token, n, MAX_NAMES).unwrap_or_else(|_| names.prefix(token, MAX_NAMES)),
+        };
+        named.extend(found.into_iter().flat_map(|(_, postings)| postings).map(|p| p.path));
+    }
+    named.sort_unstable();
+    named.dedup();
+
+    let roots: Vec<String> = ws.config.roots.iter().map(|r| r.path.to_string_lossy().to_string()).collect();
+    let mut hits = score_paths(&roots, pattern, named.clone());
+    if hits.len() < top {
+        let mut paths = Vec::new();
+        names.for_each(|_, postings| paths.extend(postings.into_iter().map(|p| p.path)));
+        hits = score_paths(&roots, pattern, paths);
+    }
+    hits.sort_by(|a, b| b.score.cmp(&a.score).then(a.path.len().cmp(&b.path.len())).then_with(|| a.path.cmp(&b.path)));
+    hits.truncate(top);
+    if hits.len() < top {
+        let matched: HashSet<String> = hits.iter().map(|h| h.path.clone()).collect();
+        let typo = named.into_iter().filter(|p| !matched.contains(p)).map(|path| FuzzyHit { path, score: 0, positions: Vec::new() });
+        hits.extend(typo.take(top - matched.len()));
+    }
+    Ok(hits)
+}
+
+/// The `paths` that `pattern` matches, scored against the part below their root.
+fn score_paths(roots: &[String], pattern: &str, paths: Vec<String>) -> Vec<FuzzyHit> {
+    paths.into_par_iter().filter_map(|path| {
+        // match against the root-relative part, but report positions into the full path
+        let skip = roots.iter()
+            .filter(|r| Path::new(&path).starts_with(r))
+            .map(|r| r.len())
+            .max()
+            .map(|len| len + path[len..].chars().take_while(|c| *c == '/' || *c == '\\').count())
+            .unwrap_or(0);
+        let rel = &path[skip..];
+        let (score, positions) = fuzzy_match(pattern, rel)?;
+        let offset = path[..skip].chars().count();
+        Some(FuzzyHit {
+            score: score + basename_bonus(pattern, rel),
+            positions: positions.into_iter().map(|p| p + offset).collect(),
+            path,
+        })
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::roots::RootConfig;
+    use crate::search::index_all;
+    use std::fs;
+
+    #[test]
+    fn exact_match_earns_start_bonus_and_consecutive_run() {
+        // 16 + 2 * 10 for the first char, then 16 + 10 carried along the run for each next one
+        assert_eq!(fuzzy_score("abc", "abc"), Some((88, vec![0, 1, 2])));
+    }
+
+    #[test]
+    fn empty_and_missing_patterns() {
+        assert_eq!(fuzzy_score("", "anything"), Some((0, Vec::new())));
+        assert_eq!(fuzzy_score("a", ""), None);
+        assert_eq!(fuzzy_score("abd", "abc"), None);
+        assert_eq!(fuzzy_score("abcd", "abc"), None);
+        // order matters within a token
+        assert_eq!(fuzzy_score("ba", "ab"), None);
+    }
+
+    #[test]
+    fn smart_case() {
+        assert!(fuzzy_score("readme", "README.md").is_some());
+        assert!(fuzzy_score("README", "README.md").is_some());
+        assert_eq!(fuzzy_score("Readme", "readme.md"), None);
+    }
+
+    #[test]
+    fn prefers_word_boundaries() {
+        // `m` after the slash and `r` after the dot beat the earlier `r` in `src`
+        let (_, positions) = fuzzy_score("mr", "src/main.rs").unwrap();
+        assert_eq!(positions, [4, 9]);
+        let (sep, _) = fuzzy_score("m", "src/main.rs").unwrap();
+        let (inner, _) = fuzzy_score("a", "src/main.rs").unwrap();
+        assert!(sep > inner);
+    }
+
+    #[test]
+    fn longer_gaps_score_lower() {
+        let score = |text| fuzzy_score("ab", text).unwrap().0;
+        assert!(score("ab") > score("axb"));
+        assert!(score("axb") > score("axxxb"));
+    }
+
+    #[test]
+    fn every_token_has_to_match_in_any_order() {
+        let (_, positions) = fuzzy_match("rs main", "src/main.rs").unwrap();
+        assert_eq!(positions, [4, 5, 6, 7, 9, 10]);
+        assert_eq!(fuzzy_match("main py", "src/main.rs"), None);
+        assert_eq!(fuzzy_match("  ", "src/main.rs"), Some((0, Vec::new())));
+    }
+
+    #[test]
+    fn exact_names_and_stems_get_a_bonus() {
+        assert_eq!(basename_bonus("main.rs", "src/main.rs"), BONUS_EXACT_BASENAME);
+        assert_eq!(basename_bonus("src MAIN", "src/main.rs"), BONUS_EXACT_STEM);
+        assert_eq!(basename_bonus("mai", "src/main.rs"), 0);
+        assert_eq!(basename_bonus("x", ""), 0);
+    }
+
+    #[test]
+    fn names_pick_the_candidates_and_typos_come_last() {
+        let (home, root) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        for p in ["src/main.rs", "src/domain/lib.rs", "docs/maintenance.md"] {
+            let path = root.path().join(p);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "x").unwrap();
+        }
+        let mut ws = Workspace::at(home.path()).unwrap();
+        ws.config.roots.push(RootConfig::new(root.path().to_path_buf()));
+        index_all(&mut ws).unwrap();
+        let found = |pattern: &str, top: usize| -> Vec<(String, i64)> {
+            let base = root.path().to_string_lossy().len() + 1;
+            fuzzy_paths(&ws, pattern, top).unwrap().into_iter().map(|h| (h.path[base..].to_string(), h.score)).collect()
+        };
+        assert_eq!(found("main", 1)[0].0, "src/main.rs");
+        // `main.rs` is a typo away from `mainn`, which only `maintenance.md` really contains
+        let typo = found("mainn", 10);
+        assert_eq!(typo.iter().map(|(p, _)| p.as_str()).collect::<Vec<_>>(), ["docs/maintenance.md", "src/main.rs"]);
+        assert!(typo[0].1 > 0 && typo[1].1 == 0);
+        // no name starts with a path, so every one is scored
+        assert_eq!(found("src/dom", 10).iter().map(|(p, _)| p.as_str()).collect::<Vec<_>>(), ["src/domain/lib.rs"]);
+    }
+}
@@ -1,6 +1,14 @@
 use anyhow::Result;
+use chrono::{Local, TimeZone};
 use serde::{Serialize, Deserialize};
+use std::path::Path;
 use std::sync::{Arc, Mutex};
+use warpseek::query::{query_index, regex_index, Fragment, Hit, LineMatch, QueryOptions};
+use warpseek::search::upgrade_index;
+use warpseek::workspace::Workspace;
+
+/// Highlighted lines attached to each result.
+const FRAGMENTS: usize = 3;
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct SearchResult {
@@ -8,6 +16,8 @@ pub struct SearchResult {
     pub path: String,
     pub name: String,
     pub content_preview: String,
+    /// matching lines with highlight byte ranges, for rendering `content_preview` with marks
+    pub fragments: Vec<Fragment>,
     pub modified: i64,
     pub score: f32,
     pub file_type: String,
@@ -60,6 +70,7 @@ pub struct SearchFilters {
 }
 
 pub struct SearchEngine {
+    workspace: Workspace,
     search_history: Arc<Mutex<SearchHistory>>,
     bookmarks: Arc<Mutex<Vec<Bookmark>>>,
     filters: Arc<Mutex<SearchFilters>>,
@@ -67,7 +78,10 @@ pub struct SearchEngine {
 
 impl SearchEngine {
     pub fn new() -> Result<Self> {
+        let mut workspace = Workspace::resolve(None, None)?;
+        upgrade_index(&mut workspace)?;
         Ok(Self {
+            workspace,
             search_history: Arc::new(Mutex::new(SearchHistory {
                 queries: Vec::new(),
                 max_entries: 100,
@@ -84,29 +98,17 @@ impl SearchEngine {
     }
 
     pub fn search(&self, query: SearchQuery) -> Result<Vec<SearchResult>> {
-        // Mock search results for now
-        let results = vec![
-            SearchResult {
-                id: "1".to_string(),
-                path: "/Users/test/example.txt".to_string(),
-                name: "example.txt".to_string(),
-                content_preview: "This is a sample file content...".to_string(),
-                modified: 1698000000,
-                score: 0.95,
-                file_type: "txt".to_string(),
-                size: 1024,
-            },
-            SearchResult {
-                id: "2".to_string(),
-                path: "/Users/test/README.md".to_string(),
-                name: "README.md".to_string(),
-                content_preview: "Project documentation...".to_string(),
-                modified: 1698000001,
-                score: 0.87,
-                file_type: "md".to_string(),
-                size: 2048,
-            },
-        ];
+        let opts = QueryOptions {
+            top: query.max_results,
+            names_only: matches!(query.search_type, SearchType::Filename),
+            fragments: FRAGMENTS,
+            case_sensitive: Some(query.case_sensitive),
+        };
+        let results = if query.use_regex || matches!(query.search_type, SearchType::Regex) {
+            regex_results(regex_index(&self.workspace, &query.query, &opts)?)
+        } else {
+            query_index(&self.workspace, &filtered_query(&query), &opts)?.into_iter().map(SearchResult::from).collect()
+        };
 
         // Add to search history
         {
@@ -149,6 +151,59 @@ impl SearchEngine {
     }
 }
 
+impl From<Hit> for SearchResult {
+    fn from(h: Hit) -> Self {
+        SearchResult {
+            id: h.path.clone(),
+            content_preview: h.fragments.first().map(|f| f.text.clone()).unwrap_or_default(),
+            path: h.path,
+            name: h.name,
+            fragments: h.fragments,
+            modified: h.modified,
+            score: h.score,
+            file_type: h.ext,
+            size: h.size,
+        }
+    }
+}
+
+/// The query with the form's file types, dates and sizes added as filters (see
+/// `warpseek::filters`); dates count to the day.
+fn filtered_query(q: &SearchQuery) -> String {
+    let mut s = q.query.clone();
+    if !q.file_types.is_empty() { s.push_str(&format!(" ext:{}", q.file_types.join(","))); }
+    let day = |t: i64| Local.timestamp_opt(t, 0).single().map(|d| d.format("%Y-%m-%d").to_string());
+    if let Some(d) = q.modified_after.and_then(day) { s.push_str(&format!(" modified:>={}", d)); }
+    if let Some(d) = q.modified_before.and_then(day) { s.push_str(&format!(" modified:<{}", d)); }
+    if let Some(n) = q.size_min { s.push_str(&format!(" size:>={}", n)); }
+    if let Some(n) = q.size_max { s.push_str(&format!(" size:<={}", n)); }
+    s
+}
+
+/// Regex matches grouped into one result per file, in the order the files came. The index
+/// isn't asked for their metadata, so dates, sizes and scores are left at 0.
+fn regex_results(matches: Vec<LineMatch>) -> Vec<SearchResult> {
+    let mut results: Vec<SearchResult> = Vec::new();
+    for m in matches {
+        let fragment = Fragment { line: m.line, page: m.page, cell: m.cell, cell_type: None, highlights: vec![(m.column - 1, m.end - 1)], text: m.text };
+        match results.last_mut().filter(|r| r.path == m.path) {
+            Some(r) => { if r.fragments.len() < FRAGMENTS { r.fragments.push(fragment); } }
+            None => results.push(SearchResult {
+                id: m.path.clone(),
+                name: Path::new(&m.path).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default(),
+                file_type: Path::new(&m.path).extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default(),
+                content_preview: fragment.text.clone(),
+                fragments: vec![fragment],
+                path: m.path,
+                modified: 0,
+                score: 0.0,
+                size: 0,
+            }),
+        }
+    }
+    results
+}
+
 // Tauri commands
 #[tauri::command]
 fn search_files(
@@ -1,4 +1,27 @@
+pub mod archive;
+pub mod compress;
+pub mod encoding;
+pub mod epub;
+pub mod external;
+pub mod extract;
+pub mod filters;
+pub mod fuzzy;
+pub mod literals;
+pub mod mail;
+pub mod manifest;
+pub mod markup;
+pub mod media;
+pub mod names;
+pub mod notebook;
+pub mod office;
+pub mod pdf;
+pub mod query;
+pub mod roots;
 pub mod search;
+pub mod symbols;
+pub mod watch;
+pub mod workspace;
+pub mod xml;
 
 // Re-export the main functionality
-pub use search::*;
\ No newline at end of file
+pub use search::*;
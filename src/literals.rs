@@ -0,0 +1,297 @@
+use regex_syntax::ast::{Ast, ClassSet, ClassSetItem};
+use regex_syntax::hir::{Class, Hir, HirKind};
+use tantivy::{Term, query::{BooleanQuery, Occur, Query, RegexQuery, TermQuery}, schema::{Field, IndexRecordOption}};
+
+/// Largest set of alternative strings tracked for one part of a pattern.
+const MAX_SET: usize = 32;
+/// Words shorter than this that may be cut off by the pattern are too common to look up.
+const MIN_PARTIAL: usize = 3;
+/// The default tokenizer drops words this long or longer, so they can't be looked up either.
+const MAX_WORD: usize = 40;
+/// Longer words that may be cut off are likely part of a word too long to be indexed.
+const MAX_PARTIAL: usize = MAX_WORD / 2;
+
+/// What any match of a part of a pattern must contain.
+#[derive(Default)]
+struct Info {
+    /// every string the part can match, when there are few enough to list
+    exact: Option<Vec<String>>,
+    /// sets of strings of which at least one occurs in every match, all of them required
+    must: Vec<Vec<String>>,
+}
+
+impl Info {
+    fn exact(set: Vec<String>) -> Info {
+        Info { exact: Some(set), must: Vec::new() }
+    }
+
+    /// Forget the exact strings, keeping them as a requirement.
+    fn into_must(mut self) -> Vec<Vec<String>> {
+        if let Some(set) = self.exact.take() { self.must.push(set); }
+        self.must
+    }
+}
+
+/// Literals that every match of `hir` contains, as sets of alternatives that are all required:
+/// `foo(bar|baz)` gives `[["foobar", "foobaz"]]`. Letters are lowercased, since they are only
+/// used to look up lowercased index terms. Empty when nothing can be said.
+pub fn required_literals(hir: &Hir) -> Vec<Vec<String>> {
+    let mut sets = info(hir).into_must();
+    sets.retain(|s| !s.is_empty() && s.iter().all(|l| !l.is_empty()));
+    sets
+}
+
+/// Whether the pattern spells out an uppercase letter, in a literal or a class, which for
+/// smart case makes it case-sensitive. Escapes like `\W` or `\S` don't count.
+pub fn mentions_uppercase(ast: &Ast) -> bool {
+    match ast {
+        Ast::Literal(l) => l.c.is_uppercase(),
+        Ast::ClassBracketed(c) => set_mentions_uppercase(&c.kind),
+        Ast::Repetition(r) => mentions_uppercase(&r.ast),
+        Ast::Group(g) => mentions_uppercase(&g.ast),
+        Ast::Alternation(a) => a.asts.iter().any(mentions_uppercase),
+        Ast::Concat(c) => c.asts.iter().any(mentions_uppercase),
+        _ => false,
+    }
+}
+
+fn set_mentions_uppercase(set: &ClassSet) -> bool {
+    match set {
+        ClassSet::Item(i) => item_mentions_uppercase(i),
+        ClassSet::BinaryOp(op) => set_mentions_uppercase(&op.lhs) || set_mentions_uppercase(&op.rhs),
+    }
+}
+
+fn item_mentions_uppercase(item: &ClassSetItem) -> bool {
+    match item {
+        ClassSetItem::Literal(l) => l.c.is_uppercase(),
+        ClassSetItem::Range(r) => r.start.c.is_uppercase() || r.end.c.is_uppercase(),
+        ClassSetItem::Bracketed(c) => set_mentions_uppercase(&c.kind),
+        ClassSetItem::Union(u) => u.items.iter().any(item_mentions_uppercase),
+        _ => false,
+    }
+}
+
+fn info(hir: &Hir) -> Info {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Look(_) => Info::exact(vec![String::new()]),
+        HirKind::Literal(l) => Info::exact(vec![String::from_utf8_lossy(&l.0).to_lowercase()]),
+        HirKind::Class(c) => match class_chars(c) {
+            Some(chars) => Info::exact(chars.into_iter().map(String::from).collect()),
+            None => Info::default(),
+        },
+        HirKind::Capture(c) => info(&c.sub),
+        HirKind::Repetition(r) if r.min == 0 => Info::default(),
+        HirKind::Repetition(r) => Info { exact: None, must: info(&r.sub).into_must() },
+        HirKind::Concat(subs) => {
+            let (mut must, mut cur, mut complete) = (Vec::new(), vec![String::new()], true);
+            for sub in subs {
+                let i = info(sub);
+                must.extend(i.must);
+                match i.exact {
+                    Some(b) if cur.len() * b.len() <= MAX_SET => cur = cross(&cur, &b),
+                    Some(b) => { must.push(std::mem::replace(&mut cur, b)); complete = false; }
+                    None => { must.push(std::mem::replace(&mut cur, vec![String::new()])); complete = false; }
+                }
+            }
+            if complete { return Info { exact: Some(cur), must }; }
+            must.push(cur);
+            Info { exact: None, must }
+        }
+        HirKind::Alternation(subs) => {
+            let infos: Vec<Info> = subs.iter().map(info).collect();
+            if infos.iter().all(|i| i.exact.is_some()) {
+                let mut set: Vec<String> = infos.into_iter().flat_map(|i| i.exact.unwrap_or_default()).collect();
+                set.sort();
+                set.dedup();
+                if set.len() <= MAX_SET { return Info::exact(set); }
+                return Info { exact: None, must: vec![set] };
+            }
+            // each branch contributes its most selective requirement; a branch without one
+            // means anything can match
+            let mut set = Vec::new();
+            for i in infos {
+                let Some(best) = i.into_must().into_iter().filter(|s| s.iter().all(|l| !l.is_empty())).max_by_key(|s| s.iter().map(|l| l.len()).min()) else {
+                    return Info::default();
+                };
+                set.extend(best);
+            }
+            Info { exact: None, must: vec![set] }
+        }
+    }
+}
+
+fn cross(a: &[String], b: &[String]) -> Vec<String> {
+    let mut out: Vec<String> = a.iter().flat_map(|x| b.iter().map(move |y| format!("{}{}", x, y))).collect();
+    out.sort();
+    out.dedup();
+    out
+}
+
+/// The chars of a small class, with case variants of one letter folded together, as a
+/// case-insensitive pattern produces for every letter.
+fn class_chars(c: &Class) -> Option<Vec<char>> {
+    let mut chars = Vec::new();
+    match c {
+        Class::Unicode(u) => for r in u.iter() {
+            if (r.end() as u32 - r.start() as u32) as usize >= MAX_SET { return None; }
+            chars.extend(r.start()..=r.end());
+        },
+        Class::Bytes(b) => for r in b.iter() {
+            if (r.end() - r.start()) as usize >= MAX_SET { return None; }
+            chars.extend((r.start()..=r.end()).map(char::from));
+        },
+    }
+    let mut lower: Vec<char> = chars.iter().flat_map(|c| c.to_lowercase()).collect();
+    lower.sort_unstable();
+    lower.dedup();
+    (lower.len() <= MAX_SET / 4).then_some(lower)
+}
+
+/// A query for the documents of `field` (tokenized into lowercased alphanumeric words) that can
+/// contain every set of literals, or `None` if the literals don't narrow anything down.
+/// A literal's first and last words may be cut off by the pattern, so they only have to be a
+/// suffix or prefix of a word in the document.
+pub fn literal_query(field: Field, sets: &[Vec<String>]) -> Option<Box<dyn Query>> {
+    let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+    for set in sets {
+        let Some(alternatives) = set.iter().map(|l| words_query(field, l)).collect::<Option<Vec<_>>>() else { continue };
+        let q: Box<dyn Query> = if alternatives.len() == 1 {
+            alternatives.into_iter().next()?
+        } else {
+            Box::new(BooleanQuery::new(alternatives.into_iter().map(|q| (Occur::Should, q)).collect()))
+        };
+        clauses.push((Occur::Must, q));
+    }
+    (!clauses.is_empty()).then(|| Box::new(BooleanQuery::new(clauses)) as Box<dyn Query>)
+}
+
+fn words_query(field: Field, literal: &str) -> Option<Box<dyn Query>> {
+    let mut words: Vec<(usize, &str)> = Vec::new();
+    let mut start = None;
+    for (i, c) in literal.char_indices() {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => { words.push((s, &literal[s..i])); start = None; }
+            _ => {}
+        }
+    }
+    if let Some(s) = start { words.push((s, &literal[s..])); }
+    let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+    for (at, word) in words {
+        if word.len() >= MAX_WORD { continue; }
+        let (cut_start, cut_end) = (at == 0, at + word.len() == literal.len());
+        let q: Box<dyn Query> = if !cut_start && !cut_end {
+            Box::new(TermQuery::new(Term::from_field_text(field, word), IndexRecordOption::Basic))
+        } else {
+            if word.chars().count() < MIN_PARTIAL || word.len() > MAX_PARTIAL { continue; }
+            let w = regex_syntax::escape(word);
+            let re = match (cut_start, cut_end) { (true, true) => format!(".*{}.*", w), (true, false) => format!(".*{}", w), _ => format!("{}.*", w) };
+            Box::new(RegexQuery::from_pattern(&re, field).ok()?)
+        };
+        clauses.push((Occur::Must, q));
+    }
+    match clauses.len() {
+        0 => None,
+        1 => clauses.pop().map(|(_, q)| q),
+        _ => Some(Box::new(BooleanQuery::new(clauses))),
+    }
+}
+
+/// Like `literal_query`, for a field of lowercased trigrams: a literal becomes all of its
+/// trigrams. Literals shorter than three chars can't be looked up, and make their set useless.
+pub fn gram_query(field: Field, sets: &[Vec<String>]) -> Option<Box<dyn Query>> {
+    let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+    for set in sets {
+        let Some(alternatives) = set.iter().map(|l| grams_query(field, l)).collect::<Option<Vec<_>>>() else { continue };
+        clauses.push((Occur::Must, Box::new(BooleanQuery::new(alternatives.into_iter().map(|q| (Occur::Should, q)).collect()))));
+    }
+    (!clauses.is_empty()).then(|| Box::new(BooleanQuery::new(clauses)) as Box<dyn Query>)
+}
+
+fn grams_query(field: Field, literal: &str) -> Option<Box<dyn Query>> {
+    let chars: Vec<char> = literal.chars().collect();
+    let mut grams: Vec<String> = chars.windows(3).map(|w| w.iter().collect()).collect();
+    grams.sort();
+    grams.dedup();
+    if grams.is_empty() { return None; }
+    let terms = grams.iter().map(|g| (Occur::Must, Box::new(TermQuery::new(Term::from_field_text(field, g), IndexRecordOption::Basic)) as Box<dyn Query>));
+    Some(Box::new(BooleanQuery::new(terms.collect())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::{build_schema, with_tokenizers};
+    use tantivy::{collector::Count, doc, Index};
+
+    fn literals(pattern: &str) -> Vec<Vec<String>> {
+        let hir = regex_syntax::Parser::new().parse(pattern).unwrap();
+        required_literals(&hir)
+    }
+
+    fn uppercase(pattern: &str) -> bool {
+        mentions_uppercase(&regex_syntax::ast::parse::Parser::new().parse(pattern).unwrap())
+    }
+
+    #[test]
+    fn literals_every_match_contains() {
+        assert_eq!(literals("foo(bar|baz)"), [["foobar", "foobaz"]]);
+        assert_eq!(literals("Hello"), [["hello"]]);
+        assert_eq!(literals("(?i)hello"), [["hello"]]);
+        assert_eq!(literals(r"^\bfoo\b$"), [["foo"]]);
+        assert_eq!(literals("[ab]c"), [["ac", "bc"]]);
+        assert_eq!(literals("x?foo"), [["foo"]]);
+        assert_eq!(literals("a+b"), [["a"], ["b"]]);
+        assert_eq!(literals(r"foo\d+bar"), [["foo"], ["bar"]]);
+        assert_eq!(literals(r"(foo|bar\d)baz"), [vec!["foo", "bar"], vec!["baz"]]);
+        let sizes: Vec<usize> = literals("[a-c][a-c][a-c][a-c]").iter().map(Vec::len).collect();
+        assert_eq!(sizes, [27, 3]);
+    }
+
+    #[test]
+    fn no_literals_when_anything_can_match() {
+        for pattern in ["", ".*", "^$", r"\w+", "foo|.*", "(foo)?", "[a-z]{3}"] {
+            assert!(literals(pattern).is_empty(), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn smart_case() {
+        assert!(uppercase("Foo"));
+        assert!(uppercase("[A-Z]x"));
+        assert!(uppercase("(a|B)+"));
+        assert!(uppercase("[[:alpha:]&&[^Q]]"));
+        assert!(!uppercase("foo"));
+        assert!(!uppercase(r"\W\S\D"));
+        assert!(!uppercase("[a-z]{2,}"));
+    }
+
+    #[test]
+    fn queries_find_the_documents_with_the_literals() {
+        let (schema, f) = build_schema();
+        let index = with_tokenizers(Index::create_in_ram(schema));
+        let mut writer = index.writer_with_num_threads(1, 15_000_000).unwrap();
+        let long = format!("call {0} now, start({0}0000)", "a".repeat(MAX_WORD));
+        for text in ["call parse_config(path) here", "the parser config", "nothing relevant", &long] {
+            writer.add_document(doc!(f.content => text, f.grams => text)).unwrap();
+        }
+        writer.commit().unwrap();
+        let searcher = index.reader().unwrap().searcher();
+        let count = |q: Option<Box<dyn Query>>| q.map(|q| searcher.search(&q, &Count).unwrap());
+        let sets = literals(r"rse_config\(pa");
+        // `rse` and `pa` may be cut off, `config` is a whole word
+        assert_eq!(count(literal_query(f.content, &sets)), Some(1));
+        assert_eq!(count(gram_query(f.grams, &sets)), Some(1));
+        assert_eq!(count(literal_query(f.content, &literals("PARSER|nothing"))), Some(2));
+        assert_eq!(count(gram_query(f.grams, &literals("er con"))), Some(1));
+        // words the tokenizer dropped for being too long are not required, whole or cut off
+        let long = "a".repeat(MAX_WORD);
+        assert_eq!(count(literal_query(f.content, &literals(&format!("call {} now", long)))), Some(1));
+        assert_eq!(count(literal_query(f.content, &literals(&format!("start\\({}", &long[..MAX_PARTIAL + 5])))), Some(1));
+        // too short to narrow anything down
+        assert_eq!(count(literal_query(f.content, &literals("ab"))), None);
+        assert_eq!(count(gram_query(f.grams, &literals("ab"))), None);
+        assert_eq!(count(literal_query(f.content, &[])), None);
+    }
+}
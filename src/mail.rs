@@ -0,0 +1,366 @@
+use anyhow::{anyhow, Result};
+use encoding_rs::Encoding;
+use std::path::Path;
+
+use crate::encoding::decode_text;
+use crate::extract::Extracted;
+use crate::markup::{extract_markup, MarkupKind};
+
+/// Mail files; registered as the `mail` file type. Maildir messages have no extension, but
+/// their names start with a ten digit delivery time.
+pub const MAIL_GLOBS: &[&str] = &["*.eml", "*.mbox", "*.mbx", "[0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9].*"];
+
+/// Nested multiparts deeper than this are ignored.
+const MAX_MIME_DEPTH: usize = 16;
+
+/// The headers a message is searchable by.
+#[derive(Debug, Clone, Default)]
+pub struct MailHeaders {
+    pub from: String,
+    /// `To` and `Cc` recipients
+    pub to: String,
+    pub subject: String,
+    /// seconds since the epoch
+    pub date: Option<i64>,
+}
+
+/// A standalone message: an `.eml` file, or a file in a Maildir's `cur` or `new` directory.
+pub fn is_message(path: &str) -> bool {
+    if path.to_ascii_lowercase().ends_with(".eml") { return true; }
+    let p = Path::new(path);
+    let dir = p.parent().and_then(|d| d.file_name()).and_then(|d| d.to_str());
+    matches!(dir, Some("cur") | Some("new")) && p.parent().and_then(Path::parent).is_some_and(|m| m.join("tmp").is_dir())
+}
+
+/// Split an mbox into its messages, without the `From ` separator lines and with `>From `
+/// escapes undone. Any text before the first separator is dropped.
+pub fn mbox_messages(bytes: &[u8]) -> Vec<Vec<u8>> {
+    let mut messages = Vec::new();
+    let mut cur: Option<Vec<u8>> = None;
+    let mut blank = true;
+    for line in bytes.split_inclusive(|&b| b == b'\n') {
+        if blank && line.starts_with(b"From ") {
+            messages.extend(cur.take());
+            cur = Some(Vec::new());
+            blank = false;
+            continue;
+        }
+        blank = line.trim_ascii().is_empty();
+        let Some(msg) = &mut cur else { continue };
+        let quoted = line.iter().position(|&b| b != b'>').filter(|&n| n > 0 && line[n..].starts_with(b"From "));
+        msg.extend_from_slice(if quoted.is_some() { &line[1..] } else { line });
+    }
+    messages.extend(cur);
+    // the blank line before each separator belongs to the mbox, not the message
+    for m in &mut messages {
+        if m.ends_with(b"\r\n") { m.truncate(m.len() - 2) } else if m.ends_with(b"\n") { m.pop(); }
+    }
+    messages
+}
+
+/// The readable text of a message: its main headers, then the text of every text part, with
+/// quoted-printable and base64 undone and HTML-only parts stripped to text. Attachments are left out.
+pub fn extract_message(bytes: &[u8]) -> Result<Extracted> {
+    let (headers, body) = split_message(bytes);
+    if !headers.iter().any(|(n, _)| matches!(n.as_str(), "from" | "subject" | "date" | "message-id")) {
+        return Err(anyhow!("no mail headers"));
+    }
+    let get = |name: &str| headers.iter().filter(|(n, _)| n == name).map(|(_, v)| decode_words(v)).collect::<Vec<_>>().join(", ");
+    let h = MailHeaders {
+        from: get("from"),
+        to: [get("to"), get("cc")].into_iter().filter(|s| !s.is_empty()).collect::<Vec<_>>().join(", "),
+        subject: get("subject"),
+        date: parse_date(&get("date")),
+    };
+
+    let mut text = String::new();
+    for (label, value) in [("From", &h.from), ("To", &h.to), ("Subject", &h.subject), ("Date", &get("date"))] {
+        if !value.is_empty() { text.push_str(&format!("{}: {}\n", label, value)); }
+    }
+    text.push('\n');
+    part_text(&headers, body, 0, &mut text);
+
+    Ok(Extracted {
+        text,
+        encoding: "UTF-8",
+        title: Some(h.subject.clone()).filter(|s| !s.is_empty()),
+        author: Some(h.from.clone()).filter(|s| !s.is_empty()),
+        mail: Some(h),
+        ..Default::default()
+    })
+}
+
+type Headers = Vec<(String, String)>;
+
+/// Unfolded headers, names lowercased, and the body that follows them.
+fn split_message(bytes: &[u8]) -> (Headers, &[u8]) {
+    let mut headers: Headers = Vec::new();
+    let mut rest = bytes;
+    while !rest.is_empty() {
+        let end = rest.iter().position(|&b| b == b'\n').map_or(rest.len(), |i| i + 1);
+        let line = String::from_utf8_lossy(&rest[..end]);
+        rest = &rest[end..];
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() { break; }
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, v)) = headers.last_mut() { v.push(' '); v.push_str(line.trim()); }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+    (headers, rest)
+}
+
+fn header<'a>(headers: &'a Headers, name: &str) -> &'a str {
+    headers.iter().find(|(n, _)| n == name).map_or("", |(_, v)| v.as_str())
+}
+
+/// The `type/subtype` of a Content-Type value, lowercased.
+fn mime_type(value: &str) -> String {
+    value.split(';').next().unwrap_or_default().trim().to_ascii_lowercase()
+}
+
+/// A parameter of a header value, like the `boundary` of a multipart Content-Type.
+fn param(value: &str, name: &str) -> Option<String> {
+    value.split(';').skip(1).find_map(|p| {
+        let (k, v) = p.split_once('=')?;
+        k.trim().eq_ignore_ascii_case(name).then(|| v.trim().trim_matches('"').to_string())
+    })
+}
+
+fn part_text(headers: &Headers, body: &[u8], depth: usize, out: &mut String) {
+    if depth > MAX_MIME_DEPTH { return; }
+    let ct = header(headers, "content-type");
+    let kind = if ct.is_empty() { "text/plain".to_string() } else { mime_type(ct) };
+    if mime_type(header(headers, "content-disposition")) == "attachment" { return; }
+
+    if let Some(sub) = kind.strip_prefix("multipart/") {
+        let Some(boundary) = param(ct, "boundary") else { return };
+        let parts: Vec<(Headers, &[u8])> = multipart(body, &boundary).into_iter().map(split_message).collect();
+        if sub == "alternative" {
+            // the same content several ways: take the plainest one there is
+            let rank = |h: &Headers| match mime_type(header(h, "content-type")).as_str() { "text/plain" | "" => 0, "text/html" => 1, _ => 2 };
+            if let Some((h, b)) = parts.iter().min_by_key(|(h, _)| rank(h)) { part_text(h, b, depth + 1, out); }
+        } else {
+            for (h, b) in &parts { part_text(h, b, depth + 1, out); }
+        }
+        return;
+    }
+    if kind == "message/rfc822" {
+        let (h, b) = split_message(body);
+        part_text(&h, b, depth + 1, out);
+        return;
+    }
+    if !kind.starts_with("text/") { return; }
+
+    let raw = match header(headers, "content-transfer-encoding").to_ascii_lowercase().trim() {
+        "base64" => base64(body),
+        "quoted-printable" => quoted_printable(body),
+        _ => body.to_vec(),
+    };
+    let text = match param(ct, "charset").and_then(|c| Encoding::for_label(c.as_bytes())) {
+        Some(enc) => enc.decode_without_bom_handling(&raw).0.into_owned(),
+        None => match decode_text(&raw) { Some(d) => d.text, None => return },
+    };
+    let text = if kind == "text/html" { extract_markup(&text, MarkupKind::Html).text } else { text };
+    out.push_str(text.trim_end());
+    out.push('\n');
+}
+
+/// The bodies between `--boundary` lines, up to the closing `--boundary--`.
+fn multipart<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delim = format!("--{}", boundary);
+    let mut parts = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut pos = 0;
+    for line in body.split_inclusive(|&b| b == b'\n') {
+        let trimmed = line.trim_ascii_end();
+        if trimmed.starts_with(delim.as_bytes()) {
+            let closing = trimmed[delim.len()..].starts_with(b"--");
+            if let Some(s) = start { parts.push(trim_line_end(&body[s..pos])); }
+            if closing { return parts; }
+            start = Some(pos + line.len());
+        }
+        pos += line.len();
+    }
+    if let Some(s) = start { parts.push(&body[s..]); }
+    parts
+}
+
+/// The line break before a boundary belongs to the boundary.
+fn trim_line_end(b: &[u8]) -> &[u8] {
+    let b = b.strip_suffix(b"\n").unwrap_or(b);
+    b.strip_suffix(b"\r").unwrap_or(b)
+}
+
+fn quoted_printable(b: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(b.len());
+    let mut i = 0;
+    while i < b.len() {
+        if b[i] != b'=' {
+            out.push(b[i]);
+            i += 1;
+            continue;
+        }
+        let rest = &b[i + 1..];
+        // `=` at the end of a line is a soft line break
+        if let Some(n) = [&b"\r\n"[..], b"\n"].iter().find(|nl| rest.starts_with(nl)).map(|nl| nl.len()) {
+            i += 1 + n;
+        } else if let Some(c) = rest.get(..2).and_then(|h| std::str::from_utf8(h).ok()).and_then(|h| u8::from_str_radix(h, 16).ok()) {
+            out.push(c);
+            i += 3;
+        } else {
+            out.push(b'=');
+            i += 1;
+        }
+    }
+    out
+}
+
+/// Standard base64, ignoring line breaks and anything else outside the alphabet.
+fn base64(b: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(b.len() * 3 / 4);
+    let (mut acc, mut bits) = (0u32, 0);
+    for &c in b {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => break,
+            _ => continue,
+        };
+        acc = (acc << 6) | v as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    out
+}
+
+/// Decode RFC 2047 encoded words like `=?utf-8?Q?Caf=C3=A9?=` in a header value. Whitespace
+/// between two encoded words is dropped, as the RFC asks.
+fn decode_words(s: &str) -> String {
+    let mut out = String::new();
+    let mut rest = s;
+    let mut after_word = false;
+    while let Some(start) = rest.find("=?") {
+        let Some(decoded) = encoded_word(&rest[start..]) else {
+            out.push_str(&rest[..start + 2]);
+            rest = &rest[start + 2..];
+            after_word = false;
+            continue;
+        };
+        let gap = &rest[..start];
+        if !(after_word && gap.trim().is_empty()) { out.push_str(gap); }
+        out.push_str(&decoded.0);
+        rest = &rest[start + decoded.1..];
+        after_word = true;
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The text of the encoded word at the start of `s`, and its length.
+fn encoded_word(s: &str) -> Option<(String, usize)> {
+    let mut it = s[2..].splitn(3, '?');
+    let (charset, enc, rest) = (it.next()?, it.next()?, it.next()?);
+    let end = rest.find("?=")?;
+    let payload = &rest[..end];
+    let bytes = match enc {
+        "B" | "b" => base64(payload.as_bytes()),
+        "Q" | "q" => quoted_printable(payload.replace('_', " ").as_bytes()),
+        _ => return None,
+    };
+    // RFC 2231 allows a language after the charset, e.g. `utf-8*en`
+    let enc = Encoding::for_label(charset.split('*').next()?.as_bytes()).unwrap_or(encoding_rs::UTF_8);
+    let len = 2 + charset.len() + 1 + 1 + 1 + end + 2;
+    Some((enc.decode_without_bom_handling(&bytes).0.into_owned(), len))
+}
+
+/// Seconds since the epoch of an RFC 2822 date, tolerating a trailing comment like `(UTC)`.
+fn parse_date(s: &str) -> Option<i64> {
+    let s = s.split('(').next()?.trim();
+    chrono::DateTime::parse_from_rfc2822(s).ok().map(|d| d.timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mbox_splits_on_from_lines_after_a_blank_line() {
+        let mbox = b"preamble\n\nFrom a@x Mon Jan 1 00:00:00 2024\nSubject: one\n\nbody\nFrom inside a paragraph\n>From escaped\n\nFrom b@x Tue Jan 2 00:00:00 2024\r\nSubject: two\r\n\r\nsecond\r\n";
+        let msgs = mbox_messages(mbox);
+        assert_eq!(msgs, [
+            b"Subject: one\n\nbody\nFrom inside a paragraph\nFrom escaped\n".to_vec(),
+            b"Subject: two\r\n\r\nsecond".to_vec(),
+        ]);
+        assert!(mbox_messages(b"").is_empty());
+        assert!(mbox_messages(b"no separator at all\n").is_empty());
+        // a `From ` line right after text is part of the message, not a separator
+        assert!(mbox_messages(b"text\nFrom a@x\n").is_empty());
+        // `>>From ` loses one level of quoting only
+        assert_eq!(mbox_messages(b"From x\n>>From y\n"), [b">From y".to_vec()]);
+    }
+
+    #[test]
+    fn quoted_printable_soft_breaks_and_bad_escapes() {
+        assert_eq!(quoted_printable(b"caf=C3=A9 =3D ok"), "café = ok".as_bytes());
+        assert_eq!(quoted_printable(b"soft=\r\nbreak=\nhere"), b"softbreakhere");
+        assert_eq!(quoted_printable(b"=ZZ and =4"), b"=ZZ and =4");
+        assert_eq!(quoted_printable(b"trailing="), b"trailing=");
+        assert_eq!(quoted_printable(b""), b"");
+    }
+
+    #[test]
+    fn base64_ignores_line_breaks_and_stops_at_padding() {
+        assert_eq!(base64(b"aGVsbG8g\r\nd29ybGQ="), b"hello world");
+        assert_eq!(base64(b"aGk=garbage"), b"hi");
+        assert_eq!(base64(b"-_8"), [0xfb, 0xff]);
+        // a truncated quantum gives the whole bytes it has
+        assert_eq!(base64(b"aGVsbG"), b"hell");
+        assert_eq!(base64(b""), b"");
+    }
+
+    #[test]
+    fn encoded_words() {
+        assert_eq!(decode_words("=?utf-8?Q?Caf=C3=A9_au_lait?="), "Café au lait");
+        assert_eq!(decode_words("=?ISO-8859-1?B?SvZyZw==?= <j@x>"), "Jörg <j@x>");
+        // whitespace between encoded words goes, around plain text it stays
+        assert_eq!(decode_words("=?utf-8?Q?a?= =?utf-8?Q?b?= c"), "ab c");
+        assert_eq!(decode_words("=?utf-8*en?q?hi?="), "hi");
+        assert_eq!(decode_words("=?utf-8?X?nope?= and =?broken"), "=?utf-8?X?nope?= and =?broken");
+        assert_eq!(decode_words("=?"), "=?");
+    }
+
+    #[test]
+    fn message_headers_and_parts() {
+        let msg = b"From: =?utf-8?Q?Ren=C3=A9?= <r@x>\r\nTo: a@x\r\nCc: b@x\r\nSubject: Quarterly\r\n report\r\nDate: Tue, 2 Jan 2024 10:00:00 +0000 (UTC)\r\nContent-Type: multipart/mixed; boundary=\"outer\"\r\n\r\n--outer\r\nContent-Type: multipart/alternative; boundary=inner\r\n\r\n--inner\r\nContent-Type: text/html\r\n\r\n<p>html version</p>\r\n--inner\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: quoted-printable\r\n\r\nplain version=E2=80=94done\r\n--inner--\r\n--outer\r\nContent-Type: text/plain\r\nContent-Disposition: attachment; filename=x.txt\r\n\r\nsecret attachment\r\n--outer--\r\n";
+        let x = extract_message(msg).unwrap();
+        let h = x.mail.unwrap();
+        assert_eq!(h.from, "René <r@x>");
+        assert_eq!(h.to, "a@x, b@x");
+        assert_eq!(h.subject, "Quarterly report");
+        assert_eq!(h.date, Some(1_704_189_600));
+        assert!(x.text.contains("plain version—done"));
+        assert!(!x.text.contains("html version"));
+        assert!(!x.text.contains("secret"));
+        assert_eq!(x.title.as_deref(), Some("Quarterly report"));
+    }
+
+    #[test]
+    fn malformed_messages() {
+        assert!(extract_message(b"").is_err());
+        assert!(extract_message(b"just some text\n").is_err());
+        // a multipart without a boundary, or one never closed, still gives what it can
+        let x = extract_message(b"Subject: s\nContent-Type: multipart/mixed\n\n--b\n\nlost\n").unwrap();
+        assert_eq!(x.text, "Subject: s\n\n");
+        let x = extract_message(b"Subject: s\nContent-Type: multipart/mixed; boundary=b\n\n--b\n\nkept\n").unwrap();
+        assert!(x.text.contains("kept"));
+        assert_eq!(extract_message(b"Subject: s\nDate: yesterday\n\n").unwrap().mail.unwrap().date, None);
+    }
+}
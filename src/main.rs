@@ -1,16 +1,29 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use clap::{Parser, Subcommand};
-use std::{fs, path::PathBuf};
+use std::{collections::BTreeMap, fs, io::IsTerminal, path::PathBuf};
 
 use warpseek::search::*;
+use warpseek::archive::{extract_member, split_virtual};
+use warpseek::fuzzy::fuzzy_paths;
+use warpseek::manifest::Manifest;
+use warpseek::names::NameIndex;
+use warpseek::query::{grep_index, open_existing_index, query_index, regex_index, QueryOptions};
+use warpseek::roots::{parse_size, RootConfig};
+use warpseek::symbols::find_symbols;
+use warpseek::watch::watch;
+use warpseek::workspace::Workspace;
 
 #[derive(Parser)]
 #[command(name="warpseek", version, about="Blazing-fast terminal search: instant filename fuzzy + full-text across your folders.")]
 struct Cli {
     /// Index location (defaults to OS data dir)
-    #[arg(long)]
+    #[arg(long, global=true)]
     index_dir: Option<PathBuf>,
 
+    /// Use a named, isolated index and config (also honours WARPSEEK_HOME)
+    #[arg(long, global=true)]
+    profile: Option<String>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -23,12 +36,70 @@ enum Commands {
     Add { #[arg(required=true)] paths: Vec<PathBuf> },
     /// Remove paths from config (does not delete files)
     Remove { #[arg(required=true)] paths: Vec<PathBuf> },
+    /// Show or change indexing settings for a root
+    Root {
+        path: PathBuf,
+        /// Only index files matching this glob (repeatable)
+        #[arg(long)] include: Vec<String>,
+        /// Skip files matching this glob (repeatable)
+        #[arg(long)] exclude: Vec<String>,
+        /// Drop existing include/exclude globs before adding new ones
+        #[arg(long)] clear_globs: bool,
+        /// Comma-separated ripgrep file types to index; `all` for every known type, "" for no filter
+        #[arg(long, value_delimiter=',')] types: Option<Vec<String>>,
+        /// Largest file to index, e.g. 512KB or 20MB
+        #[arg(long)] max_size: Option<String>,
+        #[arg(long)] follow_links: Option<bool>,
+        #[arg(long)] hidden: Option<bool>,
+        #[arg(long)] one_file_system: Option<bool>,
+        /// Comma-separated ignore files to honour, e.g. .gitignore,.ignore,.rgignore
+        #[arg(long, value_delimiter=',')] ignore_files: Option<Vec<String>>,
+        /// Index content as trigrams for `q --literal` substring search (a much larger index)
+        #[arg(long)] trigrams: Option<bool>,
+        /// Store each file's full text so `--vimgrep`, `--regex` and snippets don't read it again (a larger index)
+        #[arg(long)] store_text: Option<bool>,
+        /// Index content and names with case kept, for fast case-sensitive search (a larger index)
+        #[arg(long)] keep_case: Option<bool>,
+    },
     /// Re/build the index now
-    Index,
+    Index {
+        /// Throw the index away and build it from scratch, e.g. after upgrading warpseek
+        #[arg(long)] rebuild: bool,
+    },
     /// Query by text (content+name)
-    Q { query: String, #[arg(long, default_value_t=50)] top: usize, #[arg(long)] names_only: bool },
+    Q {
+        /// Free text plus filters like ext:rs, size:>1MB, modified:<7d, path:src/**, root:work, kind:pdf
+        #[arg(allow_hyphen_values=true)] query: String,
+        #[arg(long, default_value_t=50)] top: usize,
+        #[arg(long)] names_only: bool,
+        /// Show the best matching lines under each hit
+        #[arg(long)] snippets: bool,
+        /// Print every match as path:line:col:text (ripgrep --vimgrep format)
+        #[arg(long, conflicts_with_all=["names_only", "snippets"])] vimgrep: bool,
+        /// Treat the query as a regular expression and list the matching lines of each file
+        #[arg(long, conflicts_with_all=["names_only", "snippets"])] regex: bool,
+        /// Find the query as an exact substring, punctuation and all, e.g. `Handler::new(`
+        #[arg(long, conflicts_with_all=["names_only", "snippets", "regex"])] literal: bool,
+        /// Tell `React` from `react`; by default case matters only if the query has an uppercase letter
+        #[arg(short='s', long)] case_sensitive: bool,
+        /// Ignore case even if the query has an uppercase letter
+        #[arg(short='i', long, conflicts_with="case_sensitive")] ignore_case: bool,
+    },
     /// Fuzzy match filenames (fast, no content)
     F { pattern: String, #[arg(long, default_value_t=50)] top: usize },
+    /// Fuzzy match definitions in source files (functions, types, classes, constants, modules)
+    Sym {
+        pattern: String,
+        #[arg(long, default_value_t=50)] top: usize,
+        /// Only show this kind of definition, e.g. function, method, struct or class
+        #[arg(long)] kind: Option<String>,
+    },
+    /// Open a result with the default application; archive members are extracted to a temp file first
+    Open {
+        path: String,
+        /// Print the path that would be opened instead of opening it
+        #[arg(long)] print: bool,
+    },
     /// Show stats
     Stats,
     /// Purge the index
@@ -37,23 +108,48 @@ enum Commands {
     Watch,
 }
 
-fn index_path(cli: &Cli) -> Result<PathBuf> {
-    Ok(match &cli.index_dir {
-        Some(p) => p.clone(),
-        None => project_dirs()?.data_dir().join("index"),
-    })
+/// Wrap the chars at `positions` in ANSI bold green when writing to a terminal.
+fn highlight(text: &str, positions: &[usize], color: bool) -> String {
+    if !color || positions.is_empty() { return text.to_string(); }
+    let mut out = String::with_capacity(text.len() + positions.len() * 10);
+    for (i, c) in text.chars().enumerate() {
+        if positions.binary_search(&i).is_ok() {
+            out.push_str("\x1b[1;32m");
+            out.push(c);
+            out.push_str("\x1b[0m");
+        } else {
+            out.push(c);
+        }
+    }
+    out
 }
 
-fn ensure_index(cli: &Cli) -> Result<()> {
-    let p = index_path(cli)?;
-    fs::create_dir_all(&p)?;
-    Ok(())
+/// Wrap the byte `ranges` of `text` in ANSI bold red when writing to a terminal.
+fn highlight_ranges(text: &str, ranges: &[(usize, usize)], color: bool) -> String {
+    if !color { return text.to_string(); }
+    let mut out = String::with_capacity(text.len() + ranges.len() * 10);
+    let mut at = 0;
+    for &(start, end) in ranges {
+        out.push_str(&text[at..start]);
+        out.push_str("\x1b[1;31m");
+        out.push_str(&text[start..end]);
+        out.push_str("\x1b[0m");
+        at = end;
+    }
+    out.push_str(&text[at..]);
+    out
 }
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
-    ensure_index(&cli)?;
-    let mut cfg = read_config()?;
+    let mut ws = Workspace::resolve(cli.index_dir.clone(), cli.profile.as_deref())?;
+    fs::create_dir_all(&ws.index_dir)?;
+    // commands that only read the index first bring one from an older warpseek up to date
+    if matches!(cli.command, Commands::Q { .. } | Commands::F { .. } | Commands::Sym { .. } | Commands::Stats) {
+        if let Some(report) = upgrade_index(&mut ws)? {
+            eprintln!("🔄 Rebuilt the index for this version of warpseek ({})", report);
+        }
+    }
 
     match &cli.command {
         Commands::Init { roots } => {
@@ -70,15 +166,18 @@ fn main() -> Result<()> {
                 }
             }
             
-            cfg.roots = roots.clone();
-            write_config(&cfg)?;
+            // keep the settings of roots that were already configured
+            ws.config.roots = roots.iter()
+                .map(|p| ws.config.roots.iter().find(|r| &r.path == p).cloned().unwrap_or_else(|| RootConfig::new(p.clone())))
+                .collect();
+            ws.save_config()?;
             
             println!("📝 Configuration saved");
             println!("🔍 Building index (this may take a while for large directories)...");
             
-            match index_all(&mut cfg) {
-                Ok(_) => {
-                    println!("✅ Index created successfully!");
+            match index_all(&mut ws) {
+                Ok(report) => {
+                    println!("✅ Index created successfully! ({})", report);
                     println!("🎯 You can now search with: warpseek q \"your query\"");
                     println!("🔍 Or fuzzy search filenames with: warpseek f \"pattern\"");
                 }
@@ -91,56 +190,197 @@ fn main() -> Result<()> {
         Commands::Add { paths } => {
             println!("➕ Adding paths to index...");
             for p in paths { 
-                if !cfg.roots.contains(p) { 
-                    cfg.roots.push(p.clone()); 
+                if !ws.config.roots.iter().any(|r| &r.path == p) { 
+                    ws.config.roots.push(RootConfig::new(p.clone())); 
                 } 
             }
-            write_config(&cfg)?;
-            index_all(&mut cfg)?;
-            println!("✅ Added paths and rebuilt index!");
+            ws.save_config()?;
+            let report = index_all(&mut ws)?;
+            println!("✅ Added paths and updated index! ({})", report);
         }
         Commands::Remove { paths } => {
             println!("➖ Removing paths from config...");
-            cfg.roots.retain(|r| !paths.contains(r));
-            write_config(&cfg)?;
+            ws.config.roots.retain(|r| !paths.contains(&r.path));
+            ws.save_config()?;
             println!("✅ Removed paths from config!");
         }
-        Commands::Index => { 
-            println!("🔄 Rebuilding index...");
-            index_all(&mut cfg)?; 
-            println!("✅ Index rebuilt successfully!");
+        Commands::Root { path, include, exclude, clear_globs, types, max_size, follow_links, hidden, one_file_system, ignore_files, trigrams, store_text, keep_case } => {
+            let canon = path.canonicalize().ok();
+            let root = ws.config.roots.iter_mut()
+                .find(|r| &r.path == path || (canon.is_some() && r.path.canonicalize().ok() == canon))
+                .ok_or_else(|| anyhow!("{} is not a configured root; add it with `warpseek add`", path.display()))?;
+            let s = &mut root.settings;
+            let before = s.clone();
+            if *clear_globs { s.include.clear(); s.exclude.clear(); }
+            s.include.extend(include.iter().cloned());
+            s.exclude.extend(exclude.iter().cloned());
+            if let Some(t) = types { s.file_types = t.iter().filter(|t| !t.is_empty()).cloned().collect(); }
+            if let Some(m) = max_size { s.max_file_size = parse_size(m)?; }
+            if let Some(v) = follow_links { s.follow_links = *v; }
+            if let Some(v) = hidden { s.hidden = *v; }
+            if let Some(v) = one_file_system { s.one_file_system = *v; }
+            if let Some(v) = trigrams { s.trigrams = *v; }
+            if let Some(v) = store_text { s.store_text = *v; }
+            if let Some(v) = keep_case { s.keep_case = *v; }
+            if let Some(f) = ignore_files { s.ignore_files = f.iter().filter(|f| !f.is_empty()).cloned().collect(); }
+            let changed = root.settings != before;
+            // fail on bad globs or type names before anything is saved
+            root.types()?;
+            root.overrides()?;
+            println!("📁 {}", root.path.display());
+            println!("{}", serde_json::to_string_pretty(&root.settings)?);
+            if changed {
+                ws.save_config()?;
+                println!("✅ Settings saved; run `warpseek index` to apply them");
+            }
+        }
+        Commands::Index { rebuild } => {
+            if *rebuild {
+                remove_index(&ws.index_dir)?;
+                println!("🗑️  Removed the old index");
+            }
+            println!("🔄 Updating index...");
+            let report = index_all(&mut ws)?;
+            println!("✅ Index updated: {}", report);
+        }
+        Commands::Q { query, top, names_only, snippets, vimgrep, regex, literal, case_sensitive, ignore_case } => {
+            let fragments = if *snippets { 3 } else { 0 };
+            let case_sensitive = if *case_sensitive { Some(true) } else if *ignore_case { Some(false) } else { None };
+            if *regex || *literal {
+                let pattern = if *literal { regex::escape(query) } else { query.clone() };
+                let matches = regex_index(&ws, &pattern, &QueryOptions { top: *top, case_sensitive, ..Default::default() })?;
+                if matches.is_empty() {
+                    eprintln!("🔍 No matches for {}", if *literal { format!("\"{}\"", query) } else { format!("/{}/", query) });
+                }
+                if *vimgrep {
+                    for m in matches { println!("{}", m); }
+                    return Ok(());
+                }
+                let color = std::io::stdout().is_terminal();
+                let mut i = 0;
+                while i < matches.len() {
+                    let path = &matches[i].path;
+                    println!("{}", path);
+                    while i < matches.len() && &matches[i].path == path {
+                        let m = &matches[i];
+                        // a line matching several times is printed once with every match highlighted
+                        let mut ranges = Vec::new();
+                        while i < matches.len() && &matches[i].path == path && matches[i].line == m.line {
+                            ranges.push((matches[i].column - 1, matches[i].end - 1));
+                            i += 1;
+                        }
+                        let at = match (m.page, m.cell) {
+                            (Some(p), _) => format!("page {}", p),
+                            (_, Some(c)) => format!("cell {}", c),
+                            _ => format!("{}:{}", m.line, m.column),
+                        };
+                        println!("{:>8}: {}", at, highlight_ranges(&m.text, &ranges, color));
+                    }
+                }
+                return Ok(());
+            }
+            if *vimgrep {
+                for m in grep_index(&ws, query, &QueryOptions { top: *top, case_sensitive, ..Default::default() })? {
+                    println!("{}", m);
+                }
+                return Ok(());
+            }
+            let hits = query_index(&ws, query, &QueryOptions { top: *top, names_only: *names_only, fragments, case_sensitive })?;
+            if hits.is_empty() {
+                eprintln!("🔍 No matches for \"{}\"", query);
+            }
+            let color = std::io::stdout().is_terminal();
+            for hit in hits {
+                let mut tags: Vec<String> = [hit.compression.as_str(), hit.encoding.as_str()].into_iter()
+                    .filter(|t| !t.is_empty() && *t != "UTF-8")
+                    .map(String::from)
+                    .collect();
+                match hit.page {
+                    Some(p) => tags.push(format!("page {} of {}", p, hit.page_count)),
+                    None if hit.page_count > 0 => tags.push(format!("{} pages", hit.page_count)),
+                    None => {}
+                }
+                if let Some(c) = hit.cell { tags.push(format!("cell {}", c)); }
+                if hit.width > 0 && hit.height > 0 { tags.push(format!("{}×{}", hit.width, hit.height)); }
+                if hit.duration > 0 { tags.push(format!("{}:{:02}", hit.duration / 60, hit.duration % 60)); }
+                if !hit.skipped.is_empty() { tags.push(format!("skipped: {}", hit.skipped)); }
+                if tags.is_empty() {
+                    println!("{:>8.3}  {}", hit.score, hit.path);
+                } else {
+                    println!("{:>8.3}  {} [{}]", hit.score, hit.path, tags.join(", "));
+                }
+                for f in &hit.fragments {
+                    let at = match (f.page, f.cell) {
+                        (Some(p), _) => format!("page {}", p),
+                        (_, Some(c)) => format!("cell {}", c),
+                        _ => f.line.to_string(),
+                    };
+                    println!("{:>8}: {}", at, highlight_ranges(&f.text, &f.highlights, color));
+                }
+            }
+        }
+        Commands::F { pattern, top } => {
+            let hits = fuzzy_paths(&ws, pattern, *top)?;
+            if hits.is_empty() {
+                eprintln!("🔍 No files matching \"{}\"", pattern);
+            }
+            let color = std::io::stdout().is_terminal();
+            for hit in hits {
+                println!("{}", highlight(&hit.path, &hit.positions, color));
+            }
         }
-        Commands::Q { query, top, names_only } => { 
-            println!("🔍 Searching for: \"{}\"", query);
-            println!("📊 Max results: {}", top);
-            if *names_only {
-                println!("📝 Names only: true");
+        Commands::Sym { pattern, top, kind } => {
+            let hits = find_symbols(&ws, pattern, kind.as_deref(), *top)?;
+            if hits.is_empty() {
+                eprintln!("🔍 No symbols matching \"{}\"", pattern);
+            }
+            let color = std::io::stdout().is_terminal();
+            for hit in hits {
+                let name = highlight(&hit.name, &hit.positions, color);
+                if hit.container.is_empty() {
+                    println!("{:<9} {} {}:{}", hit.kind, name, hit.path, hit.line);
+                } else {
+                    println!("{:<9} {} {}:{} [in {}]", hit.kind, name, hit.path, hit.line, hit.container);
+                }
             }
-            println!("⚠️  Query functionality not yet implemented - use the desktop GUI for now");
         }
-        Commands::F { pattern, top } => { 
-            println!("🔍 Fuzzy searching for: \"{}\"", pattern);
-            println!("📊 Max results: {}", top);
-            println!("⚠️  Fuzzy search functionality not yet implemented - use the desktop GUI for now");
+        Commands::Open { path, print } => {
+            let target = if split_virtual(path).is_some() { extract_member(path)? } else { PathBuf::from(path) };
+            if *print {
+                println!("{}", target.display());
+                return Ok(());
+            }
+            let opener = if cfg!(target_os = "macos") { "open" } else if cfg!(windows) { "explorer" } else { "xdg-open" };
+            std::process::Command::new(opener).arg(&target).spawn().with_context(|| format!("run {} {}", opener, target.display()))?;
         }
         Commands::Stats => {
-            let index_dir = index_path(&cli)?;
+            let index_dir = &ws.index_dir;
             println!("📊 WarpSeek Statistics:");
             println!("  📁 Index directory: {}", index_dir.display());
-            println!("  📂 Configured roots: {}", cfg.roots.len());
-            for (i, root) in cfg.roots.iter().enumerate() {
-                println!("    {}. {}", i + 1, root.display());
+            println!("  📂 Configured roots: {}", ws.config.roots.len());
+            for (i, root) in ws.config.roots.iter().enumerate() {
+                println!("    {}. {}", i + 1, root.path.display());
             }
             if index_dir.exists() {
                 println!("  ✅ Index exists");
                 // Try to get basic stats
-                match open_index(&index_dir) {
+                match open_existing_index(index_dir) {
                     Ok((index, _)) => {
                         let reader = index.reader()?;
                         println!("  📊 Segments: {}", reader.searcher().segment_readers().len());
+                        if let Ok(names) = NameIndex::open(index_dir) {
+                            println!("  📄 Distinct filenames: {}", names.len());
+                        }
+                        let mut skipped: BTreeMap<String, usize> = BTreeMap::new();
+                        for e in Manifest::load(index_dir)?.files.values() {
+                            if let Some(r) = &e.skipped { *skipped.entry(r.clone()).or_default() += 1; }
+                        }
+                        for (reason, n) in skipped {
+                            println!("  ⏭️  Indexed by name only ({}): {}", reason, n);
+                        }
                     }
-                    Err(_) => {
-                        println!("  ⚠️  Index exists but may be corrupted");
+                    Err(e) => {
+                        println!("  ⚠️  Index can't be opened: {}", e);
                     }
                 }
             } else {
@@ -148,27 +388,21 @@ fn main() -> Result<()> {
             }
         }
         Commands::Purge => {
-            let p = index_path(&cli)?;
-            if p.exists() { 
-                fs::remove_dir_all(&p)?; 
+            let p = &ws.index_dir;
+            if p.exists() {
+                remove_index(p)?;
                 println!("✅ Purged index at: {}", p.display());
             } else {
                 println!("ℹ️  No index found to purge");
             }
         }
         Commands::Watch => {
-            let roots = cfg.roots.clone();
-            if roots.is_empty() { 
+            if ws.config.roots.is_empty() { 
                 return Err(anyhow!("no roots configured; run `warpseek init <paths>`")); 
             }
-            println!("👀 Watching for changes in: {:?}", roots);
+            println!("👀 Watching for changes in: {:?}", ws.config.root_paths());
             println!("Press Ctrl+C to stop...");
-            // For now, just rebuild periodically
-            loop {
-                std::thread::sleep(std::time::Duration::from_secs(30));
-                println!("🔄 Auto-rebuilding index...");
-                let _ = index_all(&mut cfg);
-            }
+            watch(&mut ws, |report| println!("🔄 {}", report))?;
         }
     }
     Ok(())
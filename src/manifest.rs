@@ -0,0 +1,91 @@
+use anyhow::Result;
+use serde::{Serialize, Deserialize};
+use std::{collections::BTreeMap, fs, path::{Path, PathBuf}, time::SystemTime};
+
+use crate::search::SCHEMA_VERSION;
+
+/// What we knew about a file the last time it was indexed.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct FileEntry {
+    pub size: u64,
+    /// modification time in nanoseconds since the epoch
+    pub mtime: i64,
+    /// xxh3 of the raw file bytes
+    pub hash: u64,
+    /// for archives, the member paths that were found inside
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub members: Vec<String>,
+    /// why the content could not be extracted, when only the name and metadata were indexed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<String>,
+    /// whether the content was indexed as trigrams too
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub trigrams: bool,
+    /// whether the full text was stored in the index
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub store_text: bool,
+    /// whether content and name were indexed with their case kept too
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub keep_case: bool,
+}
+
+impl FileEntry {
+    /// Cheap check that lets us skip reading a file at all.
+    pub fn same_stat(&self, size: u64, mtime: i64) -> bool {
+        self.size == size && self.mtime == mtime
+    }
+}
+
+/// Per-file change manifest stored next to the index, keyed by the indexed `path`.
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    /// `SCHEMA_VERSION` of the warpseek that built the index; 0 for indexes from before it
+    #[serde(default)]
+    pub schema_version: u32,
+    pub files: BTreeMap<String, FileEntry>,
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Manifest { schema_version: SCHEMA_VERSION, files: BTreeMap::new() }
+    }
+}
+
+pub fn manifest_path(index_dir: &Path) -> PathBuf {
+    index_dir.join("manifest.json")
+}
+
+impl Manifest {
+    pub fn load(index_dir: &Path) -> Result<Manifest> {
+        let p = manifest_path(index_dir);
+        if p.exists() {
+            let s = fs::read_to_string(&p)?;
+            Ok(serde_json::from_str(&s)?)
+        } else {
+            Ok(Manifest::default())
+        }
+    }
+
+    pub fn save(&self, index_dir: &Path) -> Result<()> {
+        let p = manifest_path(index_dir);
+        // write then rename so a crash never leaves a truncated manifest behind
+        let tmp = p.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec(self)?)?;
+        fs::rename(tmp, p)?;
+        Ok(())
+    }
+}
+
+pub fn mtime_nanos(meta: &fs::Metadata) -> i64 {
+    meta.modified().ok()
+        .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos() as i64)
+        .unwrap_or(0)
+}
+
+/// Creation time in seconds since the epoch, where the filesystem records one.
+pub fn created_secs(meta: &fs::Metadata) -> Option<i64> {
+    meta.created().ok()
+        .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs() as i64)
+}
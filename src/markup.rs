@@ -0,0 +1,265 @@
+use std::borrow::Cow;
+
+use crate::extract::Extracted;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MarkupKind { Html, Xml }
+
+pub fn markup_kind(name: &str) -> Option<MarkupKind> {
+    let ext = name.rsplit_once('.')?.1.to_ascii_lowercase();
+    match ext.as_str() {
+        "html" | "htm" | "xhtml" => Some(MarkupKind::Html),
+        "xml" => Some(MarkupKind::Xml),
+        _ => None,
+    }
+}
+
+/// Elements that start a new line of text. In XML every element does.
+const BLOCK_TAGS: &[&str] = &[
+    "address", "article", "aside", "blockquote", "body", "br", "caption", "dd", "details", "div", "dl", "dt",
+    "figcaption", "figure", "footer", "form", "h1", "h2", "h3", "h4", "h5", "h6", "header", "hr", "li",
+    "main", "nav", "ol", "p", "pre", "section", "summary", "table", "td", "th", "title", "tr", "ul",
+];
+
+/// Elements whose content is never text.
+const RAW_TAGS: &[&str] = &["script", "style", "template", "noscript"];
+
+/// Text of an HTML or XML document with tags, comments, scripts and styles removed and
+/// entities decoded. Whitespace is collapsed outside `<pre>`. The first `<title>` becomes the
+/// title, and in HTML the text of every `<h1>`..`<h6>` is also kept as a heading.
+pub fn extract_markup(src: &str, kind: MarkupKind) -> Extracted {
+    let mut x = Extracted::default();
+    let mut out = String::new();
+    // (element, text so far) while inside a title or heading
+    let mut capture: Option<(String, String)> = None;
+    let mut pre = 0u32;
+    let mut i = 0;
+    // for finding end tags case-insensitively; ASCII lowercasing keeps byte offsets
+    let lower = src.to_ascii_lowercase();
+    while i < src.len() {
+        let rest = &src[i..];
+        if !rest.starts_with('<') {
+            let end = rest.find('<').unwrap_or(rest.len());
+            let text = decode_entities(&rest[..end]);
+            push_text(&mut out, &text, pre > 0);
+            if let Some((_, c)) = &mut capture { push_text(c, &text, false); }
+            i += end;
+            continue;
+        }
+        if let Some(body) = rest.strip_prefix("<!--") {
+            i += 4 + body.find("-->").map_or(body.len(), |e| e + 3);
+            continue;
+        }
+        if let Some(body) = rest.strip_prefix("<![CDATA[") {
+            let end = body.find("]]>").unwrap_or(body.len());
+            push_text(&mut out, &body[..end], pre > 0);
+            i += 9 + (end + 3).min(body.len());
+            continue;
+        }
+        let Some(len) = tag_len(rest) else {
+            // a stray `<` in text
+            push_text(&mut out, "<", pre > 0);
+            i += 1;
+            continue;
+        };
+        let tag = &rest[1..len - 1];
+        i += len;
+        if tag.starts_with('!') || tag.starts_with('?') { continue; }
+        let closing = tag.starts_with('/');
+        let name: String = tag.trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | ':' | '.'))
+            .collect::<String>()
+            .to_ascii_lowercase();
+        let name = name.rsplit(':').next().unwrap_or_default().to_string();
+        let self_closing = tag.ends_with('/');
+
+        if kind == MarkupKind::Html && !closing && !self_closing && RAW_TAGS.contains(&name.as_str()) {
+            // skip to the matching end tag, whatever it contains
+            i = match lower[i..].find(&format!("</{}", name)) {
+                Some(e) => i + e + src[i + e..].find('>').map_or(src.len() - i - e, |g| g + 1),
+                None => src.len(),
+            };
+            continue;
+        }
+        if kind == MarkupKind::Xml || BLOCK_TAGS.contains(&name.as_str()) { newline(&mut out); }
+        if name == "pre" && !self_closing { pre = if closing { pre.saturating_sub(1) } else { pre + 1 }; }
+
+        let captured = name == "title" || (kind == MarkupKind::Html && is_heading(&name));
+        if !captured || self_closing { continue; }
+        match (closing, capture.take()) {
+            (false, None) => capture = Some((name, String::new())),
+            (true, Some((open, text))) if open == name => {
+                let text = text.trim().to_string();
+                if text.is_empty() { continue; }
+                if name == "title" { x.title.get_or_insert(text); } else { x.headings.push(text); }
+            }
+            (_, c) => capture = c,
+        }
+    }
+    x.text = out.trim().to_string();
+    x.encoding = "UTF-8";
+    x
+}
+
+fn is_heading(name: &str) -> bool {
+    matches!(name, "h1" | "h2" | "h3" | "h4" | "h5" | "h6")
+}
+
+/// Length of the tag at the start of `s`, through its `>`, skipping over quoted attribute values.
+/// `None` if `s` doesn't start with something that looks like a tag.
+fn tag_len(s: &str) -> Option<usize> {
+    let first = s[1..].chars().next()?;
+    if !(first.is_alphabetic() || matches!(first, '/' | '!' | '?')) { return None; }
+    let mut quote = None;
+    for (j, c) in s.char_indices().skip(1) {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, '>') => return Some(j + 1),
+            (None, '<') => return None,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn push_text(out: &mut String, text: &str, keep_whitespace: bool) {
+    if keep_whitespace {
+        out.push_str(text);
+        return;
+    }
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !out.is_empty() && !out.ends_with(' ') && !out.ends_with('\n') { out.push(' '); }
+        } else {
+            out.push(c);
+        }
+    }
+}
+
+fn newline(out: &mut String) {
+    while out.ends_with(' ') { out.pop(); }
+    if !out.is_empty() && !out.ends_with('\n') { out.push('\n'); }
+}
+
+/// Named entities beyond the Latin-1 block, which `LATIN1` covers in code point order.
+const ENTITIES: &[(&str, u32)] = &[
+    ("quot", 34), ("amp", 38), ("apos", 39), ("lt", 60), ("gt", 62), ("OElig", 338), ("oelig", 339),
+    ("Scaron", 352), ("scaron", 353), ("Yuml", 376), ("fnof", 402), ("circ", 710), ("tilde", 732),
+    ("ensp", 8194), ("emsp", 8195), ("thinsp", 8201), ("zwnj", 8204), ("zwj", 8205), ("lrm", 8206),
+    ("rlm", 8207), ("ndash", 8211), ("mdash", 8212), ("lsquo", 8216), ("rsquo", 8217), ("sbquo", 8218),
+    ("ldquo", 8220), ("rdquo", 8221), ("bdquo", 8222), ("dagger", 8224), ("Dagger", 8225), ("bull", 8226),
+    ("hellip", 8230), ("permil", 8240), ("prime", 8242), ("Prime", 8243), ("lsaquo", 8249),
+    ("rsaquo", 8250), ("euro", 8364), ("trade", 8482), ("larr", 8592), ("uarr", 8593), ("rarr", 8594),
+    ("darr", 8595), ("harr", 8596), ("minus", 8722), ("infin", 8734), ("ne", 8800), ("le", 8804), ("ge", 8805),
+];
+
+/// HTML 4 entity names for U+00A0..=U+00FF.
+const LATIN1: &[&str] = &[
+    "nbsp", "iexcl", "cent", "pound", "curren", "yen", "brvbar", "sect", "uml", "copy", "ordf", "laquo",
+    "not", "shy", "reg", "macr", "deg", "plusmn", "sup2", "sup3", "acute", "micro", "para", "middot",
+    "cedil", "sup1", "ordm", "raquo", "frac14", "frac12", "frac34", "iquest", "Agrave", "Aacute", "Acirc",
+    "Atilde", "Auml", "Aring", "AElig", "Ccedil", "Egrave", "Eacute", "Ecirc", "Euml", "Igrave", "Iacute",
+    "Icirc", "Iuml", "ETH", "Ntilde", "Ograve", "Oacute", "Ocirc", "Otilde", "Ouml", "times", "Oslash",
+    "Ugrave", "Uacute", "Ucirc", "Uuml", "Yacute", "THORN", "szlig", "agrave", "aacute", "acirc", "atilde",
+    "auml", "aring", "aelig", "ccedil", "egrave", "eacute", "ecirc", "euml", "igrave", "iacute", "icirc",
+    "iuml", "eth", "ntilde", "ograve", "oacute", "ocirc", "otilde", "ouml", "divide", "oslash", "ugrave",
+    "uacute", "ucirc", "uuml", "yacute", "thorn", "yuml",
+];
+
+fn entity(name: &str) -> Option<char> {
+    if let Some(num) = name.strip_prefix('#') {
+        let n = match num.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => num.parse().ok()?,
+        };
+        return char::from_u32(n);
+    }
+    if let Some(i) = LATIN1.iter().position(|e| *e == name) { return char::from_u32(0xa0 + i as u32); }
+    ENTITIES.iter().find(|(e, _)| *e == name).and_then(|&(_, c)| char::from_u32(c))
+}
+
+/// Replace character references like `&amp;`, `&#233;` and `&#x2014;`; unknown ones stay as they are.
+pub fn decode_entities(s: &str) -> Cow<'_, str> {
+    if !s.contains('&') { return Cow::Borrowed(s); }
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let decoded = rest[1..].find(';')
+            .filter(|&end| end > 0 && end <= 32)
+            .and_then(|end| entity(&rest[1..end + 1]).map(|c| (c, end + 2)));
+        match decoded {
+            Some((c, len)) => {
+                // a non-breaking space is just a space as far as search is concerned
+                out.push(if c == '\u{a0}' { ' ' } else { c });
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    Cow::Owned(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scripts_styles_and_comments_leave_no_text() {
+        let html = r#"<html><head><title>Page &amp; co</title><style>p { color: red }</style>
+            <SCRIPT type="text/javascript">if (a < b && "</div>") { x() }</script></head>
+            <body><!-- hidden <p>comment</p> --><p class="a>b">Hello <b>big</b>   world</p><noscript>enable js</noscript>tail</body></html>"#;
+        let x = extract_markup(html, MarkupKind::Html);
+        assert_eq!(x.text, "Page & co\nHello big world\ntail");
+        assert_eq!(x.title.as_deref(), Some("Page & co"));
+    }
+
+    #[test]
+    fn an_unclosed_script_hides_the_rest() {
+        assert_eq!(extract_markup("<p>before</p><script>never ends", MarkupKind::Html).text, "before");
+    }
+
+    #[test]
+    fn headings_pre_cdata_and_stray_brackets() {
+        let html = "<h1>Main <em>topic</em></h1><h2></h2><p>1 < 2</p><pre>  keep\n    this</pre><h3>Sub</h3><![CDATA[raw <text>]]>";
+        let x = extract_markup(html, MarkupKind::Html);
+        assert_eq!(x.text, "Main topic\n1 < 2\n  keep\n    this\nSub\nraw <text>");
+        assert_eq!(x.headings, ["Main topic", "Sub"]);
+        assert_eq!(x.title, None);
+    }
+
+    #[test]
+    fn xml_elements_are_lines_and_scripts_are_just_elements() {
+        let xml = r#"<?xml version="1.0"?><!DOCTYPE note><note><title>Memo</title><to>Tove</to><script>kept</script><h1>not a heading</h1></note>"#;
+        let x = extract_markup(xml, MarkupKind::Xml);
+        assert_eq!(x.text, "Memo\nTove\nkept\nnot a heading");
+        assert_eq!(x.title.as_deref(), Some("Memo"));
+        assert!(x.headings.is_empty());
+    }
+
+    #[test]
+    fn entities_by_name_and_number() {
+        assert_eq!(decode_entities("caf&eacute; &#233; &#xE9; &yuml; &euro;&mdash;&hellip;"), "café é é ÿ €—…");
+        assert_eq!(decode_entities("a&nbsp;b &lt;tag&gt; &quot;q&quot;"), "a b <tag> \"q\"");
+        // unknown, unterminated and invalid references stay as they are
+        assert_eq!(decode_entities("&bogus; AT&T &; &#xD800; &amp"), "&bogus; AT&T &; &#xD800; &amp");
+        assert!(matches!(decode_entities("plain"), Cow::Borrowed("plain")));
+    }
+
+    #[test]
+    fn kinds_by_extension() {
+        assert_eq!(markup_kind("index.HTM"), Some(MarkupKind::Html));
+        assert_eq!(markup_kind("ch1.xhtml"), Some(MarkupKind::Html));
+        assert_eq!(markup_kind("pom.xml"), Some(MarkupKind::Xml));
+        assert_eq!(markup_kind("notes.md"), None);
+        assert_eq!(markup_kind("html"), None);
+    }
+}
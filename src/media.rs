@@ -0,0 +1,754 @@
+use std::collections::HashMap;
+
+use crate::extract::Extracted;
+
+/// Photos, audio and video we read metadata from; registered as the `media` file type.
+pub const MEDIA_GLOBS: &[&str] = &[
+    "*.jpg", "*.jpeg", "*.png", "*.tif", "*.tiff",
+    "*.mp3", "*.flac", "*.ogg", "*.oga", "*.opus", "*.m4a", "*.mp4", "*.m4v", "*.mov",
+];
+
+/// Metadata of a photo, song or video. Everything is optional; formats carry what they carry.
+#[derive(Debug, Clone, Default)]
+pub struct MediaInfo {
+    /// camera make and model
+    pub camera: Option<String>,
+    pub lens: Option<String>,
+    /// capture or creation time as `YYYY-MM-DDTHH:MM:SS`, in whatever zone the device used
+    pub taken: Option<String>,
+    /// whether the photo has a GPS position
+    pub gps: bool,
+    pub width: Option<u64>,
+    pub height: Option<u64>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    /// whole seconds
+    pub duration: Option<u64>,
+}
+
+/// Read the metadata of a photo, audio or video file, recognised by its leading bytes. The
+/// values are also written out as text lines like `Artist: Radiohead` so plain searches find
+/// them. `None` for anything else, or a file with no metadata at all.
+pub fn extract_media(bytes: &[u8]) -> Option<Extracted> {
+    let mut m = MediaInfo::default();
+    if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+        jpeg(bytes, &mut m);
+    } else if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        png(bytes, &mut m);
+    } else if bytes.starts_with(b"II*\0") || bytes.starts_with(b"MM\0*") {
+        exif(bytes, &mut m);
+    } else if bytes.starts_with(b"fLaC") {
+        flac(bytes, &mut m);
+    } else if bytes.starts_with(b"OggS") {
+        ogg(bytes, &mut m);
+    } else if bytes.get(4..8) == Some(b"ftyp") {
+        mp4(bytes, &mut m);
+    } else if bytes.starts_with(b"ID3") || mpeg_frame(bytes).is_some() {
+        mp3(bytes, &mut m);
+    } else {
+        return None;
+    }
+
+    let mut text = String::new();
+    let mut line = |label: &str, v: Option<String>| if let Some(v) = v { text.push_str(&format!("{}: {}\n", label, v)); };
+    line("Title", m.title.clone());
+    line("Artist", m.artist.clone());
+    line("Album", m.album.clone());
+    line("Camera", m.camera.clone());
+    line("Lens", m.lens.clone());
+    line("Taken", m.taken.clone());
+    line("Dimensions", m.width.zip(m.height).map(|(w, h)| format!("{}x{}", w, h)));
+    line("Duration", m.duration.map(|d| format!("{}:{:02}", d / 60, d % 60)));
+    if text.is_empty() && !m.gps { return None; }
+    Some(Extracted { text, encoding: "UTF-8", title: m.title.clone(), media: Some(m), ..Default::default() })
+}
+
+fn be16(b: &[u8], at: usize) -> Option<u16> { Some(u16::from_be_bytes(b.get(at..at + 2)?.try_into().ok()?)) }
+fn be32(b: &[u8], at: usize) -> Option<u32> { Some(u32::from_be_bytes(b.get(at..at + 4)?.try_into().ok()?)) }
+fn be64(b: &[u8], at: usize) -> Option<u64> { Some(u64::from_be_bytes(b.get(at..at + 8)?.try_into().ok()?)) }
+fn le32(b: &[u8], at: usize) -> Option<u32> { Some(u32::from_le_bytes(b.get(at..at + 4)?.try_into().ok()?)) }
+fn le64(b: &[u8], at: usize) -> Option<u64> { Some(u64::from_le_bytes(b.get(at..at + 8)?.try_into().ok()?)) }
+
+/// Trimmed, non-empty text with NULs removed; tags pad with both.
+fn clean(s: &str) -> Option<String> {
+    let s = s.trim_matches(|c: char| c == '\0' || c.is_whitespace()).replace('\0', " ");
+    (!s.is_empty()).then_some(s)
+}
+
+// --- images ---
+
+/// Walk the JPEG segments up to the image data: EXIF from APP1, dimensions from the frame header.
+fn jpeg(b: &[u8], m: &mut MediaInfo) {
+    let mut i = 2;
+    while i + 4 <= b.len() {
+        if b[i] != 0xff { return; }
+        let marker = b[i + 1];
+        // fill bytes and markers without a length
+        if marker == 0xff { i += 1; continue; }
+        if matches!(marker, 0x01 | 0xd0..=0xd8) { i += 2; continue; }
+        let Some(len) = be16(b, i + 2).map(usize::from) else { return };
+        let Some(seg) = b.get(i + 4..i + 2 + len) else { return };
+        match marker {
+            0xe1 => if let Some(tiff) = seg.strip_prefix(b"Exif\0\0") { exif(tiff, m) },
+            // start of frame, any kind but the DHT/JPG/DAC markers sharing the range
+            0xc0..=0xcf if !matches!(marker, 0xc4 | 0xc8 | 0xcc) => {
+                if let (Some(h), Some(w)) = (be16(seg, 1), be16(seg, 3)) {
+                    m.height = Some(h as u64);
+                    m.width = Some(w as u64);
+                }
+            }
+            0xda => return,
+            _ => {}
+        }
+        i += 2 + len;
+    }
+}
+
+fn png(b: &[u8], m: &mut MediaInfo) {
+    let mut i = 8;
+    while let (Some(len), Some(kind)) = (be32(b, i), b.get(i + 4..i + 8)) {
+        let Some(data) = b.get(i + 8..i + 8 + len as usize) else { return };
+        match kind {
+            b"IHDR" => {
+                m.width = be32(data, 0).map(u64::from);
+                m.height = be32(data, 4).map(u64::from);
+            }
+            b"eXIf" => exif(data, m),
+            b"IEND" => return,
+            _ => {}
+        }
+        i += 12 + len as usize;
+    }
+}
+
+/// Values of interest from a TIFF structure: the main IFD, the EXIF IFD and the GPS IFD.
+fn exif(t: &[u8], m: &mut MediaInfo) {
+    let le = t.starts_with(b"II");
+    let u16_at = |at: usize| t.get(at..at + 2).map(|s| if le { u16::from_le_bytes([s[0], s[1]]) } else { u16::from_be_bytes([s[0], s[1]]) });
+    let u32_at = |at: usize| t.get(at..at + 4).map(|s| {
+        let a = [s[0], s[1], s[2], s[3]];
+        if le { u32::from_le_bytes(a) } else { u32::from_be_bytes(a) }
+    });
+    // tag -> (type, count, offset of the value)
+    let ifd = |at: usize| -> HashMap<u16, (u16, u32, usize)> {
+        let mut tags = HashMap::new();
+        let Some(n) = u16_at(at) else { return tags };
+        for k in 0..n as usize {
+            let e = at + 2 + k * 12;
+            let (Some(tag), Some(ty), Some(count)) = (u16_at(e), u16_at(e + 2), u32_at(e + 4)) else { break };
+            let size: usize = match ty { 1 | 2 | 6 | 7 => 1, 3 | 8 => 2, 4 | 9 | 11 => 4, 5 | 10 | 12 => 8, _ => continue };
+            let inline = size.saturating_mul(count as usize) <= 4;
+            let off = if inline { e + 8 } else { u32_at(e + 8).unwrap_or(0) as usize };
+            tags.insert(tag, (ty, count, off));
+        }
+        tags
+    };
+    let text = |tags: &HashMap<u16, (u16, u32, usize)>, tag: u16| {
+        let &(ty, count, off) = tags.get(&tag)?;
+        if ty != 2 { return None; }
+        clean(&String::from_utf8_lossy(t.get(off..off + count as usize)?))
+    };
+    let number = |tags: &HashMap<u16, (u16, u32, usize)>, tag: u16| {
+        let &(ty, _, off) = tags.get(&tag)?;
+        match ty { 3 => u16_at(off).map(u64::from), 4 => u32_at(off).map(u64::from), _ => None }
+    };
+
+    let Some(main) = u32_at(4).map(|o| ifd(o as usize)) else { return };
+    let make = text(&main, 0x010f);
+    let model = text(&main, 0x0110);
+    // models usually repeat the make, e.g. `Canon` + `Canon EOS R5`
+    m.camera = match (make, model) {
+        (Some(make), Some(model)) if model.to_lowercase().starts_with(&make.to_lowercase()) => Some(model),
+        (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
+        (make, model) => make.or(model),
+    };
+    let mut taken = text(&main, 0x0132);
+    let (mut w, mut h) = (number(&main, 0x0100), number(&main, 0x0101));
+    if let Some(sub) = number(&main, 0x8769).map(|o| ifd(o as usize)) {
+        taken = text(&sub, 0x9003).or(taken);
+        m.lens = text(&sub, 0xa434);
+        w = number(&sub, 0xa002).or(w);
+        h = number(&sub, 0xa003).or(h);
+    }
+    m.taken = taken.and_then(|d| exif_date(&d)).or(m.taken.take());
+    if m.width.is_none() { (m.width, m.height) = (w, h); }
+    m.gps = number(&main, 0x8825).is_some_and(|o| ifd(o as usize).contains_key(&0x0002));
+}
+
+/// `2024:07:15 10:30:00` to `2024-07-15T10:30:00`; unset dates are all zeros or blanks.
+fn exif_date(s: &str) -> Option<String> {
+    let (date, time) = s.split_once(' ').unwrap_or((s, ""));
+    if date.len() != 10 || date.starts_with("0000") || !date.starts_with(|c: char| c.is_ascii_digit()) { return None; }
+    let date = date.replace(':', "-");
+    Some(if time.is_empty() { date } else { format!("{}T{}", date, time) })
+}
+
+// --- audio ---
+
+/// ID3v2 tags at the front, ID3v1 at the back as a fallback, and the duration from the
+/// `TLEN` frame, a Xing/Info header or the bitrate of the first frame.
+fn mp3(b: &[u8], m: &mut MediaInfo) {
+    let mut audio = 0;
+    let mut tlen = None;
+    if b.starts_with(b"ID3") && b.len() >= 10 {
+        let major = b[3];
+        let size = syncsafe(&b[6..10]) as usize;
+        let tag = &b[10..(10 + size).min(b.len())];
+        audio = 10 + size + if b[5] & 0x10 != 0 { 10 } else { 0 };
+        let (id_len, head) = if major == 2 { (3, 6) } else { (4, 10) };
+        let mut i = 0;
+        while i + head <= tag.len() && tag[i] != 0 {
+            let id = &tag[i..i + id_len];
+            let len = match major {
+                2 => u32::from_be_bytes([0, tag[i + 3], tag[i + 4], tag[i + 5]]) as usize,
+                4 => syncsafe(&tag[i + 4..i + 8]) as usize,
+                _ => be32(tag, i + 4).unwrap_or(0) as usize,
+            };
+            let Some(body) = tag.get(i + head..i + head + len) else { break };
+            let value = || id3_text(body);
+            match id {
+                b"TIT2" | b"TT2" => m.title = value(),
+                b"TPE1" | b"TP1" => m.artist = value(),
+                b"TALB" | b"TAL" => m.album = value(),
+                b"TLEN" | b"TLE" => tlen = value().and_then(|v| v.parse::<u64>().ok()),
+                _ => {}
+            }
+            i += head + len;
+        }
+    }
+    if let Some(v1) = b.len().checked_sub(128).map(|at| &b[at..]).filter(|t| t.starts_with(b"TAG")) {
+        let field = |r: std::ops::Range<usize>| clean(&v1[r].iter().map(|&c| c as char).collect::<String>());
+        m.title = m.title.take().or_else(|| field(3..33));
+        m.artist = m.artist.take().or_else(|| field(33..63));
+        m.album = m.album.take().or_else(|| field(63..93));
+    }
+    m.duration = tlen.map(|ms| ms / 1000).or_else(|| mp3_duration(b, audio));
+}
+
+fn syncsafe(b: &[u8]) -> u32 {
+    b.iter().fold(0, |acc, &x| (acc << 7) | (x & 0x7f) as u32)
+}
+
+/// A text frame: an encoding byte, then Latin-1, UTF-16 with a BOM, UTF-16BE or UTF-8.
+/// Multiple values are NUL-separated and joined with `/`.
+fn id3_text(body: &[u8]) -> Option<String> {
+    let (&enc, rest) = body.split_first()?;
+    let s = match enc {
+        1 | 2 => {
+            let le = enc == 1 && rest.starts_with(&[0xff, 0xfe]);
+            let rest = if enc == 1 && rest.len() >= 2 { &rest[2..] } else { rest };
+            let units: Vec<u16> = rest.chunks_exact(2).map(|c| if le { u16::from_le_bytes([c[0], c[1]]) } else { u16::from_be_bytes([c[0], c[1]]) }).collect();
+            String::from_utf16_lossy(&units)
+        }
+        3 => String::from_utf8_lossy(rest).to_string(),
+        _ => rest.iter().map(|&c| c as char).collect(),
+    };
+    clean(&s.split('\0').filter(|p| !p.is_empty()).collect::<Vec<_>>().join("/"))
+}
+
+/// (bitrate in kbit/s, sample rate, samples per frame, side info size) of an MPEG audio frame
+/// header at the start of `b`; layer III only.
+fn mpeg_frame(b: &[u8]) -> Option<(u32, u32, u32, usize)> {
+    let h = be32(b, 0)?;
+    if h >> 21 != 0x7ff || (h >> 17) & 3 != 1 { return None; }
+    let version = (h >> 19) & 3; // 3 = MPEG1, 2 = MPEG2, 0 = MPEG2.5
+    let mpeg1 = version == 3;
+    const V1: [u32; 15] = [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320];
+    const V2: [u32; 15] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+    let bitrate = *if mpeg1 { &V1 } else { &V2 }.get(((h >> 12) & 15) as usize)?;
+    let base: u32 = *[44100, 48000, 32000].get(((h >> 10) & 3) as usize)?;
+    let rate = match version { 3 => base, 2 => base / 2, 0 => base / 4, _ => return None };
+    let mono = (h >> 6) & 3 == 3;
+    let side = match (mpeg1, mono) { (true, false) => 32, (true, true) => 17, (false, false) => 17, (false, true) => 9 };
+    Some((bitrate, rate, if mpeg1 { 1152 } else { 576 }, side))
+}
+
+fn mp3_duration(b: &[u8], start: usize) -> Option<u64> {
+    // the first frame may follow some padding
+    let at = (start..b.len().min(start + 64 * 1024)).find(|&i| mpeg_frame(&b[i..]).is_some_and(|f| f.0 > 0))?;
+    let (bitrate, rate, samples, side) = mpeg_frame(&b[at..])?;
+    if bitrate == 0 { return None; }
+    let xing = at + 4 + side;
+    if matches!(b.get(xing..xing + 4), Some(b"Xing") | Some(b"Info")) && be32(b, xing + 4)? & 1 != 0 {
+        return Some(be32(b, xing + 8)? as u64 * samples as u64 / rate as u64);
+    }
+    let end = if b.len() >= 128 && b[b.len() - 128..].starts_with(b"TAG") { b.len() - 128 } else { b.len() };
+    // the frame found may sit inside the ID3v1 tag itself
+    Some(end.checked_sub(at)? as u64 * 8 / (bitrate as u64 * 1000))
+}
+
+fn flac(b: &[u8], m: &mut MediaInfo) {
+    let mut i = 4;
+    while i + 4 <= b.len() {
+        let last = b[i] & 0x80 != 0;
+        let kind = b[i] & 0x7f;
+        let len = u32::from_be_bytes([0, b[i + 1], b[i + 2], b[i + 3]]) as usize;
+        let Some(block) = b.get(i + 4..i + 4 + len) else { return };
+        match kind {
+            0 => if let Some(x) = be64(block, 10) {
+                // 20 bits of sample rate, 3 of channels, 5 of bits per sample, 36 of total samples
+                let rate = x >> 44;
+                let total = x & 0xf_ffff_ffff;
+                if rate > 0 && total > 0 { m.duration = Some(total / rate); }
+            },
+            4 => vorbis_comments(block, m),
+            _ => {}
+        }
+        if last { return; }
+        i += 4 + len;
+    }
+}
+
+/// A Vorbis comment block: vendor string, then `KEY=value` pairs, all little-endian lengths.
+fn vorbis_comments(b: &[u8], m: &mut MediaInfo) {
+    let Some(vendor) = le32(b, 0) else { return };
+    let mut i = 4 + vendor as usize;
+    let Some(n) = le32(b, i) else { return };
+    i += 4;
+    for _ in 0..n {
+        let Some(len) = le32(b, i).map(|l| l as usize) else { return };
+        let Some(c) = b.get(i + 4..i + 4 + len) else { return };
+        i += 4 + len;
+        let c = String::from_utf8_lossy(c);
+        let Some((key, value)) = c.split_once('=') else { continue };
+        let slot = match key.to_ascii_uppercase().as_str() {
+            "TITLE" => &mut m.title,
+            "ARTIST" => &mut m.artist,
+            "ALBUM" => &mut m.album,
+            _ => continue,
+        };
+        if slot.is_none() { *slot = clean(value); }
+    }
+}
+
+/// The first two packets of an Ogg Vorbis or Opus stream for the sample rate and tags, and
+/// the granule position of the last page for the duration.
+fn ogg(b: &[u8], m: &mut MediaInfo) {
+    let mut packets: Vec<Vec<u8>> = vec![Vec::new()];
+    let mut i = 0;
+    while packets.len() <= 2 && b.get(i..i + 4) == Some(b"OggS") {
+        let Some(&nsegs) = b.get(i + 26) else { return };
+        let Some(lacing) = b.get(i + 27..i + 27 + nsegs as usize) else { return };
+        let mut at = i + 27 + nsegs as usize;
+        for &l in lacing {
+            let Some(seg) = b.get(at..at + l as usize) else { return };
+            packets.last_mut().unwrap().extend_from_slice(seg);
+            at += l as usize;
+            if l < 255 { packets.push(Vec::new()); }
+        }
+        i = at;
+    }
+    let (id, tags) = (&packets[0], packets.get(1).map_or(&[][..], |p| p.as_slice()));
+    let (rate, skip) = if id.starts_with(b"\x01vorbis") {
+        if let Some(c) = tags.strip_prefix(b"\x03vorbis") { vorbis_comments(c, m); }
+        (le32(id, 12).unwrap_or(0) as u64, 0)
+    } else if id.starts_with(b"OpusHead") {
+        if let Some(c) = tags.strip_prefix(b"OpusTags") { vorbis_comments(c, m); }
+        // Opus always counts granules at 48kHz, after a pre-skip
+        (48000, id.get(10..12).map_or(0, |s| u16::from_le_bytes([s[0], s[1]]) as u64))
+    } else {
+        return;
+    };
+    let last = memchr::memmem::rfind(b, b"OggS");
+    let granule = last.and_then(|at| le64(b, at + 6)).filter(|&g| g != u64::MAX);
+    if let (Some(g), true) = (granule, rate > 0) { m.duration = Some(g.saturating_sub(skip) / rate); }
+}
+
+// --- MP4 and QuickTime ---
+
+/// Children of an MP4 box body as (type, body) pairs.
+fn boxes(b: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while let (Some(size), Some(kind)) = (be32(b, i), b.get(i + 4..i + 8)) {
+        let (head, size) = match size {
+            0 => (8, b.len() - i),
+            1 => match be64(b, i + 8).and_then(|s| usize::try_from(s).ok()) { Some(s) => (16, s), None => break },
+            s => (8, s as usize),
+        };
+        // sizes come from the file: a huge one must not wrap around
+        let Some(end) = i.checked_add(size).filter(|_| size >= head) else { break };
+        let Some(body) = b.get(i + head..end) else {
+            // a truncated last box still gives what is there
+            out.push((kind, &b[(i + head).min(b.len())..]));
+            break;
+        };
+        out.push((kind, body));
+        i = end;
+    }
+    out
+}
+
+fn child<'a>(b: &'a [u8], kind: &[u8]) -> Option<&'a [u8]> {
+    boxes(b).into_iter().find(|(k, _)| *k == kind).map(|(_, body)| body)
+}
+
+/// Duration and creation time from `mvhd`, frame size from the track headers, and iTunes-style
+/// tags from `moov/udta/meta/ilst`.
+fn mp4(b: &[u8], m: &mut MediaInfo) {
+    let Some(moov) = child(b, b"moov") else { return };
+    if let Some(mvhd) = child(moov, b"mvhd") {
+        let (created, scale, duration) = if mvhd.first() == Some(&1) {
+            (be64(mvhd, 4), be32(mvhd, 20), be64(mvhd, 24))
+        } else {
+            (be32(mvhd, 4).map(u64::from), be32(mvhd, 12), be32(mvhd, 16).map(u64::from))
+        };
+        if let (Some(s), Some(d)) = (scale.filter(|&s| s > 0), duration) { m.duration = Some(d / s as u64); }
+        // seconds since 1904
+        let unix = created.and_then(|c| c.checked_sub(2_082_844_800)).filter(|&c| c > 0);
+        m.taken = unix.and_then(|c| chrono::DateTime::from_timestamp(c as i64, 0)).map(|t| t.format("%Y-%m-%dT%H:%M:%S").to_string());
+    }
+    for (kind, trak) in boxes(moov) {
+        if kind != b"trak" { continue; }
+        // width and height are the last two 16.16 fixed point fields of `tkhd`
+        let Some(tkhd) = child(trak, b"tkhd").filter(|t| t.len() >= 8) else { continue };
+        let (w, h) = (be32(tkhd, tkhd.len() - 8).unwrap_or(0) >> 16, be32(tkhd, tkhd.len() - 4).unwrap_or(0) >> 16);
+        if w > 0 && h > 0 && m.width.is_none() { (m.width, m.height) = (Some(w as u64), Some(h as u64)); }
+    }
+    let Some(meta) = child(moov, b"udta").and_then(|u| child(u, b"meta")) else { return };
+    // `meta` is a full box in MP4 but not always in QuickTime files
+    let meta = if meta.get(4..8) == Some(b"hdlr") { meta } else { meta.get(4..).unwrap_or_default() };
+    let Some(ilst) = child(meta, b"ilst") else { return };
+    for (kind, item) in boxes(ilst) {
+        let slot = match kind {
+            b"\xa9nam" => &mut m.title,
+            b"\xa9ART" => &mut m.artist,
+            b"\xa9alb" => &mut m.album,
+            _ => continue,
+        };
+        // a `data` box: 4 bytes of type, 4 of locale, then the UTF-8 value
+        if let Some(data) = child(item, b"data").and_then(|d| d.get(8..)) { *slot = clean(&String::from_utf8_lossy(data)); }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(bytes: &[u8]) -> MediaInfo {
+        extract_media(bytes).and_then(|x| x.media).expect("media info")
+    }
+
+    // --- EXIF, JPEG and PNG ---
+
+    enum Val<'a> { Text(&'a str), Long(u32) }
+
+    /// Append a big-endian IFD with its out-of-line values right after it; returns its offset.
+    fn write_ifd(t: &mut Vec<u8>, entries: &[(u16, Val)]) -> u32 {
+        let at = t.len();
+        let values = at + 2 + entries.len() * 12 + 4;
+        let mut extra = Vec::new();
+        t.extend((entries.len() as u16).to_be_bytes());
+        for (tag, v) in entries {
+            t.extend(tag.to_be_bytes());
+            match v {
+                Val::Text(s) => {
+                    let mut bytes = s.as_bytes().to_vec();
+                    bytes.push(0);
+                    t.extend(2u16.to_be_bytes());
+                    t.extend((bytes.len() as u32).to_be_bytes());
+                    if bytes.len() <= 4 {
+                        bytes.resize(4, 0);
+                        t.extend(bytes);
+                    } else {
+                        t.extend(((values + extra.len()) as u32).to_be_bytes());
+                        extra.extend(bytes);
+                    }
+                }
+                Val::Long(n) => {
+                    t.extend(4u16.to_be_bytes());
+                    t.extend(1u32.to_be_bytes());
+                    t.extend(n.to_be_bytes());
+                }
+            }
+        }
+        t.extend(0u32.to_be_bytes());
+        t.extend(extra);
+        at as u32
+    }
+
+    fn tiff() -> Vec<u8> {
+        let mut t = b"MM\0*\0\0\0\0".to_vec();
+        let sub = write_ifd(&mut t, &[(0x9003, Val::Text("2024:07:15 10:30:00")), (0xa434, Val::Text("RF 24-105mm")), (0xa002, Val::Long(8192)), (0xa003, Val::Long(5464))]);
+        let gps = write_ifd(&mut t, &[(0x0002, Val::Long(52))]);
+        let main = write_ifd(&mut t, &[(0x010f, Val::Text("Canon")), (0x0110, Val::Text("Canon EOS R5")), (0x0132, Val::Text("2024:07:16 08:00:00")), (0x8769, Val::Long(sub)), (0x8825, Val::Long(gps))]);
+        t[4..8].copy_from_slice(&main.to_be_bytes());
+        t
+    }
+
+    fn jpeg_with(exif: &[u8]) -> Vec<u8> {
+        let mut j = vec![0xff, 0xd8, 0xff, 0xe1];
+        j.extend(((2 + 6 + exif.len()) as u16).to_be_bytes());
+        j.extend(b"Exif\0\0");
+        j.extend(exif);
+        // baseline frame header: precision, height 480, width 640, three components
+        j.extend([0xff, 0xc0, 0, 17, 8, 0x01, 0xe0, 0x02, 0x80, 3, 1, 0x22, 0, 2, 0x11, 1, 3, 0x11, 1]);
+        j.extend([0xff, 0xda, 0, 2]);
+        j
+    }
+
+    #[test]
+    fn jpeg_exif_and_frame_size() {
+        let m = info(&jpeg_with(&tiff()));
+        assert_eq!(m.camera.as_deref(), Some("Canon EOS R5"));
+        assert_eq!(m.lens.as_deref(), Some("RF 24-105mm"));
+        // the original capture time wins over the file's modification time
+        assert_eq!(m.taken.as_deref(), Some("2024-07-15T10:30:00"));
+        assert!(m.gps);
+        assert_eq!((m.width, m.height), (Some(640), Some(480)));
+    }
+
+    #[test]
+    fn bare_tiff_takes_its_size_from_exif() {
+        let m = info(&tiff());
+        assert_eq!((m.width, m.height), (Some(8192), Some(5464)));
+    }
+
+    #[test]
+    fn truncated_and_malformed_exif() {
+        let t = tiff();
+        // cut inside the main IFD: the header is fine but nothing after it is readable
+        assert!(extract_media(&jpeg_with(&t[..t.len() - 40])).is_none_or(|x| x.media.unwrap().camera.is_none()));
+        // an IFD offset pointing past the end leaves only the frame size
+        let m = info(&jpeg_with(b"MM\0*\xff\xff\xff\xff"));
+        assert_eq!((m.camera, m.width), (None, Some(640)));
+        // a segment length running past the end of the file
+        assert!(extract_media(&[0xff, 0xd8, 0xff, 0xe1, 0xff, 0xff, b'E']).is_none());
+        assert_eq!(exif_date("0000:00:00 00:00:00"), None);
+        assert_eq!(exif_date("    :  :     :  :  "), None);
+        assert_eq!(exif_date("2024:01:02").as_deref(), Some("2024-01-02"));
+    }
+
+    fn png(w: u32, h: u32) -> Vec<u8> {
+        let mut p = b"\x89PNG\r\n\x1a\n".to_vec();
+        p.extend(13u32.to_be_bytes());
+        p.extend(b"IHDR");
+        p.extend(w.to_be_bytes());
+        p.extend(h.to_be_bytes());
+        p.extend([8, 6, 0, 0, 0, 0, 0, 0, 0]);
+        p.extend(0u32.to_be_bytes());
+        p.extend(b"IEND");
+        p.extend([0; 4]);
+        p
+    }
+
+    #[test]
+    fn png_dimensions() {
+        let x = extract_media(&png(1920, 1080)).unwrap();
+        assert_eq!(x.text, "Dimensions: 1920x1080\n");
+        // cut inside IHDR
+        assert!(extract_media(&png(1920, 1080)[..20]).is_none());
+    }
+
+    // --- MP3 ---
+
+    /// MPEG-1 layer III, 128 kbit/s, 44.1 kHz, stereo.
+    const FRAME: [u8; 4] = [0xff, 0xfb, 0x90, 0x00];
+
+    fn id3v23(frames: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let body: Vec<u8> = frames.iter().flat_map(|(id, data)| {
+            let mut f = id.to_vec();
+            f.extend((data.len() as u32).to_be_bytes());
+            f.extend([0, 0]);
+            f.extend(*data);
+            f
+        }).collect();
+        let n = body.len() as u32;
+        let mut t = b"ID3\x03\0\0".to_vec();
+        t.extend([(n >> 21) as u8 & 0x7f, (n >> 14) as u8 & 0x7f, (n >> 7) as u8 & 0x7f, n as u8 & 0x7f]);
+        t.extend(body);
+        t
+    }
+
+    #[test]
+    fn id3v2_text_frames_and_length() {
+        let utf16: Vec<u8> = [1u8, 0xff, 0xfe].into_iter().chain("Björk".encode_utf16().flat_map(u16::to_le_bytes)).collect();
+        let mut b = id3v23(&[(b"TIT2", b"\0J\xF3ga\0"), (b"TPE1", &utf16), (b"TALB", b"\x03Homogenic"), (b"TLEN", b"\x00305000")]);
+        b.extend(FRAME);
+        let m = info(&b);
+        assert_eq!(m.title.as_deref(), Some("J\u{f3}ga"));
+        assert_eq!(m.artist.as_deref(), Some("Björk"));
+        assert_eq!(m.album.as_deref(), Some("Homogenic"));
+        assert_eq!(m.duration, Some(305));
+    }
+
+    #[test]
+    fn id3v1_fallback_and_duration_from_bitrate() {
+        let mut b = FRAME.to_vec();
+        b.resize(16_000, 0);
+        let mut v1 = b"TAG".to_vec();
+        for field in ["Old Song", "Old Band", "Old Album"] {
+            let mut f = field.as_bytes().to_vec();
+            f.resize(30, 0);
+            v1.extend(f);
+        }
+        v1.resize(128, 0);
+        b.extend(v1);
+        let m = info(&b);
+        assert_eq!((m.title.as_deref(), m.artist.as_deref(), m.album.as_deref()), (Some("Old Song"), Some("Old Band"), Some("Old Album")));
+        // 16000 bytes at 128 kbit/s
+        assert_eq!(m.duration, Some(1));
+    }
+
+    #[test]
+    fn xing_header_frame_count() {
+        let mut b = FRAME.to_vec();
+        b.resize(4 + 32, 0);
+        b.extend(b"Xing");
+        b.extend(1u32.to_be_bytes());
+        b.extend(383u32.to_be_bytes());
+        // 383 frames of 1152 samples at 44.1 kHz
+        assert_eq!(info(&b).duration, Some(10));
+    }
+
+    #[test]
+    fn malformed_mp3() {
+        // free-format bitrate can't give a duration, and a lone frame has no tags
+        assert!(extract_media(&[0xff, 0xfb, 0x00, 0x00]).is_none());
+        assert_eq!(mp3_duration(&[0xff, 0xfb, 0x00, 0x00, 0, 0], 0), None);
+        // a tag size claiming more than the file has
+        let mut b = b"ID3\x03\0\0\x7f\x7f\x7f\x7fTIT2".to_vec();
+        b.extend([0, 0, 0, 5, 0, 0, 0, b'x']);
+        assert!(extract_media(&b).is_none_or(|x| x.media.unwrap().title.is_none()));
+        assert!(extract_media(b"ID3").is_none());
+        assert_eq!(syncsafe(&[0x7f, 0x7f, 0x7f, 0x7f]), (1 << 28) - 1);
+    }
+
+    // --- FLAC and Ogg ---
+
+    fn comments(tags: &[&str]) -> Vec<u8> {
+        let mut c = 4u32.to_le_bytes().to_vec();
+        c.extend(b"test");
+        c.extend((tags.len() as u32).to_le_bytes());
+        for t in tags {
+            c.extend((t.len() as u32).to_le_bytes());
+            c.extend(t.as_bytes());
+        }
+        c
+    }
+
+    fn flac() -> Vec<u8> {
+        let mut b = b"fLaC".to_vec();
+        b.extend([0, 0, 0, 34]);
+        let mut info = [0u8; 34];
+        // 44.1 kHz, stereo, 16 bits, three seconds of samples
+        let packed: u64 = (44_100 << 44) | (1 << 41) | (15 << 36) | (3 * 44_100);
+        info[10..18].copy_from_slice(&packed.to_be_bytes());
+        b.extend(info);
+        let c = comments(&["TITLE=Tune", "artist=Band", "junk"]);
+        b.push(0x84);
+        b.extend(&(c.len() as u32).to_be_bytes()[1..]);
+        b.extend(c);
+        b
+    }
+
+    #[test]
+    fn flac_streaminfo_and_comments() {
+        let m = info(&flac());
+        assert_eq!(m.duration, Some(3));
+        assert_eq!((m.title.as_deref(), m.artist.as_deref()), (Some("Tune"), Some("Band")));
+        // cut inside the comment block: the stream info before it still counts
+        let b = flac();
+        let m = info(&b[..b.len() - 5]);
+        assert_eq!((m.duration, m.title), (Some(3), None));
+    }
+
+    fn ogg_page(granule: u64, packets: &[&[u8]]) -> Vec<u8> {
+        let mut p = b"OggS\0\0".to_vec();
+        p.extend(granule.to_le_bytes());
+        p.extend([0; 12]);
+        let lacing: Vec<u8> = packets.iter().flat_map(|d| {
+            let mut l = vec![255; d.len() / 255];
+            l.push((d.len() % 255) as u8);
+            l
+        }).collect();
+        p.push(lacing.len() as u8);
+        p.extend(lacing);
+        for d in packets { p.extend(*d); }
+        p
+    }
+
+    #[test]
+    fn ogg_vorbis_and_opus() {
+        let mut id = b"\x01vorbis\0\0\0\0\x02".to_vec();
+        id.extend(44_100u32.to_le_bytes());
+        let mut tags = b"\x03vorbis".to_vec();
+        tags.extend(comments(&["ALBUM=Long Player"]));
+        let mut b = ogg_page(0, &[&id]);
+        b.extend(ogg_page(0, &[&tags]));
+        b.extend(ogg_page(5 * 44_100, &[b"audio"]));
+        let m = info(&b);
+        assert_eq!((m.album.as_deref(), m.duration), (Some("Long Player"), Some(5)));
+
+        let mut head = b"OpusHead\x01\x02".to_vec();
+        head.extend(312u16.to_le_bytes());
+        let mut tags = b"OpusTags".to_vec();
+        tags.extend(comments(&["TITLE=Voice"]));
+        let mut b = ogg_page(0, &[&head, &tags]);
+        b.extend(ogg_page(7 * 48_000 + 312, &[b"audio"]));
+        let m = info(&b);
+        assert_eq!((m.title.as_deref(), m.duration), (Some("Voice"), Some(7)));
+    }
+
+    #[test]
+    fn truncated_ogg() {
+        let b = ogg_page(0, &[b"\x01vorbis"]);
+        assert!(extract_media(&b[..20]).is_none());
+        assert!(extract_media(b"OggS").is_none());
+    }
+
+    // --- MP4 ---
+
+    fn mp4_box(kind: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut b = ((8 + body.len()) as u32).to_be_bytes().to_vec();
+        b.extend(kind);
+        b.extend(body);
+        b
+    }
+
+    fn mp4() -> Vec<u8> {
+        let mut mvhd = vec![0; 4];
+        // created 2024-01-01T00:00:00 in seconds since 1904, then modified, time scale and duration
+        mvhd.extend((1_704_067_200u32 + 2_082_844_800).to_be_bytes());
+        mvhd.extend([0; 4]);
+        mvhd.extend(600u32.to_be_bytes());
+        mvhd.extend((600 * 93u32).to_be_bytes());
+        let mut tkhd = vec![0; 76];
+        tkhd.extend((1280u32 << 16).to_be_bytes());
+        tkhd.extend((720u32 << 16).to_be_bytes());
+        let data = mp4_box(b"data", b"\0\0\0\x01\0\0\0\0Clip title");
+        let ilst = mp4_box(b"ilst", &mp4_box(b"\xa9nam", &data));
+        let meta = mp4_box(b"meta", &[vec![0; 4], mp4_box(b"hdlr", &[0; 25]), ilst].concat());
+        let moov = mp4_box(b"moov", &[mp4_box(b"mvhd", &mvhd), mp4_box(b"trak", &mp4_box(b"tkhd", &tkhd)), mp4_box(b"udta", &meta)].concat());
+        [mp4_box(b"ftyp", b"isom\0\0\0\0"), moov].concat()
+    }
+
+    #[test]
+    fn mp4_movie_header_track_size_and_tags() {
+        let m = info(&mp4());
+        assert_eq!(m.duration, Some(93));
+        assert_eq!(m.taken.as_deref(), Some("2024-01-01T00:00:00"));
+        assert_eq!((m.width, m.height), (Some(1280), Some(720)));
+        assert_eq!(m.title.as_deref(), Some("Clip title"));
+    }
+
+    #[test]
+    fn crafted_box_sizes() {
+        // 64-bit size near the top of the range, a size smaller than its header, and a size past the end
+        let mut huge = mp4_box(b"free", b"");
+        huge.extend(1u32.to_be_bytes());
+        huge.extend(b"moov");
+        huge.extend(u64::MAX.to_be_bytes());
+        assert_eq!(boxes(&huge), [(&b"free"[..], &b""[..])]);
+        assert_eq!(boxes(b"\0\0\0\x04free\0\0\0\0").len(), 0);
+        let past = [&0xffff_fff0u32.to_be_bytes()[..], b"moov", b"body"].concat();
+        assert_eq!(boxes(&past), [(&b"moov"[..], &b"body"[..])]);
+        // size 0 runs to the end of the file
+        assert_eq!(boxes(b"\0\0\0\0mdatxyz"), [(&b"mdat"[..], &b"xyz"[..])]);
+        let full = mp4();
+        for cut in [12, 40, full.len() / 2, full.len() - 3] {
+            let _ = extract_media(&full[..cut]);
+        }
+    }
+}
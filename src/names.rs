@@ -0,0 +1,224 @@
+use anyhow::{anyhow, Context, Result};
+use fst::{Automaton, IntoStreamer, Map, MapBuilder, Streamer, automaton::{Levenshtein, Str}};
+use std::{collections::BTreeMap, fs, path::Path};
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::archive::ARCHIVE_SEP;
+use crate::manifest::Manifest;
+
+const NAMES_FST: &str = "names.fst";
+const NAMES_POSTINGS: &str = "names.postings";
+/// Every file the filename index keeps next to the main index.
+pub(crate) const NAME_FILES: &[&str] = &[NAMES_FST, NAMES_POSTINGS];
+
+/// One file carrying a given name. `id` is a stable document id derived from the path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamePosting {
+    pub id: u64,
+    pub path: String,
+}
+
+/// Filename lookup: an `fst::Map` from normalized filename to an offset into a postings
+/// side-file listing every path that has that name.
+pub struct NameIndex {
+    map: Map<Vec<u8>>,
+    postings: Vec<u8>,
+}
+
+/// Key form used in the map; lookups must normalize their input the same way.
+pub fn normalize(name: &str) -> String {
+    name.to_lowercase()
+}
+
+pub fn doc_id(path: &str) -> u64 {
+    xxh3_64(path.as_bytes())
+}
+
+/// Rebuild the filename map and postings from the paths tracked in the manifest.
+pub fn write_names(index_dir: &Path, manifest: &Manifest) -> Result<()> {
+    let mut grouped: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (path, entry) in &manifest.files {
+        // archive members are listed under their virtual `archive!/member` paths
+        let members = entry.members.iter().map(|m| format!("{}{}{}", path, ARCHIVE_SEP, m));
+        for p in std::iter::once(path.clone()).chain(members) {
+            if let Some(name) = Path::new(&p).file_name() {
+                grouped.entry(normalize(&name.to_string_lossy())).or_default().push(p);
+            }
+        }
+    }
+    // postings layout: u64 xxh3 of the fst they belong to, then per name: u32 count, then per
+    // path: u64 id, u32 len, utf-8 bytes (all LE)
+    let mut postings = vec![0; 8];
+    let mut builder = MapBuilder::memory();
+    for (name, paths) in &grouped {
+        builder.insert(name, postings.len() as u64).context("build names fst")?;
+        postings.extend_from_slice(&(paths.len() as u32).to_le_bytes());
+        for p in paths {
+            postings.extend_from_slice(&doc_id(p).to_le_bytes());
+            postings.extend_from_slice(&(p.len() as u32).to_le_bytes());
+            postings.extend_from_slice(p.as_bytes());
+        }
+    }
+    let fst = builder.into_inner()?;
+    postings[..8].copy_from_slice(&xxh3_64(&fst).to_le_bytes());
+    // each file is replaced whole; a reader catching one new and one old file sees the hash differ
+    write_atomic(&index_dir.join(NAMES_POSTINGS), &postings)?;
+    write_atomic(&index_dir.join(NAMES_FST), &fst)?;
+    Ok(())
+}
+
+fn write_atomic(path: &Path, bytes: &[u8]) -> Result<()> {
+    let tmp = path.with_extension(format!("{}.tmp", path.extension().unwrap_or_default().to_string_lossy()));
+    fs::write(&tmp, bytes)?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
+
+/// Smallest posting: a u64 id and a u32 length with an empty path.
+const MIN_POSTING: usize = 12;
+
+impl NameIndex {
+    pub fn open(index_dir: &Path) -> Result<NameIndex> {
+        let fst_path = index_dir.join(NAMES_FST);
+        if !fst_path.exists() {
+            return Err(anyhow!("no filename index at {}; run `warpseek index` first", index_dir.display()));
+        }
+        // `write_names` may be swapping the files right now; give it a moment to finish
+        for attempt in 0..3 {
+            if attempt > 0 { std::thread::sleep(std::time::Duration::from_millis(50)); }
+            let fst = fs::read(&fst_path).context("read names fst")?;
+            let postings = fs::read(index_dir.join(NAMES_POSTINGS)).context("read names postings")?;
+            if postings.get(..8) == Some(&xxh3_64(&fst).to_le_bytes()[..]) {
+                return Ok(NameIndex { map: Map::new(fst).context("read names fst")?, postings });
+            }
+        }
+        Err(anyhow!("filename index at {} is inconsistent; run `warpseek index`", index_dir.display()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Names starting with `prefix`, in key order.
+    pub fn prefix(&self, prefix: &str, limit: usize) -> Vec<(String, Vec<NamePosting>)> {
+        let key = normalize(prefix);
+        self.search(Str::new(&key).starts_with(), limit)
+    }
+
+    /// Names within `distance` edits of some prefix of theirs, i.e. typo-tolerant prefix search.
+    pub fn fuzzy_prefix(&self, prefix: &str, distance: u32, limit: usize) -> Result<Vec<(String, Vec<NamePosting>)>> {
+        let lev = Levenshtein::new(&normalize(prefix), distance).map_err(|e| anyhow!("fuzzy name lookup: {}", e))?;
+        Ok(self.search(lev.starts_with(), limit))
+    }
+
+    /// Visit every name with its postings, in key order.
+    pub fn for_each(&self, mut f: impl FnMut(&str, Vec<NamePosting>)) {
+        let mut stream = self.map.stream();
+        while let Some((k, off)) = stream.next() {
+            f(&String::from_utf8_lossy(k), self.postings_at(off));
+        }
+    }
+
+    fn search<A: Automaton>(&self, aut: A, limit: usize) -> Vec<(String, Vec<NamePosting>)> {
+        let mut out = Vec::new();
+        let mut stream = self.map.search(aut).into_stream();
+        while let Some((k, off)) = stream.next() {
+            if out.len() >= limit { break; }
+            out.push((String::from_utf8_lossy(k).to_string(), self.postings_at(off)));
+        }
+        out
+    }
+
+    fn postings_at(&self, off: u64) -> Vec<NamePosting> {
+        let Ok(mut cur) = usize::try_from(off) else { return Vec::new() };
+        let Some(n) = self.read(&mut cur, 4).map(|b| u32::from_le_bytes(b.try_into().unwrap())) else { return Vec::new() };
+        // the count comes from the file; never trust it for more than the bytes left could hold
+        let mut v = Vec::with_capacity((n as usize).min((self.postings.len() - cur) / MIN_POSTING));
+        for _ in 0..n {
+            let Some(id) = self.read(&mut cur, 8).map(|b| u64::from_le_bytes(b.try_into().unwrap())) else { break };
+            let Some(len) = self.read(&mut cur, 4).map(|b| u32::from_le_bytes(b.try_into().unwrap())) else { break };
+            let Some(bytes) = self.read(&mut cur, len as usize) else { break };
+            v.push(NamePosting { id, path: String::from_utf8_lossy(bytes).to_string() });
+        }
+        v
+    }
+
+    /// The next `len` bytes at `cur`, advancing it, or `None` past the end of the postings.
+    fn read(&self, cur: &mut usize, len: usize) -> Option<&[u8]> {
+        let end = cur.checked_add(len)?;
+        let b = self.postings.get(*cur..end)?;
+        *cur = end;
+        Some(b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::FileEntry;
+
+    fn manifest(files: &[(&str, &[&str])]) -> Manifest {
+        let entry = |members: &[&str]| FileEntry { size: 0, mtime: 0, hash: 0, members: members.iter().map(|m| m.to_string()).collect(), skipped: None, trigrams: false, store_text: false, keep_case: false };
+        Manifest { files: files.iter().map(|(p, members)| (p.to_string(), entry(members))).collect(), ..Manifest::default() }
+    }
+
+    fn written(files: &[(&str, &[&str])]) -> (tempfile::TempDir, NameIndex) {
+        let dir = tempfile::tempdir().unwrap();
+        write_names(dir.path(), &manifest(files)).unwrap();
+        let names = NameIndex::open(dir.path()).unwrap();
+        (dir, names)
+    }
+
+    fn keys(found: Vec<(String, Vec<NamePosting>)>) -> Vec<String> {
+        found.into_iter().map(|(k, _)| k).collect()
+    }
+
+    #[test]
+    fn a_name_lists_every_path_that_has_it() {
+        let (_dir, names) = written(&[("/a/mod.rs", &[]), ("/b/mod.rs", &[]), ("/a/Main.rs", &[]), ("/a/README.md", &[]), ("/z.zip", &["docs/readme.txt"])]);
+        assert_eq!(names.len(), 5);
+        let found = names.prefix("MOD.RS", 10);
+        assert_eq!(keys(found.clone()), ["mod.rs"]);
+        let paths = ["/a/mod.rs", "/b/mod.rs"];
+        assert_eq!(found[0].1, paths.map(|p| NamePosting { id: doc_id(p), path: p.to_string() }));
+        assert_eq!(names.prefix("read", 10)[1].1[0].path, format!("/z.zip{}docs/readme.txt", ARCHIVE_SEP));
+    }
+
+    #[test]
+    fn prefixes_find_names_in_key_order_up_to_the_limit() {
+        let (_dir, names) = written(&[("/readme.md", &[]), ("/README.txt", &[]), ("/reader.rs", &[]), ("/main.rs", &[])]);
+        assert_eq!(keys(names.prefix("readme", 10)), ["readme.md", "readme.txt"]);
+        assert_eq!(keys(names.prefix("read", 2)), ["reader.rs", "readme.md"]);
+        assert!(names.prefix("x", 10).is_empty());
+    }
+
+    #[test]
+    fn typos_are_counted_in_edits() {
+        let (_dir, names) = written(&[("/readme.md", &[]), ("/main.rs", &[])]);
+        assert_eq!(keys(names.fuzzy_prefix("rexdme", 1, 10).unwrap()), ["readme.md"]);
+        assert!(names.fuzzy_prefix("rxxdme", 1, 10).unwrap().is_empty());
+        assert_eq!(keys(names.fuzzy_prefix("rxxdme", 2, 10).unwrap()), ["readme.md"]);
+        assert_eq!(keys(names.fuzzy_prefix("mian", 2, 10).unwrap()), ["main.rs"]);
+    }
+
+    #[test]
+    fn a_map_and_postings_from_different_writes_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(NameIndex::open(dir.path()).is_err());
+        write_names(dir.path(), &manifest(&[("/old.rs", &[])])).unwrap();
+        let old_fst = fs::read(dir.path().join(NAMES_FST)).unwrap();
+        write_names(dir.path(), &manifest(&[("/new.rs", &[]), ("/newer.rs", &[])])).unwrap();
+        assert_eq!(keys(NameIndex::open(dir.path()).unwrap().prefix("", 10)), ["new.rs", "newer.rs"]);
+        // nothing but the two files is left behind
+        let mut left: Vec<_> = fs::read_dir(dir.path()).unwrap().map(|e| e.unwrap().file_name()).collect();
+        left.sort();
+        assert_eq!(left, [NAMES_FST, NAMES_POSTINGS]);
+
+        fs::write(dir.path().join(NAMES_FST), old_fst).unwrap();
+        assert!(NameIndex::open(dir.path()).err().unwrap().to_string().contains("inconsistent"));
+    }
+}
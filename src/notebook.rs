@@ -0,0 +1,125 @@
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+use crate::encoding::decode_text;
+use crate::extract::Extracted;
+
+pub fn is_notebook(name: &str) -> bool {
+    name.to_ascii_lowercase().ends_with(".ipynb")
+}
+
+/// Markdown, code and raw cells as plain text, recording the line each cell starts on and its
+/// type. Outputs are dropped unless `keep_outputs` is set, and even then only their text/plain
+/// parts are kept; images and other binary payloads never are.
+pub fn extract_notebook(bytes: &[u8], keep_outputs: bool) -> Result<Extracted> {
+    let d = decode_text(bytes).ok_or_else(|| anyhow!("notebook is not text"))?;
+    let nb: Value = serde_json::from_str(&d.text)?;
+    // nbformat 4 has top-level cells, nbformat 3 nests them in worksheets
+    let cells: Vec<&Value> = match nb.get("cells") {
+        Some(Value::Array(cells)) => cells.iter().collect(),
+        _ => nb.get("worksheets").and_then(Value::as_array).into_iter().flatten()
+            .filter_map(|w| w.get("cells").and_then(Value::as_array))
+            .flatten()
+            .collect(),
+    };
+
+    let mut x = Extracted { encoding: d.encoding, ..Default::default() };
+    let mut line = 1;
+    for cell in cells {
+        let kind = match cell.get("cell_type").and_then(Value::as_str) {
+            Some("markdown") | Some("heading") => "markdown",
+            Some("code") => "code",
+            _ => "raw",
+        };
+        let mut text = joined(cell.get("source").or_else(|| cell.get("input")));
+        if keep_outputs {
+            for out in cell.get("outputs").and_then(Value::as_array).into_iter().flatten() {
+                let t = match out.get("output_type").and_then(Value::as_str) {
+                    Some("stream") => joined(out.get("text")),
+                    Some("error") => format!("{}: {}", str_of(out, "ename"), str_of(out, "evalue")),
+                    _ => joined(out.get("data").and_then(|d| d.get("text/plain")).or_else(|| out.get("text"))),
+                };
+                if !t.trim().is_empty() {
+                    if !text.is_empty() && !text.ends_with('\n') { text.push('\n'); }
+                    text.push_str(&t);
+                }
+            }
+        }
+        if x.title.is_none() && kind == "markdown" {
+            x.title = text.lines().find_map(|l| l.strip_prefix("# ")).map(|t| t.trim().to_string());
+        }
+        let text = text.trim_end();
+        x.cells.push((line, kind));
+        x.text.push_str(text);
+        x.text.push('\n');
+        line += text.lines().count().max(1);
+    }
+
+    let meta = nb.get("metadata");
+    if let Some(t) = meta.and_then(|m| m.get("title")).and_then(Value::as_str) { x.title = Some(t.to_string()); }
+    x.author = meta.and_then(|m| m.get("authors")).and_then(Value::as_array)
+        .and_then(|a| a.first())
+        .and_then(|a| a.get("name").or(Some(a)).and_then(Value::as_str))
+        .map(String::from);
+    Ok(x)
+}
+
+/// Notebook text is either one string or a list of lines.
+fn joined(v: Option<&Value>) -> String {
+    match v {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Array(lines)) => lines.iter().filter_map(Value::as_str).collect(),
+        _ => String::new(),
+    }
+}
+
+fn str_of<'a>(v: &'a Value, key: &str) -> &'a str {
+    v.get(key).and_then(Value::as_str).unwrap_or("")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTEBOOK: &str = r##"{
+        "metadata": {"authors": [{"name": "Grace"}]},
+        "cells": [
+            {"cell_type": "markdown", "source": ["# Analysis\n", "Loads the data.\n"]},
+            {"cell_type": "code", "source": "import pandas as pd\ndf = pd.read_csv('x.csv')\n", "outputs": [
+                {"output_type": "stream", "text": ["loaded 3 rows\n"]},
+                {"output_type": "display_data", "data": {"image/png": "iVBORw0KGgo=", "text/plain": ["<Figure>"]}},
+                {"output_type": "error", "ename": "KeyError", "evalue": "'y'"}
+            ]},
+            {"cell_type": "raw", "source": ""},
+            {"cell_type": "code", "source": ["df.head()"]}
+        ]
+    }"##;
+
+    #[test]
+    fn cells_start_lines_follow_the_text_before_them() {
+        let x = extract_notebook(NOTEBOOK.as_bytes(), false).unwrap();
+        assert_eq!(x.text, "# Analysis\nLoads the data.\nimport pandas as pd\ndf = pd.read_csv('x.csv')\n\ndf.head()\n");
+        assert_eq!(x.cells, [(1, "markdown"), (3, "code"), (5, "raw"), (6, "code")]);
+        assert_eq!((x.title.as_deref(), x.author.as_deref()), (Some("Analysis"), Some("Grace")));
+    }
+
+    #[test]
+    fn text_outputs_are_kept_on_request_and_images_never() {
+        let x = extract_notebook(NOTEBOOK.as_bytes(), true).unwrap();
+        assert_eq!(x.text, "# Analysis\nLoads the data.\nimport pandas as pd\ndf = pd.read_csv('x.csv')\nloaded 3 rows\n<Figure>\nKeyError: 'y'\n\ndf.head()\n");
+        assert_eq!(x.cells, [(1, "markdown"), (3, "code"), (8, "raw"), (9, "code")]);
+        assert!(!x.text.contains("iVBOR"));
+    }
+
+    #[test]
+    fn nbformat_3_worksheets_and_metadata_title() {
+        let nb = r#"{"metadata": {"title": "Old one", "authors": ["Alan"]}, "worksheets": [{"cells": [
+            {"cell_type": "heading", "source": "Intro"}, {"cell_type": "code", "input": ["x = 1\n", "y = 2"]}
+        ]}]}"#;
+        let x = extract_notebook(nb.as_bytes(), false).unwrap();
+        assert_eq!(x.text, "Intro\nx = 1\ny = 2\n");
+        assert_eq!(x.cells, [(1, "markdown"), (2, "code")]);
+        assert_eq!((x.title.as_deref(), x.author.as_deref()), (Some("Old one"), Some("Alan")));
+        assert!(extract_notebook(b"{not json", false).is_err());
+    }
+}
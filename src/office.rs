@@ -0,0 +1,345 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+
+use crate::extract::Extracted;
+use crate::xml::{attr, resolve, walk_xml, Tag};
+
+/// Office Open XML and OpenDocument files; registered as the `office` file type.
+pub const OFFICE_GLOBS: &[&str] = &["*.docx", "*.xlsx", "*.pptx", "*.odt", "*.ods"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OfficeKind { Docx, Xlsx, Pptx, Odt, Ods }
+
+pub fn office_kind(name: &str) -> Option<OfficeKind> {
+    let ext = name.rsplit_once('.')?.1.to_ascii_lowercase();
+    match ext.as_str() {
+        "docx" => Some(OfficeKind::Docx),
+        "xlsx" => Some(OfficeKind::Xlsx),
+        "pptx" => Some(OfficeKind::Pptx),
+        "odt" => Some(OfficeKind::Odt),
+        "ods" => Some(OfficeKind::Ods),
+        _ => None,
+    }
+}
+
+pub(crate) type Zip<'a> = zip::ZipArchive<Cursor<&'a [u8]>>;
+
+/// Pull the text and core properties out of an office document. `budget` caps how many
+/// uncompressed bytes are read from any one part of the package.
+pub fn extract_office(bytes: &[u8], kind: OfficeKind, budget: u64) -> Result<Extracted> {
+    let mut zip = zip::ZipArchive::new(Cursor::new(bytes))?;
+    let text = match kind {
+        OfficeKind::Docx => {
+            let mut text = String::new();
+            for name in ["word/document.xml", "word/footnotes.xml", "word/endnotes.xml"] {
+                if let Some(xml) = read_part(&mut zip, name, budget) { ooxml_text(&xml, b"p", &mut text); }
+            }
+            text
+        }
+        OfficeKind::Pptx => pptx_text(&mut zip, budget),
+        OfficeKind::Xlsx => xlsx_text(&mut zip, budget),
+        OfficeKind::Odt | OfficeKind::Ods => {
+            let xml = read_part(&mut zip, "content.xml", budget).ok_or_else(|| anyhow!("no content.xml"))?;
+            odf_text(&xml)
+        }
+    };
+    let meta = match kind {
+        OfficeKind::Odt | OfficeKind::Ods => read_part(&mut zip, "meta.xml", budget),
+        _ => read_part(&mut zip, "docProps/core.xml", budget),
+    };
+    let mut doc = Extracted { text, encoding: "UTF-8", ..Default::default() };
+    if let Some(xml) = meta { core_properties(&xml, &mut doc); }
+    Ok(doc)
+}
+
+pub(crate) fn read_part(zip: &mut Zip, name: &str, budget: u64) -> Option<Vec<u8>> {
+    let file = zip.by_name(name).ok()?;
+    let mut buf = Vec::new();
+    file.take(budget).read_to_end(&mut buf).ok()?;
+    Some(buf)
+}
+
+/// Text of the `t` runs in a WordprocessingML or DrawingML part, one line per `para` element.
+fn ooxml_text(xml: &[u8], para: &[u8], out: &mut String) {
+    let mut in_t = false;
+    walk_xml(xml, |tag| match tag {
+        Tag::Open(e) => match e.local_name().as_ref() {
+            b"t" => in_t = true,
+            b"tab" => out.push('\t'),
+            b"br" | b"cr" => out.push('\n'),
+            _ => {}
+        },
+        Tag::Close(b"t") => in_t = false,
+        Tag::Close(name) if name == para => out.push('\n'),
+        Tag::Text(s) if in_t => out.push_str(s),
+        _ => {}
+    });
+}
+
+/// Relationship ids to their targets, from a `.rels` part.
+fn relationships(xml: &[u8]) -> HashMap<String, (String, String)> {
+    let mut rels = HashMap::new();
+    walk_xml(xml, |tag| {
+        if let Tag::Open(e) = tag {
+            if e.local_name().as_ref() == b"Relationship" {
+                if let (Some(id), Some(target)) = (attr(e, b"Id"), attr(e, b"Target")) {
+                    rels.insert(id, (attr(e, b"Type").unwrap_or_default(), target));
+                }
+            }
+        }
+    });
+    rels
+}
+
+/// Part names like `ppt/slides/slide12.xml` under `dir`, in numeric order.
+fn numbered_parts(zip: &Zip, dir: &str, stem: &str) -> Vec<String> {
+    let mut parts: Vec<(u32, String)> = zip.file_names()
+        .filter_map(|n| {
+            let rest = n.strip_prefix(dir)?.strip_prefix(stem)?.strip_suffix(".xml")?;
+            Some((rest.parse().ok()?, n.to_string()))
+        })
+        .collect();
+    parts.sort();
+    parts.into_iter().map(|(_, n)| n).collect()
+}
+
+/// Slide text in slide order, each slide followed by its speaker notes.
+fn pptx_text(zip: &mut Zip, budget: u64) -> String {
+    let mut out = String::new();
+    for slide in numbered_parts(zip, "ppt/slides/", "slide") {
+        if let Some(xml) = read_part(zip, &slide, budget) { ooxml_text(&xml, b"p", &mut out); }
+        let rels_name = slide.replacen("ppt/slides/", "ppt/slides/_rels/", 1) + ".rels";
+        let notes = read_part(zip, &rels_name, budget)
+            .map(|xml| relationships(&xml))
+            .and_then(|rels| rels.into_values().find(|(ty, _)| ty.ends_with("/notesSlide")))
+            .map(|(_, target)| resolve("ppt/slides", &target));
+        if let Some(xml) = notes.and_then(|n| read_part(zip, &n, budget)) {
+            ooxml_text(&xml, b"p", &mut out);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Every sheet as its name on a line of its own, then one line per row with tab-separated cells.
+fn xlsx_text(zip: &mut Zip, budget: u64) -> String {
+    let mut shared = Vec::new();
+    if let Some(xml) = read_part(zip, "xl/sharedStrings.xml", budget) {
+        let (mut cur, mut in_t, mut skip) = (String::new(), false, 0);
+        walk_xml(&xml, |tag| match tag {
+            Tag::Open(e) => match e.local_name().as_ref() {
+                b"t" => in_t = true,
+                // phonetic guides repeat the string in another script
+                b"rPh" => skip += 1,
+                _ => {}
+            },
+            Tag::Close(b"t") => in_t = false,
+            Tag::Close(b"rPh") => skip -= 1,
+            Tag::Close(b"si") => shared.push(std::mem::take(&mut cur)),
+            Tag::Text(s) if in_t && skip == 0 => cur.push_str(s),
+            _ => {}
+        });
+    }
+
+    let rels = read_part(zip, "xl/_rels/workbook.xml.rels", budget).map(|x| relationships(&x)).unwrap_or_default();
+    let mut sheets = Vec::new();
+    if let Some(xml) = read_part(zip, "xl/workbook.xml", budget) {
+        walk_xml(&xml, |tag| {
+            if let Tag::Open(e) = tag {
+                if e.local_name().as_ref() == b"sheet" {
+                    let target = attr(e, b"id").and_then(|id| rels.get(&id)).map(|(_, t)| resolve("xl", t));
+                    sheets.push((attr(e, b"name").unwrap_or_default(), target));
+                }
+            }
+        });
+    }
+    if sheets.iter().all(|(_, t)| t.is_none()) {
+        sheets = numbered_parts(zip, "xl/worksheets/", "sheet").into_iter().map(|p| (String::new(), Some(p))).collect();
+    }
+
+    let mut out = String::new();
+    for (name, target) in sheets {
+        if !name.is_empty() { out.push_str(&name); out.push('\n'); }
+        let Some(xml) = target.and_then(|t| read_part(zip, &t, budget)) else { continue };
+        let (mut row, mut value, mut cell_type, mut in_value) = (Vec::new(), String::new(), String::new(), false);
+        walk_xml(&xml, |tag| match tag {
+            Tag::Open(e) => match e.local_name().as_ref() {
+                b"c" => { cell_type = attr(e, b"t").unwrap_or_default(); value.clear(); }
+                b"v" | b"t" => in_value = true,
+                _ => {}
+            },
+            Tag::Close(b"v") | Tag::Close(b"t") => in_value = false,
+            Tag::Close(b"c") => {
+                let v = std::mem::take(&mut value);
+                let text = if cell_type == "s" { v.trim().parse::<usize>().ok().and_then(|i| shared.get(i).cloned()).unwrap_or_default() } else { v };
+                if !text.is_empty() { row.push(text); }
+            }
+            Tag::Close(b"row") => {
+                if !row.is_empty() { out.push_str(&row.join("\t")); out.push('\n'); }
+                row.clear();
+            }
+            Tag::Text(s) if in_value => value.push_str(s),
+            _ => {}
+        });
+    }
+    out
+}
+
+/// Paragraphs and headings of an OpenDocument `content.xml`; spreadsheet tables come out as
+/// their name, then one line per row with tab-separated cells.
+fn odf_text(xml: &[u8]) -> String {
+    let (mut out, mut row, mut cell) = (String::new(), Vec::new(), String::new());
+    let (mut para, mut cells) = (0, 0);
+    // text inside a table cell collects in `cell` until the cell closes
+    fn sink<'a>(cells: i32, cell: &'a mut String, out: &'a mut String) -> &'a mut String {
+        if cells > 0 { cell } else { out }
+    }
+    walk_xml(xml, |tag| {
+        match tag {
+            Tag::Open(e) => match e.local_name().as_ref() {
+                b"p" | b"h" => para += 1,
+                b"s" => sink(cells, &mut cell, &mut out).push_str(&" ".repeat(attr(e, b"c").and_then(|c| c.parse().ok()).unwrap_or(1))),
+                b"tab" => sink(cells, &mut cell, &mut out).push('\t'),
+                b"line-break" => sink(cells, &mut cell, &mut out).push('\n'),
+                b"table" => if let Some(name) = attr(e, b"name") { out.push_str(&name); out.push('\n'); },
+                b"table-cell" | b"covered-table-cell" => cells += 1,
+                _ => {}
+            },
+            Tag::Close(b"p") | Tag::Close(b"h") => {
+                para -= 1;
+                sink(cells, &mut cell, &mut out).push(if cells > 0 { ' ' } else { '\n' });
+            }
+            Tag::Close(b"table-cell") | Tag::Close(b"covered-table-cell") => {
+                cells -= 1;
+                let c = std::mem::take(&mut cell);
+                if !c.trim().is_empty() { row.push(c.trim().to_string()); }
+            }
+            Tag::Close(b"table-row") => {
+                if !row.is_empty() { out.push_str(&row.join("\t")); out.push('\n'); }
+                row.clear();
+            }
+            Tag::Text(s) if para > 0 => sink(cells, &mut cell, &mut out).push_str(s),
+            _ => {}
+        }
+    });
+    out
+}
+
+/// Title and author from `docProps/core.xml` or an OpenDocument `meta.xml`.
+fn core_properties(xml: &[u8], doc: &mut Extracted) {
+    #[derive(Clone, Copy)]
+    enum Prop { Title, Creator, InitialCreator }
+    let (mut field, mut creator, mut initial) = (None, None, None);
+    walk_xml(xml, |tag| match tag {
+        Tag::Open(e) => field = match e.local_name().as_ref() {
+            b"title" => Some(Prop::Title),
+            b"creator" => Some(Prop::Creator),
+            b"initial-creator" => Some(Prop::InitialCreator),
+            _ => None,
+        },
+        Tag::Close(_) => field = None,
+        Tag::Text(s) if !s.trim().is_empty() => {
+            let v = Some(s.trim().to_string());
+            match field {
+                Some(Prop::Title) => doc.title = v,
+                Some(Prop::Creator) => creator = v,
+                Some(Prop::InitialCreator) => initial = v,
+                None => {}
+            }
+        }
+        _ => {}
+    });
+    // in OpenDocument `creator` is whoever saved last; the author is the initial creator
+    doc.author = initial.or(creator);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn zip_of(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut w = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in files {
+            w.start_file(*name, zip::write::SimpleFileOptions::default()).unwrap();
+            w.write_all(data.as_bytes()).unwrap();
+        }
+        w.finish().unwrap().into_inner()
+    }
+
+    fn extract(kind: OfficeKind, files: &[(&str, &str)]) -> Extracted {
+        extract_office(&zip_of(files), kind, 1 << 20).unwrap()
+    }
+
+    const W: &str = r#"xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main""#;
+    const A: &str = r#"xmlns:a="http://schemas.openxmlformats.org/drawingml/2006/main""#;
+
+    #[test]
+    fn docx_paragraphs_footnotes_and_core_properties() {
+        let x = extract(OfficeKind::Docx, &[
+            ("word/document.xml", &format!(r#"<w:document {W}><w:body><w:p><w:r><w:t>Hello</w:t><w:tab/><w:t xml:space="preserve">wide </w:t></w:r><w:r><w:t>world</w:t><w:br/><w:t>again</w:t></w:r></w:p><w:p><w:r><w:t>Fish &amp; chips</w:t></w:r></w:p><w:p><w:pPr><w:jc w:val="center"/></w:pPr></w:p></w:body></w:document>"#)),
+            ("word/footnotes.xml", &format!(r#"<w:footnotes {W}><w:footnote><w:p><w:r><w:t>A footnote</w:t></w:r></w:p></w:footnote></w:footnotes>"#)),
+            ("docProps/core.xml", r#"<cp:coreProperties xmlns:cp="c" xmlns:dc="http://purl.org/dc/elements/1.1/"><dc:title>Report</dc:title><dc:creator> Ada </dc:creator></cp:coreProperties>"#),
+        ]);
+        assert_eq!(x.text, "Hello\twide world\nagain\nFish & chips\n\nA footnote\n");
+        assert_eq!((x.title.as_deref(), x.author.as_deref()), (Some("Report"), Some("Ada")));
+    }
+
+    #[test]
+    fn xlsx_sheets_follow_the_workbook_and_resolve_shared_strings() {
+        let x = extract(OfficeKind::Xlsx, &[
+            ("xl/sharedStrings.xml", r#"<sst><si><t>Name</t></si><si><r><t>Ka</t></r><r><t>nji</t></r><rPh><t>かんじ</t></rPh></si></sst>"#),
+            ("xl/workbook.xml", r#"<workbook xmlns:r="r"><sheets><sheet name="Totals" r:id="rId2"/><sheet name="Data" r:id="rId1"/></sheets></workbook>"#),
+            ("xl/_rels/workbook.xml.rels", r#"<Relationships><Relationship Id="rId1" Type="t/worksheet" Target="worksheets/sheet1.xml"/><Relationship Id="rId2" Type="t/worksheet" Target="/xl/worksheets/sheet2.xml"/></Relationships>"#),
+            ("xl/worksheets/sheet1.xml", r#"<worksheet><sheetData><row><c t="s"><v>0</v></c><c><v>42</v></c></row><row><c t="s"><v>1</v></c><c t="inlineStr"><is><t>inline</t></is></c><c t="s"><v>9</v></c></row><row/></sheetData></worksheet>"#),
+            ("xl/worksheets/sheet2.xml", r#"<worksheet><sheetData><row><c t="str"><v>sum</v></c><c><v>3.5</v></c></row></sheetData></worksheet>"#),
+        ]);
+        assert_eq!(x.text, "Totals\nsum\t3.5\nData\nName\t42\nKanji\tinline\n");
+    }
+
+    #[test]
+    fn xlsx_without_a_workbook_reads_sheets_in_numeric_order() {
+        let sheet = |v: &str| format!(r#"<worksheet><sheetData><row><c><v>{v}</v></c></row></sheetData></worksheet>"#);
+        let x = extract(OfficeKind::Xlsx, &[("xl/worksheets/sheet10.xml", &sheet("ten")), ("xl/worksheets/sheet2.xml", &sheet("two"))]);
+        assert_eq!(x.text, "two\nten\n");
+    }
+
+    #[test]
+    fn pptx_slides_in_order_each_with_its_notes() {
+        let slide = |t: &str| format!(r#"<p:sld xmlns:p="p" {A}><p:txBody><a:p><a:r><a:t>{t}</a:t></a:r></a:p></p:txBody></p:sld>"#);
+        let x = extract(OfficeKind::Pptx, &[
+            ("ppt/slides/slide10.xml", &slide("Slide ten")),
+            ("ppt/slides/slide1.xml", &slide("Slide one")),
+            ("ppt/slides/_rels/slide1.xml.rels", r#"<Relationships><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/slideLayout" Target="../slideLayouts/slideLayout1.xml"/><Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/notesSlide" Target="../notesSlides/notesSlide7.xml"/></Relationships>"#),
+            ("ppt/notesSlides/notesSlide7.xml", &slide("Say hello")),
+        ]);
+        assert_eq!(x.text, "Slide one\nSay hello\n\nSlide ten\n\n");
+    }
+
+    #[test]
+    fn odt_paragraphs_spaces_and_initial_creator() {
+        let x = extract(OfficeKind::Odt, &[
+            ("content.xml", r#"<office:document-content xmlns:office="o" xmlns:text="t"><office:body><office:text><text:h>Intro</text:h><text:p>a<text:s text:c="3"/>b<text:tab/>c<text:line-break/>d<text:span> e</text:span></text:p><text:sequence-decls>ignored</text:sequence-decls></office:text></office:body></office:document-content>"#),
+            ("meta.xml", r#"<office:document-meta xmlns:office="o" xmlns:meta="m" xmlns:dc="d"><office:meta><dc:title>Notes</dc:title><dc:creator>Editor</dc:creator><meta:initial-creator>Author</meta:initial-creator></office:meta></office:document-meta>"#),
+        ]);
+        assert_eq!(x.text, "Intro\na   b\tc\nd e\n");
+        assert_eq!((x.title.as_deref(), x.author.as_deref()), (Some("Notes"), Some("Author")));
+    }
+
+    #[test]
+    fn ods_tables_come_out_as_rows_of_cells() {
+        let x = extract(OfficeKind::Ods, &[
+            ("content.xml", r#"<office:document-content xmlns:office="o" xmlns:table="t" xmlns:text="x"><office:body><office:spreadsheet><table:table table:name="Budget"><table:table-row><table:table-cell><text:p>Rent</text:p></table:table-cell><table:table-cell/><table:table-cell><text:p>12</text:p><text:p>00</text:p></table:table-cell></table:table-row><table:table-row><table:covered-table-cell><text:p>merged</text:p></table:covered-table-cell></table:table-row><table:table-row><table:table-cell/></table:table-row></table:table></office:spreadsheet></office:body></office:document-content>"#),
+        ]);
+        assert_eq!(x.text, "Budget\nRent\t12 00\nmerged\n");
+        assert!(x.title.is_none() && x.author.is_none());
+    }
+
+    #[test]
+    fn a_package_without_its_main_part() {
+        assert!(extract_office(&zip_of(&[("meta.xml", "<x/>")]), OfficeKind::Odt, 1 << 20).is_err());
+        assert_eq!(extract(OfficeKind::Docx, &[("docProps/core.xml", "<x/>")]).text, "");
+        assert!(extract_office(b"not a zip", OfficeKind::Docx, 1 << 20).is_err());
+    }
+}
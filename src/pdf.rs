@@ -0,0 +1,153 @@
+use pdf_extract::{output_doc_page, Document, Object, PlainTextOutput};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use crate::extract::Extracted;
+
+/// Reasons a PDF is indexed by name and metadata only, kept in the `skipped` field.
+pub const SKIP_ENCRYPTED: &str = "encrypted";
+pub const SKIP_IMAGE_ONLY: &str = "image-only";
+pub const SKIP_UNREADABLE: &str = "unreadable";
+
+pub fn is_pdf(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"%PDF-")
+}
+
+/// Page text, page boundaries, page count and document info. Never fails: a PDF whose text
+/// can't be had still yields whatever metadata is readable plus a `skipped` reason.
+pub fn extract_pdf(bytes: &[u8]) -> Extracted {
+    let mut x = Extracted { encoding: "UTF-8", ..Default::default() };
+    // the parser and font code panic on some malformed files
+    let doc = match catch_unwind(|| Document::load_mem(bytes)) {
+        Ok(Ok(doc)) => doc,
+        _ => {
+            x.skipped = Some(SKIP_UNREADABLE.to_string());
+            return x;
+        }
+    };
+    let pages = doc.get_pages();
+    // an encrypted document's page tree may not be readable either
+    x.page_count = Some(pages.len() as u64).filter(|&n| n > 0);
+    // documents with an empty user password are decrypted on load, anything else stays locked
+    if doc.is_encrypted() && doc.authenticate_password("").is_err() {
+        x.skipped = Some(SKIP_ENCRYPTED.to_string());
+        return x;
+    }
+    info(&doc, &mut x);
+
+    let mut line = 1;
+    for &n in pages.keys() {
+        let mut s = String::new();
+        let _ = catch_unwind(AssertUnwindSafe(|| output_doc_page(&doc, &mut PlainTextOutput::new(&mut s), n)));
+        let page = s.trim();
+        x.pages.push(line);
+        x.text.push_str(page);
+        x.text.push('\n');
+        line += page.lines().count().max(1);
+    }
+    if x.text.trim().is_empty() {
+        x.text.clear();
+        x.pages.clear();
+        x.skipped = Some(SKIP_IMAGE_ONLY.to_string());
+    }
+    x
+}
+
+/// Title and author from the document information dictionary.
+fn info(doc: &Document, x: &mut Extracted) {
+    let dict = match doc.trailer.get(b"Info") {
+        Ok(Object::Reference(id)) => doc.get_dictionary(*id).ok(),
+        Ok(Object::Dictionary(d)) => Some(d),
+        _ => None,
+    };
+    let Some(dict) = dict else { return };
+    let get = |key: &[u8]| dict.get(key).ok()
+        .and_then(|o| o.as_str().ok())
+        .map(decode_pdf_string)
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty());
+    x.title = get(b"Title");
+    x.author = get(b"Author");
+}
+
+/// PDF text strings are UTF-16BE with a BOM, UTF-8 with a BOM, or PDFDocEncoding, which
+/// agrees with Latin-1 for everything likely to turn up in a title.
+fn decode_pdf_string(b: &[u8]) -> String {
+    if let Some(rest) = b.strip_prefix(&[0xfe, 0xff]) {
+        let units: Vec<u16> = rest.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+        String::from_utf16_lossy(&units)
+    } else if let Some(rest) = b.strip_prefix(&[0xef, 0xbb, 0xbf]) {
+        String::from_utf8_lossy(rest).to_string()
+    } else {
+        b.iter().map(|&c| c as char).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pdf_extract::{content::{Content, Operation}, dictionary, Dictionary, Stream, StringFormat};
+
+    /// A PDF with one page per entry, each a list of lines set in a standard font.
+    fn pdf_of(pages: &[&[&str]], info: Option<Dictionary>) -> Vec<u8> {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let font = doc.add_object(dictionary! { "Type" => "Font", "Subtype" => "Type1", "BaseFont" => "Courier" });
+        let resources = doc.add_object(dictionary! { "Font" => dictionary! { "F1" => font } });
+        let kids: Vec<Object> = pages.iter().map(|lines| {
+            let mut operations = vec![Operation::new("BT", vec![]), Operation::new("Tf", vec!["F1".into(), 12.into()]), Operation::new("Td", vec![72.into(), 700.into()])];
+            for line in *lines {
+                operations.push(Operation::new("Tj", vec![Object::string_literal(*line)]));
+                operations.push(Operation::new("Td", vec![0.into(), (-14).into()]));
+            }
+            operations.push(Operation::new("ET", vec![]));
+            let content = doc.add_object(Stream::new(dictionary! {}, Content { operations }.encode().unwrap()));
+            doc.add_object(dictionary! { "Type" => "Page", "Parent" => pages_id, "Contents" => content }).into()
+        }).collect();
+        let count = kids.len() as i64;
+        doc.objects.insert(pages_id, Object::Dictionary(dictionary! {
+            "Type" => "Pages", "Kids" => kids, "Count" => count, "Resources" => resources,
+            "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+        }));
+        let catalog = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog);
+        if let Some(info) = info {
+            let id = doc.add_object(info);
+            doc.trailer.set("Info", id);
+        }
+        let mut out = Vec::new();
+        doc.save_to(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn pages_start_where_their_text_does() {
+        let bytes = pdf_of(&[&["first page"], &["second page", "more of it"], &["third"]], None);
+        assert!(is_pdf(&bytes));
+        let x = extract_pdf(&bytes);
+        let lines: Vec<&str> = x.text.lines().map(str::trim).collect();
+        assert_eq!(lines, ["first page", "second page", "more of it", "third"]);
+        assert_eq!(x.pages, [1, 2, 4]);
+        assert_eq!((x.page_count, x.skipped), (Some(3), None));
+    }
+
+    #[test]
+    fn title_and_author_in_any_text_string_encoding() {
+        let utf16: Vec<u8> = [0xfe, 0xff].into_iter().chain("Café ✓".encode_utf16().flat_map(u16::to_be_bytes)).collect();
+        let info = dictionary! {
+            "Title" => Object::String(utf16, StringFormat::Hexadecimal),
+            "Author" => Object::String(b"Ren\xe9 ".to_vec(), StringFormat::Literal),
+        };
+        let x = extract_pdf(&pdf_of(&[&["text"]], Some(info)));
+        assert_eq!((x.title.as_deref(), x.author.as_deref()), (Some("Café ✓"), Some("René")));
+        assert_eq!(decode_pdf_string(b"\xef\xbb\xbfna\xc3\xafve"), "naïve");
+    }
+
+    #[test]
+    fn pages_without_text_are_image_only_and_junk_is_unreadable() {
+        let x = extract_pdf(&pdf_of(&[&[], &[]], None));
+        assert_eq!((x.page_count, x.skipped.as_deref(), x.text.as_str()), (Some(2), Some(SKIP_IMAGE_ONLY), ""));
+        assert!(x.pages.is_empty());
+        let x = extract_pdf(b"%PDF-1.7\nnot really");
+        assert_eq!((x.page_count, x.skipped.as_deref()), (None, Some(SKIP_UNREADABLE)));
+    }
+}
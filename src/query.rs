@@ -0,0 +1,587 @@
+use anyhow::{anyhow, Result};
+use serde::{Serialize, Deserialize};
+use indicatif::{ProgressBar, ProgressStyle};
+use rayon::prelude::*;
+use regex::{Regex, RegexBuilder};
+use std::{collections::{HashMap, HashSet}, fmt, path::Path};
+use tantivy::{DocAddress, Index, IndexWriter, ReloadPolicy, Score, Searcher, TantivyDocument, Term, collector::{DocSetCollector, TopDocs}, directory::MmapDirectory, query::{AllQuery, BooleanQuery, Occur, Query, QueryParser, TermQuery}, schema::{Field, IndexRecordOption, Value}, snippet::SnippetGenerator, tokenizer::TokenStream};
+
+use crate::archive::read_path;
+use crate::extract::Registry;
+use crate::filters::{parse_filters, Filtered};
+use crate::literals::{gram_query, literal_query, mentions_uppercase, required_literals};
+use crate::roots::RootConfig;
+use crate::search::{build_schema, check_version, with_tokenizers, Config, Fields};
+use crate::workspace::Workspace;
+
+#[derive(Clone, Copy, Debug)]
+pub struct QueryOptions {
+    /// maximum number of hits to return
+    pub top: usize,
+    /// match against file names only, not content
+    pub names_only: bool,
+    /// highlighted content fragments to attach to each hit; 0 skips snippet generation
+    pub fragments: usize,
+    /// tell `React` from `react`; `None` is smart case, sensitive only if the query has an
+    /// uppercase letter
+    pub case_sensitive: Option<bool>,
+}
+
+impl Default for QueryOptions {
+    fn default() -> Self {
+        QueryOptions { top: 50, names_only: false, fragments: 0, case_sensitive: None }
+    }
+}
+
+/// One line of a file that explains a hit. `highlights` are byte ranges into `text`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Fragment {
+    /// 1-based line number in the file
+    pub line: usize,
+    /// 1-based page number, for paged documents
+    pub page: Option<usize>,
+    /// 1-based notebook cell and its type, e.g. `code` or `markdown`
+    pub cell: Option<usize>,
+    pub cell_type: Option<String>,
+    pub text: String,
+    pub highlights: Vec<(usize, usize)>,
+}
+
+/// Longest fragment, in chars, taken from a single matching line.
+const SNIPPET_CHARS: usize = 160;
+
+/// A ranked full-text match.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Hit {
+    pub path: String,
+    pub name: String,
+    pub score: f32,
+    pub modified: i64,
+    /// seconds since the epoch, 0 where the filesystem doesn't record it
+    pub created: i64,
+    /// size in bytes, lines of extracted text, lowercased extension and kind, see `file_kind`
+    pub size: u64,
+    pub lines: u64,
+    pub ext: String,
+    pub kind: String,
+    /// charset the file was decoded from
+    pub encoding: String,
+    /// compression the file was stored with, empty if none
+    pub compression: String,
+    /// document title and author, empty for formats without them
+    pub title: String,
+    pub author: String,
+    /// number of pages, 0 if the format has none
+    pub page_count: u64,
+    /// pixel size of photos and videos and the length of audio and video in seconds, 0 if unknown
+    pub width: u64,
+    pub height: u64,
+    pub duration: u64,
+    /// why the content could not be indexed, empty if it was
+    pub skipped: String,
+    /// page or notebook cell (both 1-based) of the best matching line
+    pub page: Option<usize>,
+    pub cell: Option<usize>,
+    pub fragments: Vec<Fragment>,
+}
+
+/// Open an index for reading without creating one as a side effect.
+pub fn open_existing_index(index_dir: &Path) -> Result<(Index, Fields)> {
+    let missing = || anyhow!("no index found at {}; run `warpseek init <paths>` first", index_dir.display());
+    if !index_dir.is_dir() { return Err(missing()); }
+    let directory = MmapDirectory::open(index_dir)?;
+    if !Index::exists(&directory)? { return Err(missing()); }
+    let (_, fields) = build_schema();
+    let index = Index::open(directory)?;
+    check_version(index_dir, &index)?;
+    Ok((with_tokenizers(index), fields))
+}
+
+/// A query ready to run, see `parse_query`.
+struct Parsed {
+    query: Box<dyn Query>,
+    /// whether case matters
+    cased: bool,
+    /// with case on and roots that don't keep it, the free text of the query against the fields
+    /// that do: documents from such roots are found ignoring case and have to pass this too
+    /// (see `top_docs`)
+    recheck: Option<Box<dyn Query>>,
+}
+
+/// Compile the filters in `q` (see `parse_filters`) and hand the rest to tantivy's parser,
+/// against the fields that keep case if `case_sensitive` is on, or `None` and `smart_case` says
+/// so.
+fn parse_query(index: &Index, fields: &Fields, cfg: &Config, q: &str, names_only: bool, case_sensitive: Option<bool>) -> Result<Parsed> {
+    let Filtered { text, clauses } = parse_filters(q, fields, cfg)?;
+    let cased = case_sensitive.unwrap_or_else(|| smart_case(&text));
+    let parser = |content, name| QueryParser::for_index(index, if names_only { vec![name] } else { vec![content, name] });
+    let parse = |qp: &QueryParser, q: &str| qp.parse_query(q).map_err(|e| anyhow!("invalid query {:?}: {}", q, e));
+    let with_filters = |qp: &QueryParser| -> Result<Box<dyn Query>> {
+        if clauses.is_empty() { return parse(qp, q); }
+        let mut all: Vec<(Occur, Box<dyn Query>)> = clauses.iter().map(|(occur, c)| (*occur, c.box_clone())).collect();
+        if !text.is_empty() {
+            all.insert(0, (Occur::Must, parse(qp, &text)?));
+        } else if all.iter().all(|(occur, _)| *occur == Occur::MustNot) {
+            all.push((Occur::Must, Box::new(AllQuery)));
+        }
+        Ok(Box::new(BooleanQuery::new(all)))
+    };
+    let ignoring_case = parser(fields.content, fields.name);
+    if !cased { return Ok(Parsed { query: with_filters(&ignoring_case)?, cased, recheck: None }); }
+    let keeping_case = parser(fields.content_cased, fields.name_cased);
+    let query = with_filters(&keeping_case)?;
+    if text.is_empty() || cfg.roots.iter().all(|r| r.settings.keep_case) { return Ok(Parsed { query, cased, recheck: None }); }
+    let ids: Vec<u64> = cfg.roots.iter().filter(|r| r.settings.keep_case).map(RootConfig::id).collect();
+    let kept = BooleanQuery::new(vec![(Occur::Must, query), (Occur::Must, in_roots(fields, &ids))]);
+    let found = BooleanQuery::new(vec![(Occur::Must, with_filters(&ignoring_case)?), (Occur::MustNot, in_roots(fields, &ids))]);
+    let query = BooleanQuery::new(vec![(Occur::Should, Box::new(kept) as Box<dyn Query>), (Occur::Should, Box::new(found))]);
+    Ok(Parsed { query: Box::new(query), cased, recheck: Some(parse(&keeping_case, &text)?) })
+}
+
+/// Documents from any of the roots `ids`.
+fn in_roots(fields: &Fields, ids: &[u64]) -> Box<dyn Query> {
+    Box::new(BooleanQuery::new(ids.iter().map(|&id| (Occur::Should, Box::new(TermQuery::new(Term::from_field_u64(fields.root, id), IndexRecordOption::Basic)) as Box<dyn Query>)).collect()))
+}
+
+/// The best `top` documents for `query`, highest score first. With `recheck` (see
+/// `parse_query`), documents from roots that don't keep case only count if it matches them once
+/// their text is indexed with case kept; candidates are checked a batch at a time, and at most
+/// `MAX_SCAN_FILES` of them.
+fn top_docs(searcher: &Searcher, fields: &Fields, cfg: &Config, registry: &Registry, query: &dyn Query, recheck: Option<&dyn Query>, top: usize) -> Result<Vec<(Score, DocAddress)>> {
+    let Some(recheck) = recheck else { return Ok(searcher.search(query, &TopDocs::with_limit(top))?) };
+    let kept: HashSet<u64> = cfg.roots.iter().filter(|r| r.settings.keep_case).map(RootConfig::id).collect();
+    let step = top.max(64);
+    let mut found = Vec::new();
+    for offset in (0..MAX_SCAN_FILES).step_by(step) {
+        let batch = searcher.search(query, &TopDocs::with_limit(step).and_offset(offset))?;
+        let mut sure = HashSet::new();
+        let mut unsure = Vec::new();
+        for &(_, addr) in &batch {
+            let doc: TantivyDocument = searcher.doc(addr)?;
+            let root = doc.get_first(fields.root).and_then(|v| v.as_u64()).unwrap_or(0);
+            if kept.contains(&root) { sure.insert(addr); } else { unsure.push(addr); }
+        }
+        sure.extend(case_matches(searcher, fields, registry, recheck, &unsure)?);
+        found.extend(batch.iter().filter(|(_, addr)| sure.contains(addr)).take(top - found.len()));
+        if found.len() == top || batch.len() < step { break; }
+    }
+    Ok(found)
+}
+
+/// Which of `addrs` `query` matches once their text and name are indexed with case kept, in a
+/// scratch index in memory.
+fn case_matches(searcher: &Searcher, fields: &Fields, registry: &Registry, query: &dyn Query, addrs: &[DocAddress]) -> Result<HashSet<DocAddress>> {
+    if addrs.is_empty() { return Ok(HashSet::new()); }
+    let (schema, _) = build_schema();
+    let index = with_tokenizers(Index::create_in_ram(schema));
+    let mut writer: IndexWriter = index.writer_with_num_threads(1, 15_000_000)?;
+    let mut by_path = HashMap::new();
+    for &addr in addrs {
+        let mut doc: TantivyDocument = searcher.doc(addr)?;
+        let x = doc_text(searcher, fields, registry, addr)?;
+        let name = doc.get_first(fields.name).and_then(|v| v.as_str()).unwrap_or("").to_string();
+        doc.add_text(fields.content, &x.text);
+        doc.add_text(fields.content_cased, &x.text);
+        doc.add_text(fields.name_cased, &name);
+        writer.add_document(doc)?;
+        by_path.insert(x.path, addr);
+    }
+    writer.commit()?;
+    let scratch = index.reader()?.searcher();
+    let mut found = HashSet::new();
+    for addr in scratch.search(query, &DocSetCollector)? {
+        let doc: TantivyDocument = scratch.doc(addr)?;
+        if let Some(&a) = doc.get_first(fields.path).and_then(|v| v.as_str()).and_then(|p| by_path.get(p)) { found.insert(a); }
+    }
+    Ok(found)
+}
+
+/// Smart case: whether the free text of a query has an uppercase letter, not counting the
+/// operators `AND`, `OR` and `NOT` or field names.
+pub fn smart_case(text: &str) -> bool {
+    text.split_whitespace()
+        .filter(|w| !matches!(*w, "AND" | "OR" | "NOT"))
+        .map(|w| w.split_once(':').map_or(w, |(_, v)| v))
+        .any(|w| w.chars().any(char::is_uppercase))
+}
+
+/// Run a tantivy query-syntax search over `content` and `name` (or `name` alone)
+/// and return the best `top` hits, highest score first.
+pub fn query_index(ws: &Workspace, q: &str, opts: &QueryOptions) -> Result<Vec<Hit>> {
+    let (index, fields) = open_existing_index(&ws.index_dir)?;
+    let reader = index.reader_builder().reload_policy(ReloadPolicy::Manual).try_into()?;
+    let searcher = reader.searcher();
+
+    let Parsed { query, cased, recheck } = parse_query(&index, &fields, &ws.config, q, opts.names_only, opts.case_sensitive)?;
+    if opts.top == 0 { return Ok(Vec::new()); }
+    let registry = Registry::new(&ws.config);
+    let found = top_docs(&searcher, &fields, &ws.config, &registry, &*query, recheck.as_deref(), opts.top)?;
+    let mut snippets = None;
+    let mut hits = Vec::with_capacity(found.len());
+    for (score, addr) in found {
+        let doc: TantivyDocument = searcher.doc(addr)?;
+        let path = doc.get_first(fields.path).and_then(|v| v.as_str()).unwrap_or("").to_string();
+        let text = |f| doc.get_first(f).and_then(|v| v.as_str()).unwrap_or("").to_string();
+        let number = |f| doc.get_first(f).and_then(|v| v.as_u64()).unwrap_or(0);
+        let starts = |f| doc.get_all(f).filter_map(|v| v.as_u64()).map(|l| l as usize).collect::<Vec<_>>();
+        let (pages, cells) = (starts(fields.pages), starts(fields.cells));
+        let cell_types: Vec<&str> = doc.get_all(fields.cell_types).filter_map(|v| v.as_str()).collect();
+        // paged documents and notebooks need their best line even without snippets, to say where the hit is
+        let want = if pages.is_empty() && cells.is_empty() { opts.fragments } else { opts.fragments.max(1) };
+        let mut fragments = if want > 0 && !opts.names_only {
+            let g = match &mut snippets {
+                Some(g) => g,
+                None => {
+                    let mut g = SnippetGenerator::create(&searcher, &*query, if cased { fields.content_cased } else { fields.content })?;
+                    g.set_max_num_chars(SNIPPET_CHARS);
+                    snippets.insert(g)
+                }
+            };
+            match doc.get_first(fields.text).and_then(|v| v.as_str()) {
+                Some(whole) => fragments(g, whole, want),
+                None => {
+                    // the excerpt may stop short of the match, in which case read the file again
+                    let found = fragments(g, &text(fields.excerpt), want);
+                    if found.is_empty() { fragments(g, &doc_text(&searcher, &fields, &registry, addr)?.text, want) } else { found }
+                }
+            }
+        } else {
+            Vec::new()
+        };
+        for f in &mut fragments {
+            f.page = section_of(&pages, f.line);
+            f.cell = section_of(&cells, f.line);
+            f.cell_type = f.cell.and_then(|c| cell_types.get(c - 1)).map(|t| t.to_string());
+        }
+        let best = fragments.iter().max_by_key(|f| (f.highlights.len(), std::cmp::Reverse(f.line)));
+        let (page, cell) = (best.and_then(|f| f.page), best.and_then(|f| f.cell));
+        fragments.truncate(opts.fragments);
+        hits.push(Hit {
+            name: text(fields.name),
+            path,
+            score,
+            modified: doc.get_first(fields.modified).and_then(|v| v.as_i64()).unwrap_or(0),
+            created: doc.get_first(fields.created).and_then(|v| v.as_i64()).unwrap_or(0),
+            size: number(fields.size),
+            lines: number(fields.lines),
+            ext: text(fields.ext),
+            kind: text(fields.kind),
+            encoding: text(fields.encoding),
+            compression: text(fields.compression),
+            title: text(fields.title),
+            author: text(fields.author),
+            page_count: number(fields.page_count),
+            width: number(fields.width),
+            height: number(fields.height),
+            duration: number(fields.duration),
+            skipped: text(fields.skipped),
+            page,
+            cell,
+            fragments,
+        });
+    }
+    Ok(hits)
+}
+
+/// One occurrence of a query term, shaped like a ripgrep `--vimgrep` row.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LineMatch {
+    pub path: String,
+    /// 1-based line number
+    pub line: usize,
+    /// 1-based byte column, and the column just past the match
+    pub column: usize,
+    pub end: usize,
+    /// 1-based page number, for paged documents
+    pub page: Option<usize>,
+    /// 1-based notebook cell
+    pub cell: Option<usize>,
+    /// the whole line, without its terminator
+    pub text: String,
+}
+
+impl fmt::Display for LineMatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}:{}", self.path, self.line, self.column, self.text)
+    }
+}
+
+/// Let the index pick up to `top` candidate files for `q`, then go through the text of each one
+/// (see `doc_text`) and report every occurrence of a content term with its exact line and
+/// column. Files keep their rank order; matches within a file are in reading order.
+pub fn grep_index(ws: &Workspace, q: &str, opts: &QueryOptions) -> Result<Vec<LineMatch>> {
+    let (index, fields) = open_existing_index(&ws.index_dir)?;
+    let reader = index.reader_builder().reload_policy(ReloadPolicy::Manual).try_into()?;
+    let searcher = reader.searcher();
+    let Parsed { query, cased, recheck } = parse_query(&index, &fields, &ws.config, q, false, opts.case_sensitive)?;
+    if opts.top == 0 { return Ok(Vec::new()); }
+
+    // with case on, terms are looked up and matched in the field that keeps it
+    let content = if cased { fields.content_cased } else { fields.content };
+    let mut terms = HashSet::new();
+    wanted_terms(&*query, content, &mut terms);
+    if terms.is_empty() { return Ok(Vec::new()); }
+
+    let registry = Registry::new(&ws.config);
+    let addrs: Vec<DocAddress> = top_docs(&searcher, &fields, &ws.config, &registry, &*query, recheck.as_deref(), opts.top)?.into_iter().map(|(_, a)| a).collect();
+    let tokenizer = index.tokenizer_for_field(content)?;
+    let per_file: Vec<Vec<LineMatch>> = addrs.par_iter().map(|&addr| {
+        let mut tokenizer = tokenizer.clone();
+        let mut out = Vec::new();
+        let x = doc_text(&searcher, &fields, &registry, addr)?;
+        for (i, line) in x.text.lines().enumerate() {
+            let mut stream = tokenizer.token_stream(line);
+            while let Some(tok) = stream.next() {
+                if terms.contains(&tok.text) {
+                    out.push(LineMatch { path: x.path.clone(), line: i + 1, column: tok.offset_from + 1, end: tok.offset_to + 1, page: section_of(&x.pages, i + 1), cell: section_of(&x.cells, i + 1), text: line.to_string() });
+                }
+            }
+        }
+        Ok(out)
+    }).collect::<Result<_>>()?;
+    Ok(per_file.into_iter().flatten().collect())
+}
+
+/// The terms of `query` in `field`, leaving out those under `MustNot` clauses, which say what
+/// the files found don't contain rather than what to look for in them.
+fn wanted_terms(query: &dyn Query, field: Field, terms: &mut HashSet<String>) {
+    if let Some(b) = query.downcast_ref::<BooleanQuery>() {
+        for (occur, q) in b.clauses() {
+            if *occur != Occur::MustNot { wanted_terms(q.as_ref(), field, terms); }
+        }
+        return;
+    }
+    query.query_terms(&mut |term, _| {
+        if term.field() == field {
+            if let Some(t) = term.value().as_str() { terms.insert(t.to_string()); }
+        }
+    });
+}
+
+/// Files scanned at most for a regex that has no literal to look up in the index.
+pub const MAX_SCAN_FILES: usize = 20_000;
+
+/// Find every line matching the regex `pattern` in up to `top` files. Literals that any match
+/// must contain pick the candidate files from the index; without any, indexed files are scanned
+/// one by one, at most `MAX_SCAN_FILES` of them. Either way the text of each file (see
+/// `doc_text`) is matched for real. Files keep their rank order (index order for a scan);
+/// matches within a file are in reading order. Case follows `opts.case_sensitive`, with smart
+/// case looking for uppercase letters the pattern spells out.
+pub fn regex_index(ws: &Workspace, pattern: &str, opts: &QueryOptions) -> Result<Vec<LineMatch>> {
+    let ast = regex_syntax::ast::parse::Parser::new().parse(pattern).map_err(|e| anyhow!("invalid regex: {}", e))?;
+    let hir = regex_syntax::hir::translate::Translator::new().translate(pattern, &ast).map_err(|e| anyhow!("invalid regex: {}", e))?;
+    let cased = opts.case_sensitive.unwrap_or_else(|| mentions_uppercase(&ast));
+    let re = RegexBuilder::new(pattern).case_insensitive(!cased).build().map_err(|e| anyhow!("invalid regex: {}", e))?;
+    let (index, fields) = open_existing_index(&ws.index_dir)?;
+    let reader = index.reader_builder().reload_policy(ReloadPolicy::Manual).try_into()?;
+    let searcher = reader.searcher();
+    if opts.top == 0 { return Ok(Vec::new()); }
+
+    let literals = required_literals(&hir);
+    // addresses only; stored documents are read a batch at a time below
+    let (query, scan): (Box<dyn Query>, bool) = match candidate_query(&fields, &ws.config, &literals) {
+        Some(q) => (q, false),
+        None => (Box::new(AllQuery), true),
+    };
+    let addrs: Vec<DocAddress> = searcher.search(&*query, &TopDocs::with_limit(MAX_SCAN_FILES))?.into_iter().map(|(_, a)| a).collect();
+
+    let registry = Registry::new(&ws.config);
+    let pb = if scan { ProgressBar::new(addrs.len() as u64) } else { ProgressBar::hidden() };
+    pb.set_style(ProgressStyle::with_template("{spinner:.green} scanning {bar:40.cyan/blue} {pos}/{len} {msg}")?.progress_chars("=>-"));
+    let mut found = Vec::new();
+    let mut files = 0;
+    // check candidates a batch at a time, so a common pattern stops once `top` files matched
+    for batch in addrs.chunks(opts.top.max(64)) {
+        let per_file: Vec<Vec<LineMatch>> = batch.par_iter().map(|&addr| {
+            let matches = doc_text(&searcher, &fields, &registry, addr).map(|x| regex_matches(&re, &x));
+            pb.inc(1);
+            matches
+        }).collect::<Result<_>>()?;
+        for m in per_file.into_iter().filter(|m| !m.is_empty()) {
+            if files == opts.top { break; }
+            files += 1;
+            found.extend(m);
+        }
+        if files == opts.top { break; }
+    }
+    pb.finish_and_clear();
+    Ok(found)
+}
+
+/// Documents that can contain every set of `literals`: looked up by trigram in roots that index
+/// them, which finds any substring, and by word everywhere else.
+fn candidate_query(fields: &Fields, cfg: &Config, literals: &[Vec<String>]) -> Option<Box<dyn Query>> {
+    let words = literal_query(fields.content, literals);
+    let ids: Vec<u64> = cfg.roots.iter().filter(|r| r.settings.trigrams).map(RootConfig::id).collect();
+    if ids.is_empty() { return words; }
+    let grams = gram_query(fields.grams, literals);
+    if words.is_none() && grams.is_none() { return None; }
+    let in_gram_roots = || in_roots(fields, &ids);
+    let gram_side = BooleanQuery::new(vec![(Occur::Must, in_gram_roots()), (Occur::Must, grams.unwrap_or_else(|| Box::new(AllQuery)))]);
+    let word_side = BooleanQuery::new(vec![(Occur::Must, words.unwrap_or_else(|| Box::new(AllQuery))), (Occur::MustNot, in_gram_roots())]);
+    Some(Box::new(BooleanQuery::new(vec![(Occur::Should, Box::new(gram_side)), (Occur::Should, Box::new(word_side))])))
+}
+
+fn regex_matches(re: &Regex, x: &DocText) -> Vec<LineMatch> {
+    let mut out = Vec::new();
+    for (i, line) in x.text.lines().enumerate() {
+        for m in re.find_iter(line).filter(|m| !m.is_empty()) {
+            out.push(LineMatch { path: x.path.clone(), line: i + 1, column: m.start() + 1, end: m.end() + 1, page: section_of(&x.pages, i + 1), cell: section_of(&x.cells, i + 1), text: line.to_string() });
+        }
+    }
+    out
+}
+
+/// A document's text, with the lines its pages and notebook cells start on.
+struct DocText {
+    path: String,
+    text: String,
+    pages: Vec<usize>,
+    cells: Vec<usize>,
+}
+
+/// The text of `addr`: read back from the index where its root stores text, and otherwise
+/// extracted from the file again, empty if that no longer works.
+fn doc_text(searcher: &Searcher, fields: &Fields, registry: &Registry, addr: DocAddress) -> Result<DocText> {
+    let doc: TantivyDocument = searcher.doc(addr)?;
+    let text = |f| doc.get_first(f).and_then(|v| v.as_str()).map(String::from);
+    let starts = |f| doc.get_all(f).filter_map(|v| v.as_u64()).map(|l| l as usize).collect();
+    let path = text(fields.path).unwrap_or_default();
+    // documents without lines, like images, have nothing to read again
+    let has_lines = doc.get_first(fields.lines).and_then(|v| v.as_u64()).unwrap_or(0) > 0;
+    let body = text(fields.text).or_else(|| has_lines.then(|| read_text(&path, registry)).flatten()).unwrap_or_default();
+    Ok(DocText { path, text: body, pages: starts(fields.pages), cells: starts(fields.cells) })
+}
+
+/// Extract `path`, which may be an archive member, the way it was indexed.
+fn read_text(path: &str, registry: &Registry) -> Option<String> {
+    registry.extract(path, &read_path(path).ok()?).map(|x| x.text)
+}
+
+/// The 1-based page or cell containing `line`, given the line each of them starts on.
+fn section_of(starts: &[usize], line: usize) -> Option<usize> {
+    match starts.partition_point(|&start| start <= line) {
+        0 => None,
+        n => Some(n),
+    }
+}
+
+/// Up to `max` matching lines of `text`, the ones with the most highlighted terms first,
+/// returned in line order.
+pub fn fragments(generator: &SnippetGenerator, text: &str, max: usize) -> Vec<Fragment> {
+    let mut found: Vec<Fragment> = text.lines().enumerate().filter_map(|(i, line)| {
+        let snippet = generator.snippet(line);
+        if snippet.is_empty() { return None; }
+        Some(Fragment {
+            line: i + 1,
+            page: None,
+            cell: None,
+            cell_type: None,
+            text: snippet.fragment().to_string(),
+            highlights: snippet.highlighted().iter().map(|r| (r.start, r.end)).collect(),
+        })
+    }).collect();
+    found.sort_by(|a, b| b.highlights.len().cmp(&a.highlights.len()).then(a.line.cmp(&b.line)));
+    found.truncate(max);
+    found.sort_by_key(|f| f.line);
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::roots::RootSettings;
+    use crate::search::{index_all, DEFAULT_EXCERPT_BYTES};
+    use std::fs;
+    use tempfile::TempDir;
+
+    /// A workspace whose only root holds `files`, indexed. The temp dirs go when it's dropped.
+    fn indexed(files: &[(&str, &str)]) -> (Workspace, [TempDir; 2]) {
+        indexed_with(files, RootSettings::default(), DEFAULT_EXCERPT_BYTES)
+    }
+
+    fn indexed_with(files: &[(&str, &str)], settings: RootSettings, excerpt_bytes: usize) -> (Workspace, [TempDir; 2]) {
+        let (home, root) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        for (name, text) in files { fs::write(root.path().join(name), text).unwrap(); }
+        let mut ws = Workspace::at(home.path()).unwrap();
+        ws.config.excerpt_bytes = excerpt_bytes;
+        ws.config.roots.push(RootConfig { path: root.path().to_path_buf(), settings });
+        index_all(&mut ws).unwrap();
+        (ws, [home, root])
+    }
+
+    /// `name:line:column` of each match, in result order.
+    fn grep(ws: &Workspace, q: &str, case_sensitive: Option<bool>) -> Vec<String> {
+        let opts = QueryOptions { case_sensitive, ..QueryOptions::default() };
+        let mut rows: Vec<String> = grep_index(ws, q, &opts).unwrap().into_iter()
+            .map(|m| format!("{}:{}:{}", Path::new(&m.path).file_name().unwrap().to_string_lossy(), m.line, m.column))
+            .collect();
+        rows.sort();
+        rows
+    }
+
+    #[test]
+    fn grep_reports_every_occurrence() {
+        let (ws, _dirs) = indexed(&[("a.txt", "foo here\nnothing\n  foo foo\n"), ("b.txt", "bar only\n")]);
+        assert_eq!(grep(&ws, "foo", None), ["a.txt:1:1", "a.txt:3:3", "a.txt:3:7"]);
+        assert_eq!(grep(&ws, "foo OR bar", None), ["a.txt:1:1", "a.txt:3:3", "a.txt:3:7", "b.txt:1:1"]);
+        assert!(grep(&ws, "missing", None).is_empty());
+    }
+
+    #[test]
+    fn grep_skips_excluded_terms() {
+        let (ws, _dirs) = indexed(&[("a.txt", "foo\nbar qux\n"), ("b.txt", "foo\nbar baz\n"), ("c.txt", "foo bar\n")]);
+        // a file with `bar` but not `bar baz` matches, but its `bar` is not what was looked for
+        assert_eq!(grep(&ws, "foo -\"bar baz\"", None), ["a.txt:1:1", "c.txt:1:1"]);
+        assert_eq!(grep(&ws, "foo -qux", None), ["b.txt:1:1", "c.txt:1:1"]);
+    }
+
+    #[test]
+    fn lines_come_from_stored_text_or_the_file_as_it_is_now() {
+        for store_text in [true, false] {
+            let (ws, dirs) = indexed_with(&[("a.txt", "foo\nbar\n")], RootSettings { store_text, ..RootSettings::default() }, 4);
+            fs::write(dirs[1].path().join("a.txt"), "\n\nfoo\n").unwrap();
+            assert_eq!(grep(&ws, "foo", None), [if store_text { "a.txt:1:1" } else { "a.txt:3:1" }]);
+            let hits = query_index(&ws, "foo", &QueryOptions { fragments: 1, ..QueryOptions::default() }).unwrap();
+            assert_eq!(hits[0].fragments.iter().map(|f| f.line).collect::<Vec<_>>(), [1], "the excerpt has it at line 1");
+        }
+    }
+
+    #[test]
+    fn text_is_stored_whole_when_it_fits_the_excerpt_and_read_again_past_it() {
+        let (ws, dirs) = indexed_with(&[("a.txt", "foo\nbar\n"), ("b.txt", "bar\n")], RootSettings::default(), 4);
+        fs::write(dirs[1].path().join("a.txt"), "\n\nfoo\nbar\n").unwrap();
+        fs::write(dirs[1].path().join("b.txt"), "\nbar\n").unwrap();
+        assert_eq!(grep(&ws, "bar", None), ["a.txt:4:1", "b.txt:1:1"]);
+        let hits = query_index(&ws, "bar", &QueryOptions { fragments: 1, ..QueryOptions::default() }).unwrap();
+        let line = |name: &str| hits.iter().find(|h| h.path.ends_with(name)).unwrap().fragments[0].line;
+        assert_eq!((line("a.txt"), line("b.txt")), (4, 1), "`bar` is past a.txt's excerpt but in b.txt's stored text");
+    }
+
+    #[test]
+    fn case_follows_the_query_whether_or_not_the_root_keeps_it() {
+        let files = [("a.txt", "React here\n"), ("b.txt", "react here\n"), ("c.txt", "REACT\n"), ("Readme.md", "nothing\n"), ("d.txt", "react again\n")];
+        for keep_case in [true, false] {
+            let (ws, _dirs) = indexed_with(&files, RootSettings { keep_case, ..RootSettings::default() }, DEFAULT_EXCERPT_BYTES);
+            let names = |q: &str, case_sensitive: Option<bool>, top: usize| -> Vec<String> {
+                let mut names: Vec<String> = query_index(&ws, q, &QueryOptions { top, case_sensitive, ..QueryOptions::default() }).unwrap().into_iter().map(|h| h.name).collect();
+                names.sort();
+                names
+            };
+            // smart case: an uppercase letter turns it on
+            assert_eq!(names("React", None, 10), ["a.txt"], "keep_case {keep_case}");
+            assert_eq!(names("react", None, 10), ["a.txt", "b.txt", "c.txt", "d.txt"]);
+            assert_eq!(names("react", Some(true), 10), ["b.txt", "d.txt"]);
+            assert_eq!(names("React", Some(false), 10), ["a.txt", "b.txt", "c.txt", "d.txt"]);
+            assert_eq!(names("React ext:txt", None, 10), ["a.txt"]);
+            assert_eq!(names("Readme", None, 10), ["Readme.md"]);
+            assert!(names("README", None, 10).is_empty());
+            // files that only match ignoring case don't use up `top`
+            assert_eq!(names("REACT", None, 1), ["c.txt"]);
+
+            assert_eq!(grep(&ws, "React", None), ["a.txt:1:1"]);
+            assert_eq!(grep(&ws, "react", Some(true)), ["b.txt:1:1", "d.txt:1:1"]);
+            assert_eq!(grep(&ws, "react", None), ["a.txt:1:1", "b.txt:1:1", "c.txt:1:1", "d.txt:1:1"]);
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use anyhow::{anyhow, Context, Result};
+use ignore::{WalkBuilder, overrides::{Override, OverrideBuilder}, types::{Types, TypesBuilder}};
+use serde::{Serialize, Deserialize};
+use std::path::{Path, PathBuf};
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::archive::ARCHIVE_GLOBS;
+use crate::epub::EPUB_GLOBS;
+use crate::mail::MAIL_GLOBS;
+use crate::media::MEDIA_GLOBS;
+use crate::office::OFFICE_GLOBS;
+
+/// Directories that are never worth indexing, even though they are neither hidden-filtered nor ignored.
+pub const SKIP_DIRS: &[&str] = &[".git"];
+
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 20 * 1024 * 1024;
+
+/// File types we extract text from that ripgrep's defaults don't know about; part of `all`.
+const EXTRA_TYPES: &[(&str, &[&str])] = &[("archive", ARCHIVE_GLOBS), ("office", OFFICE_GLOBS), ("ebook", EPUB_GLOBS), ("mail", MAIL_GLOBS), ("media", MEDIA_GLOBS)];
+
+/// How a single root is walked and which of its files get indexed.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct RootSettings {
+    /// only index files matching one of these globs (relative to the root); empty means all
+    pub include: Vec<String>,
+    /// never index files matching these globs
+    pub exclude: Vec<String>,
+    /// ripgrep file type names to index, e.g. `rust` or `markdown`; `all` is every known type,
+    /// an empty list disables type filtering altogether
+    pub file_types: Vec<String>,
+    /// skip files larger than this many bytes
+    pub max_file_size: u64,
+    pub follow_links: bool,
+    /// index hidden (dot) files and directories
+    pub hidden: bool,
+    /// don't descend into other mounted filesystems
+    pub one_file_system: bool,
+    /// ignore files to honour; `.gitignore` also enables `.git/info/exclude` and the global
+    /// gitignore, any other name is read as an extra ignore file with the highest precedence
+    pub ignore_files: Vec<String>,
+    /// also index content as trigrams, so literal substrings like `Handler::new(` can be looked
+    /// up; makes the index several times larger
+    pub trigrams: bool,
+    /// store each document's full text in the index, so matching lines come straight from it
+    /// instead of reading the file again; makes the index larger
+    pub store_text: bool,
+    /// also index content and file names with their case kept, so case-sensitive searches are
+    /// looked up directly instead of checking what a case-blind lookup found; a larger index
+    pub keep_case: bool,
+}
+
+impl Default for RootSettings {
+    fn default() -> Self {
+        RootSettings {
+            include: Vec::new(),
+            exclude: Vec::new(),
+            file_types: vec!["all".to_string()],
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            follow_links: false,
+            hidden: true,
+            one_file_system: false,
+            ignore_files: vec![".gitignore".to_string(), ".ignore".to_string()],
+            trigrams: false,
+            store_text: false,
+            keep_case: false,
+        }
+    }
+}
+
+impl RootSettings {
+    pub fn honours(&self, ignore_file: &str) -> bool {
+        self.ignore_files.iter().any(|f| f == ignore_file)
+    }
+
+    /// Ignore file names other than the built-in `.gitignore` and `.ignore`.
+    pub fn custom_ignore_files(&self) -> impl Iterator<Item = &str> {
+        self.ignore_files.iter().map(|s| s.as_str()).filter(|f| *f != ".gitignore" && *f != ".ignore")
+    }
+}
+
+/// An indexed root directory with its own settings. Deserializes from either a bare path
+/// (the format older configs used) or a full object.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(from = "RootRepr")]
+pub struct RootConfig {
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub settings: RootSettings,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RootRepr {
+    Path(PathBuf),
+    Full {
+        path: PathBuf,
+        #[serde(flatten)]
+        settings: RootSettings,
+    },
+}
+
+impl From<RootRepr> for RootConfig {
+    fn from(r: RootRepr) -> Self {
+        match r {
+            RootRepr::Path(path) => RootConfig::new(path),
+            RootRepr::Full { path, settings } => RootConfig { path, settings },
+        }
+    }
+}
+
+impl RootConfig {
+    pub fn new(path: PathBuf) -> RootConfig {
+        RootConfig { path, settings: RootSettings::default() }
+    }
+
+    /// Stable id stored with every document from this root, derived from its path.
+    pub fn id(&self) -> u64 {
+        xxh3_64(self.path.to_string_lossy().as_bytes())
+    }
+
+    pub fn types(&self) -> Result<Option<Types>> {
+        if self.settings.file_types.is_empty() { return Ok(None); }
+        let mut types = TypesBuilder::new();
+        types.add_defaults();
+        for (name, globs) in EXTRA_TYPES {
+            for g in *globs { types.add(name, g)?; }
+        }
+        for t in &self.settings.file_types { types.select(t); }
+        Ok(Some(types.build().with_context(|| format!("file types for {}", self.path.display()))?))
+    }
+
+    pub fn overrides(&self) -> Result<Override> {
+        let mut b = OverrideBuilder::new(&self.path);
+        for g in &self.settings.include { b.add(g).with_context(|| format!("include glob {:?}", g))?; }
+        for g in &self.settings.exclude { b.add(&format!("!{}", g)).with_context(|| format!("exclude glob {:?}", g))?; }
+        Ok(b.build()?)
+    }
+
+    /// A walker over this root, or over `start` below it, applying all of the root's settings.
+    pub fn walk_builder(&self, start: &Path) -> Result<WalkBuilder> {
+        let s = &self.settings;
+        let git = s.honours(".gitignore");
+        let mut w = WalkBuilder::new(start);
+        w.hidden(!s.hidden)
+            .follow_links(s.follow_links)
+            .same_file_system(s.one_file_system)
+            .max_filesize(Some(s.max_file_size))
+            .git_ignore(git)
+            .git_exclude(git)
+            .git_global(git)
+            .ignore(s.honours(".ignore"));
+        for name in s.custom_ignore_files() { w.add_custom_ignore_filename(name); }
+        w.filter_entry(|e| !SKIP_DIRS.iter().any(|d| e.file_name() == *d));
+        w.overrides(self.overrides()?);
+        if let Some(t) = self.types()? { w.types(t); }
+        Ok(w)
+    }
+}
+
+/// Parse a human size like `512`, `64KB`, `1.5MB` or `2G` into bytes (binary multiples).
+pub fn parse_size(s: &str) -> Result<u64> {
+    let t = s.trim();
+    let split = t.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(t.len());
+    let (num, unit) = t.split_at(split);
+    let n: f64 = num.parse().map_err(|_| anyhow!("invalid size {:?}", s))?;
+    let mult: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        "t" | "tb" | "tib" => 1 << 40,
+        _ => return Err(anyhow!("invalid size unit in {:?}; use B, KB, MB, GB or TB", s)),
+    };
+    Ok((n * mult as f64) as u64)
+}
@@ -1,84 +1,342 @@
-use anyhow::{anyhow, Context, Result};
-use directories::ProjectDirs;
-use ignore::{WalkBuilder, types::TypesBuilder};
+use anyhow::{anyhow, Result};
 use indicatif::{ProgressBar, ProgressStyle};
 use rayon::prelude::*;
-use std::{fs, path::{Path, PathBuf}, time::SystemTime};
+use std::{fs, path::{Path, PathBuf}};
 use std::io::Read;
-use tantivy::schema::{Schema, TEXT, STORED, STRING, FAST, Field};
-use tantivy::{Index, doc, directory::MmapDirectory};
+use tantivy::schema::{IndexRecordOption, Schema, TextFieldIndexing, TextOptions, TEXT, STORED, STRING, FAST, INDEXED, Field};
+use tantivy::tokenizer::{LowerCaser, NgramTokenizer, RemoveLongFilter, SimpleTokenizer, TextAnalyzer};
+use tantivy::{DateTime, Index, IndexWriter, TantivyDocument, Term, doc, directory::{MmapDirectory, INDEX_WRITER_LOCK, META_LOCK}};
 use serde::{Serialize, Deserialize};
 use xxhash_rust::xxh3::xxh3_64;
 
-#[derive(Serialize, Deserialize, Default)]
+use crate::archive::{ArchiveLimits, ARCHIVE_SEP};
+use crate::compress::DEFAULT_MAX_DECOMPRESSED_SIZE;
+use crate::external::ExternalCommand;
+use crate::extract::{file_kind, Extracted, Registry};
+use crate::manifest::{manifest_path, Manifest, FileEntry, created_secs, mtime_nanos};
+use crate::names::{write_names, NAME_FILES};
+use crate::roots::{RootConfig, DEFAULT_MAX_FILE_SIZE};
+use crate::symbols::extract_symbols;
+use crate::workspace::Workspace;
+
+/// Default number of bytes of each file's text kept in the index for snippets.
+pub const DEFAULT_EXCERPT_BYTES: usize = 64 * 1024;
+
+fn default_excerpt_bytes() -> usize { DEFAULT_EXCERPT_BYTES }
+
+fn default_max_decompressed_size() -> u64 { DEFAULT_MAX_DECOMPRESSED_SIZE }
+
+#[derive(Serialize, Deserialize)]
 pub struct Config {
-    pub roots: Vec<PathBuf>,
+    pub roots: Vec<RootConfig>,
     // keep last scan checksum to avoid rebuilds
     pub last_scan: Option<u64>,
+    /// How much of a document's text to store for snippets when its root doesn't store all of
+    /// it: text this short is stored whole, longer text only up to here, and the file is read
+    /// again for matches past that. 0 stores none.
+    #[serde(default = "default_excerpt_bytes")]
+    pub excerpt_bytes: usize,
+    /// Gzip, zstd, xz and bzip2 files are indexed by their content up to this many
+    /// expanded bytes; 0 leaves them compressed.
+    #[serde(default = "default_max_decompressed_size")]
+    pub max_decompressed_size: u64,
+    /// Keep the text output of notebook cells, not just their source.
+    #[serde(default)]
+    pub notebook_outputs: bool,
+    /// How deep and how much to read into zip and tar archives.
+    #[serde(default)]
+    pub archives: ArchiveLimits,
+    /// Local programs to extract text with, tried before the built-in extractors.
+    #[serde(default)]
+    pub extractors: Vec<ExternalCommand>,
+}
+
+impl Config {
+    /// The configured root containing `p`, preferring the most specific one when roots nest.
+    pub fn root_for(&self, p: &Path) -> Option<&RootConfig> {
+        self.roots.iter().filter(|r| p.starts_with(&r.path)).max_by_key(|r| r.path.as_os_str().len())
+    }
+
+    pub fn root_paths(&self) -> Vec<&Path> {
+        self.roots.iter().map(|r| r.path.as_path()).collect()
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config { roots: Vec::new(), last_scan: None, excerpt_bytes: DEFAULT_EXCERPT_BYTES, max_decompressed_size: DEFAULT_MAX_DECOMPRESSED_SIZE, notebook_outputs: false, archives: ArchiveLimits::default(), extractors: Vec::new() }
+    }
 }
 
 pub struct Fields {
     pub path: Field,
+    /// the file on disk a document came from: its own path, or the archive it is a member of
+    pub container: Field,
     pub name: Field,
     pub content: Field,
     pub modified: Field,
+    /// leading part of `content` when `text` isn't stored, up to `Config::excerpt_bytes`;
+    /// stored (compressed by the doc store) but not indexed
+    pub excerpt: Field,
+    /// all of `content`, stored but not indexed, for roots with `store_text` on and for text no
+    /// longer than an excerpt; searches find exact lines in it without extracting the file again
+    pub text: Field,
+    /// charset the file was decoded from, e.g. `UTF-8` or `windows-1252`
+    pub encoding: Field,
+    /// compression the content was stored with, e.g. `gzip`; absent for plain files
+    pub compression: Field,
+    /// document metadata, where the format has it
+    pub title: Field,
+    pub author: Field,
+    /// headings of markup documents and EPUB chapters, one value each
+    pub headings: Field,
+    /// mail headers; `date` is when the message was sent
+    pub from: Field,
+    pub to: Field,
+    pub subject: Field,
+    pub date: Field,
+    /// photo and media metadata; `taken` is text like `2024-07-15T10:30:00` so that `taken:2024-07`
+    /// matches a month, `duration` is in seconds
+    pub camera: Field,
+    pub lens: Field,
+    pub taken: Field,
+    pub gps: Field,
+    pub width: Field,
+    pub height: Field,
+    pub artist: Field,
+    pub album: Field,
+    pub duration: Field,
+    pub page_count: Field,
+    /// 1-based line each page starts on, one value per page
+    pub pages: Field,
+    /// 1-based line each notebook cell starts on, and the cell types in the same order
+    pub cells: Field,
+    pub cell_types: Field,
+    /// reason the content is missing, e.g. `encrypted`
+    pub skipped: Field,
+    /// name and version of the extractor the content came from, e.g. `pdf` or `pandoc`
+    pub extractor: Field,
+    pub extractor_version: Field,
+    /// names of the definitions in source files, one value each, and each definition as
+    /// `kind\tname\tline\tcontainer` in the same order
+    pub symbol: Field,
+    pub symbol_defs: Field,
+    /// lowercased extension without the dot, and the directory the document sits in (inside
+    /// the archive, for members)
+    pub ext: Field,
+    pub dir: Field,
+    /// size in bytes, of the member itself for archive members
+    pub size: Field,
+    /// `RootConfig::id` of the root the file was found under
+    pub root: Field,
+    /// seconds since the epoch, where the filesystem records it
+    pub created: Field,
+    /// lines of extracted text
+    pub lines: Field,
+    /// xxh3 of the bytes the text was extracted from
+    pub hash: Field,
+    /// what the document is, see `file_kind`
+    pub kind: Field,
+    /// content as lowercased trigrams, for roots with `trigrams` on
+    pub grams: Field,
+    /// content and name with case intact, for case-sensitive search in roots with `keep_case` on
+    pub content_cased: Field,
+    pub name_cased: Field,
 }
 
-pub fn project_dirs() -> Result<ProjectDirs> {
-    ProjectDirs::from("io", "frangedev", "warpseek").ok_or_else(|| anyhow!("cannot resolve project dirs"))
-}
-
-pub fn index_path() -> Result<PathBuf> {
-    Ok(project_dirs()?.data_dir().join("index"))
-}
+/// Tokenizer of the `grams` field: every run of three chars, lowercased.
+pub const TRIGRAM_TOKENIZER: &str = "trigram";
 
-pub fn config_path() -> Result<PathBuf> {
-    Ok(project_dirs()?.config_dir().join("config.json"))
-}
-
-pub fn read_config() -> Result<Config> {
-    let p = config_path()?;
-    if p.exists() {
-        let s = fs::read_to_string(&p)?;
-        Ok(serde_json::from_str(&s)?)
-    } else {
-        Ok(Config::default())
-    }
-}
+/// Tokenizer of the `*_cased` fields: tantivy's default one without lowercasing.
+pub const CASED_TOKENIZER: &str = "cased";
 
-pub fn write_config(cfg: &Config) -> Result<()> {
-    let p = config_path()?;
-    fs::create_dir_all(p.parent().unwrap())?;
-    fs::write(p, serde_json::to_string_pretty(cfg)?)?;
-    Ok(())
-}
+/// Version of the schema and of what gets extracted into it, recorded in the manifest. Bump it
+/// whenever either changes, so older indexes are rebuilt instead of half-filled.
+pub const SCHEMA_VERSION: u32 = 7;
 
 pub fn build_schema() -> (Schema, Fields) {
     let mut schema = Schema::builder();
     let path = schema.add_text_field("path", STRING | STORED);
+    let container = schema.add_text_field("container", STRING);
     let name = schema.add_text_field("name", TEXT | STORED);
     let content = schema.add_text_field("content", TEXT);
     let modified = schema.add_i64_field("modified", FAST | STORED);
+    let excerpt = schema.add_text_field("excerpt", STORED);
+    let encoding = schema.add_text_field("encoding", STRING | STORED);
+    let compression = schema.add_text_field("compression", STRING | STORED);
+    let title = schema.add_text_field("title", TEXT | STORED);
+    let author = schema.add_text_field("author", TEXT | STORED);
+    let headings = schema.add_text_field("headings", TEXT | STORED);
+    let from = schema.add_text_field("from", TEXT | STORED);
+    let to = schema.add_text_field("to", TEXT | STORED);
+    let subject = schema.add_text_field("subject", TEXT | STORED);
+    let date = schema.add_date_field("date", INDEXED | STORED);
+    let camera = schema.add_text_field("camera", TEXT | STORED);
+    let lens = schema.add_text_field("lens", TEXT | STORED);
+    let taken = schema.add_text_field("taken", TEXT | STORED);
+    let gps = schema.add_bool_field("gps", INDEXED | STORED);
+    let width = schema.add_u64_field("width", INDEXED | STORED);
+    let height = schema.add_u64_field("height", INDEXED | STORED);
+    let artist = schema.add_text_field("artist", TEXT | STORED);
+    let album = schema.add_text_field("album", TEXT | STORED);
+    let duration = schema.add_u64_field("duration", INDEXED | STORED);
+    let page_count = schema.add_u64_field("page_count", INDEXED | STORED);
+    let pages = schema.add_u64_field("pages", STORED);
+    let cells = schema.add_u64_field("cells", STORED);
+    let cell_types = schema.add_text_field("cell_types", STORED);
+    let skipped = schema.add_text_field("skipped", STRING | STORED);
+    let extractor = schema.add_text_field("extractor", STRING | STORED);
+    let extractor_version = schema.add_text_field("extractor_version", STORED);
+    let symbol = schema.add_text_field("symbol", STRING);
+    let symbol_defs = schema.add_text_field("symbol_defs", STORED);
+    let ext = schema.add_text_field("ext", STRING | STORED | FAST);
+    let dir = schema.add_text_field("dir", STRING | STORED);
+    let size = schema.add_u64_field("size", INDEXED | STORED | FAST);
+    let root = schema.add_u64_field("root", INDEXED | STORED | FAST);
+    let created = schema.add_i64_field("created", INDEXED | STORED | FAST);
+    let lines = schema.add_u64_field("lines", INDEXED | STORED | FAST);
+    let hash = schema.add_u64_field("hash", INDEXED | STORED | FAST);
+    let kind = schema.add_text_field("kind", STRING | STORED | FAST);
+    let grams = schema.add_text_field("grams", TextOptions::default().set_indexing_options(TextFieldIndexing::default().set_tokenizer(TRIGRAM_TOKENIZER).set_index_option(IndexRecordOption::Basic)));
+    let cased = TextOptions::default().set_indexing_options(TextFieldIndexing::default().set_tokenizer(CASED_TOKENIZER).set_index_option(IndexRecordOption::WithFreqsAndPositions));
+    let content_cased = schema.add_text_field("content_cased", cased.clone());
+    let name_cased = schema.add_text_field("name_cased", cased);
+    let text = schema.add_text_field("text", STORED);
     let built_schema = schema.build();
     let fields = Fields {
         path,
+        container,
         name,
         content,
         modified,
+        excerpt,
+        encoding,
+        compression,
+        title,
+        author,
+        headings,
+        from,
+        to,
+        subject,
+        date,
+        camera,
+        lens,
+        taken,
+        gps,
+        width,
+        height,
+        artist,
+        album,
+        duration,
+        page_count,
+        pages,
+        cells,
+        cell_types,
+        skipped,
+        extractor,
+        extractor_version,
+        symbol,
+        symbol_defs,
+        ext,
+        dir,
+        size,
+        root,
+        created,
+        lines,
+        hash,
+        kind,
+        grams,
+        content_cased,
+        name_cased,
+        text,
     };
     (built_schema, fields)
 }
 
+/// Open the index for writing, creating it if there is none. An index built by another version
+/// of warpseek is replaced by an empty one, along with its manifest, so every file is indexed
+/// again into the current schema.
 pub fn open_index(index_dir: &Path) -> Result<(Index, Fields)> {
     fs::create_dir_all(index_dir)?;
+    if !is_current(index_dir)? { remove_index(index_dir)?; }
     let (schema, fields) = build_schema();
     let directory = MmapDirectory::open(index_dir)?;
     let index = if Index::exists(&directory)? {
         Index::open(directory)?
     } else {
-        Index::create(directory, schema, tantivy::IndexSettings::default())?
+        let index = Index::create(directory, schema, tantivy::IndexSettings::default())?;
+        // a manifest left without its index would make every file look indexed already
+        Manifest::default().save(index_dir)?;
+        index
     };
-    Ok((index, fields))
+    Ok((with_tokenizers(index), fields))
+}
+
+/// Delete the index in `index_dir`: the files tantivy manages there, the manifest and the
+/// filename index. Anything else in the directory is left alone. Fails while another process,
+/// such as `warpseek watch`, holds the index writer.
+pub fn remove_index(index_dir: &Path) -> Result<()> {
+    if !index_dir.is_dir() { return Ok(()); }
+    let directory = MmapDirectory::open(index_dir)?;
+    if Index::exists(&directory)? {
+        let index = Index::open(directory)?;
+        // holding the writer lock keeps anyone else from writing segments while they go
+        let writer: IndexWriter = index.writer_with_num_threads(1, 15_000_000)
+            .map_err(|e| anyhow!("index at {} is in use (is `warpseek watch` running?): {}", index_dir.display(), e))?;
+        let mut files: Vec<PathBuf> = index.directory().list_managed_files().into_iter().collect();
+        // tantivy's list of the files it manages, and its meta lock
+        files.push(PathBuf::from(".managed.json"));
+        files.push(META_LOCK.filepath.clone());
+        for f in files { remove_if_exists(&index_dir.join(f))?; }
+        drop(writer);
+        remove_if_exists(&index_dir.join(&INDEX_WRITER_LOCK.filepath))?;
+    }
+    remove_if_exists(&manifest_path(index_dir))?;
+    for f in NAME_FILES { remove_if_exists(&index_dir.join(f))?; }
+    Ok(())
+}
+
+fn remove_if_exists(p: &Path) -> Result<()> {
+    match fs::remove_file(p) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(anyhow!("remove {}: {}", p.display(), e)),
+        _ => Ok(()),
+    }
+}
+
+/// Register the tokenizers our schema uses beyond tantivy's defaults; needed on every open.
+pub(crate) fn with_tokenizers(index: Index) -> Index {
+    let trigrams = NgramTokenizer::new(3, 3, false).expect("valid ngram sizes");
+    index.tokenizers().register(TRIGRAM_TOKENIZER, TextAnalyzer::builder(trigrams).filter(LowerCaser).build());
+    index.tokenizers().register(CASED_TOKENIZER, TextAnalyzer::builder(SimpleTokenizer::default()).filter(RemoveLongFilter::limit(40)).build());
+    index
+}
+
+/// Rebuild an index that another version of warpseek built (see `open_index`), indexing every
+/// root into the current schema. `None` when the index is current or there is none yet.
+pub fn upgrade_index(ws: &mut Workspace) -> Result<Option<IndexReport>> {
+    if is_current(&ws.index_dir)? { return Ok(None); }
+    index_all(ws).map(Some)
+}
+
+/// Whether the index in `index_dir` has the schema and `SCHEMA_VERSION` of this build, or there
+/// is no index to speak of.
+fn is_current(index_dir: &Path) -> Result<bool> {
+    if !index_dir.is_dir() { return Ok(true); }
+    let directory = MmapDirectory::open(index_dir)?;
+    if !Index::exists(&directory)? { return Ok(true); }
+    up_to_date(index_dir, &Index::open(directory)?)
+}
+
+fn up_to_date(index_dir: &Path, index: &Index) -> Result<bool> {
+    let (schema, _) = build_schema();
+    Ok(index.schema() == schema && Manifest::load(index_dir)?.schema_version == SCHEMA_VERSION)
+}
+
+/// Readers never rewrite an index; one built by another version has to go through
+/// `upgrade_index` first.
+pub(crate) fn check_version(index_dir: &Path, index: &Index) -> Result<()> {
+    if up_to_date(index_dir, index)? { return Ok(()); }
+    Err(anyhow!("index at {} was built by a different version of warpseek; run `warpseek index` to rebuild it", index_dir.display()))
 }
 
 pub fn is_probably_text(bytes: &[u8]) -> bool {
@@ -92,79 +350,329 @@ pub fn is_probably_text(bytes: &[u8]) -> bool {
     }
 }
 
-pub fn collect_files(roots: &[PathBuf]) -> Vec<PathBuf> {
-    let mut types = TypesBuilder::new();
-    // index common text types by default
-    let _ = types.add_defaults();
-    let matcher = types.select("all").build().ok();
+pub fn collect_files(roots: &[RootConfig]) -> Result<Vec<PathBuf>> {
     let mut v = Vec::new();
     for r in roots {
-        let mut w = WalkBuilder::new(r);
-        w.hidden(false).follow_links(false).add_custom_ignore_filename(".ignore");
-        if let Some(m) = &matcher { w.types(m.clone()); }
-        let walker = w.build();
-        for res in walker {
-            if let Ok(entry) = res {
-                let p = entry.path();
-                if p.is_file() {
-                    v.push(p.to_path_buf());
-                }
+        let walker = r.walk_builder(&r.path)?.build();
+        for entry in walker.flatten() {
+            let p = entry.path();
+            if p.is_file() {
+                v.push(p.to_path_buf());
             }
         }
     }
-    // Sort files lexicographically for FST compatibility
+    // Sort files lexicographically for a deterministic indexing order
     v.sort();
-    v
+    Ok(v)
+}
+
+/// Outcome of an incremental `index_all` run.
+#[derive(Default, Debug, Clone)]
+pub struct IndexReport {
+    pub added: usize,
+    pub updated: usize,
+    pub deleted: usize,
+    pub unchanged: usize,
+    /// problems the file watcher reported while collecting this batch
+    pub errors: Vec<String>,
+}
+
+impl IndexReport {
+    /// Number of documents that were added, updated or deleted.
+    pub fn changes(&self) -> usize {
+        self.added + self.updated + self.deleted
+    }
+
+    pub(crate) fn record(&mut self, change: Change) {
+        match change {
+            Change::Added => self.added += 1,
+            Change::Updated => self.updated += 1,
+            Change::Unchanged => self.unchanged += 1,
+        }
+    }
+}
+
+impl std::fmt::Display for IndexReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} added, {} updated, {} deleted, {} unchanged", self.added, self.updated, self.deleted, self.unchanged)?;
+        for e in &self.errors { write!(f, "; watch error: {}", e)?; }
+        Ok(())
+    }
 }
 
-pub fn index_all(cfg: &mut Config) -> Result<()> {
-    let index_dir = index_path()?;
+pub(crate) enum Change { Added, Updated, Unchanged }
+
+pub fn index_all(ws: &mut Workspace) -> Result<IndexReport> {
+    let index_dir = ws.index_dir.clone();
     let (index, fields) = open_index(&index_dir)?;
     let mut writer = index.writer(256 * 1024 * 1024)?; // 256MB
-    let files = collect_files(&cfg.roots);
+    let old = Manifest::load(&index_dir)?;
+    let files = collect_files(&ws.config.roots)?;
+    let registry = Registry::new(&ws.config);
     let pb = ProgressBar::new(files.len() as u64);
     pb.set_style(ProgressStyle::with_template("{spinner:.green} [{elapsed_precise}] {bar:40.cyan/blue} {pos}/{len} {msg}")?.progress_chars("=>-"));
 
-    files.par_iter().for_each(|p| {
+    let seen: Vec<(String, FileEntry, Change)> = files.par_iter().filter_map(|p| {
         pb.set_message(p.to_string_lossy().to_string());
-        if let Ok(meta) = fs::metadata(p) {
-            if meta.len() > 20 * 1024 * 1024 { pb.inc(1); return; } // skip >20MB files by default
-            if let Ok(mut f) = fs::File::open(p) {
-                let mut buf = Vec::new();
-                if f.read_to_end(&mut buf).is_ok() {
-                    if !is_probably_text(&buf) { pb.inc(1); return; }
-                    let text = match String::from_utf8(buf) { Ok(s)=>s, Err(_)=>return };
-                    let name = p.file_name().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
-                    let modified = meta.modified().ok().and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok()).map(|d| d.as_secs() as i64).unwrap_or(0);
-                    let docu = doc!(
-                        fields.path => p.to_string_lossy().to_string(),
-                        fields.name => name,
-                        fields.content => text,
-                        fields.modified => modified
-                    );
-                    let _ = writer.add_document(docu);
-                }
-            }
-        }
+        let r = index_file(&writer, &fields, &ws.config, &registry, &old, p);
         pb.inc(1);
-    });
+        r
+    }).collect();
     pb.finish_with_message("Index built");
+
+    let mut report = IndexReport::default();
+    let mut manifest = Manifest::default();
+    for (key, entry, change) in seen {
+        report.record(change);
+        manifest.files.insert(key, entry);
+    }
+    for key in old.files.keys().filter(|k| !manifest.files.contains_key(*k)) {
+        writer.delete_term(Term::from_field_text(fields.container, key));
+        report.deleted += 1;
+    }
     writer.commit()?;
+    manifest.save(&index_dir)?;
     // Note: reload_searchers is not available in this version of tantivy
     // The index will be automatically reloaded when needed
 
-    // Build FST set for filenames
-    let names: Vec<String> = files.iter()
-        .filter_map(|p| p.file_name().map(|s| s.to_string_lossy().to_string()))
-        .collect();
-    let set = fst::Set::from_iter(names.iter()).context("build fst")?;
-    let fst_path = index_dir.join("names.fst");
-    fs::write(fst_path, set.as_fst().to_vec())?;
+    write_names(&index_dir, &manifest)?;
 
     // save checksum of roots list to skip redundant rebuilds later
     let mut hasher_input = String::new();
-    for r in &cfg.roots { hasher_input.push_str(&r.to_string_lossy()); }
-    cfg.last_scan = Some(xxh3_64(hasher_input.as_bytes()));
-    write_config(cfg)?;
-    Ok(())
+    for r in &ws.config.roots { hasher_input.push_str(&r.path.to_string_lossy()); }
+    ws.config.last_scan = Some(xxh3_64(hasher_input.as_bytes()));
+    ws.save_config()?;
+    Ok(report)
+}
+
+/// Re-extract `p` only if its stat or content hash differs from the manifest.
+/// Returns `None` for files that should not be tracked at all (unreadable or too large).
+pub(crate) fn index_file(writer: &IndexWriter, fields: &Fields, cfg: &Config, registry: &Registry, old: &Manifest, p: &Path) -> Option<(String, FileEntry, Change)> {
+    let key = p.to_string_lossy().to_string();
+    let meta = fs::metadata(p).ok()?;
+    let root = cfg.root_for(p);
+    let max_size = root.map_or(DEFAULT_MAX_FILE_SIZE, |r| r.settings.max_file_size);
+    if meta.len() > max_size { return None; }
+    let trigrams = root.is_some_and(|r| r.settings.trigrams);
+    let store_text = root.is_some_and(|r| r.settings.store_text);
+    let keep_case = root.is_some_and(|r| r.settings.keep_case);
+    let mtime = mtime_nanos(&meta);
+    // turning trigrams, stored text or kept case on or off for a root means indexing its files again
+    let same_settings = |e: &&FileEntry| e.trigrams == trigrams && e.store_text == store_text && e.keep_case == keep_case;
+    let prev = old.files.get(&key);
+    if let Some(e) = prev.filter(same_settings) {
+        if e.same_stat(meta.len(), mtime) { return Some((key, e.clone(), Change::Unchanged)); }
+    }
+    let mut buf = Vec::new();
+    fs::File::open(p).ok()?.read_to_end(&mut buf).ok()?;
+    let mut entry = FileEntry { size: meta.len(), mtime, hash: xxh3_64(&buf), members: Vec::new(), skipped: None, trigrams, store_text, keep_case };
+    match prev {
+        Some(e) if e.hash == entry.hash && same_settings(&e) => return Some((key, FileEntry { members: e.members.clone(), skipped: e.skipped.clone(), ..entry }, Change::Unchanged)),
+        // drops the members of an archive along with it
+        Some(_) => { writer.delete_term(Term::from_field_text(fields.container, &key)); }
+        None => {}
+    }
+    let change = if prev.is_some() { Change::Updated } else { Change::Added };
+    let file = FileMeta {
+        container: &key,
+        root: root.map_or(0, RootConfig::id),
+        modified: mtime / 1_000_000_000,
+        created: created_secs(&meta),
+        size: entry.size,
+        hash: entry.hash,
+        kind: "",
+        trigrams,
+        store_text,
+        keep_case,
+    };
+    if let Some(c) = registry.container(&key) {
+        let mut members = Vec::new();
+        c.for_each_document(&key, &buf, registry, &mut |d| {
+            let path = format!("{}{}{}", key, ARCHIVE_SEP, d.path);
+            if let Some(x) = d.text {
+                let member = FileMeta { size: d.bytes.len() as u64, hash: xxh3_64(d.bytes), kind: file_kind(&path, d.bytes, &x.extractor), ..file };
+                let _ = writer.add_document(text_doc(fields, cfg, &path, member, x));
+            }
+            members.push(d.path);
+        });
+        return Some((key, FileEntry { members, ..entry }, change));
+    }
+    // binary files are still tracked so they are not re-read on every run
+    let Some(x) = registry.extract(&key, &buf) else { return Some((key, entry, change)) };
+    entry.skipped = x.skipped.clone();
+    let kind = file_kind(&key, &buf, &x.extractor);
+    let _ = writer.add_document(text_doc(fields, cfg, &key, FileMeta { kind, ..file }, x));
+    Some((key, entry, change))
+}
+
+/// What is known about a document apart from its content: the file on disk it came from, and
+/// the size, hash and kind of the document itself.
+#[derive(Clone, Copy)]
+struct FileMeta<'a> {
+    container: &'a str,
+    root: u64,
+    /// seconds since the epoch
+    modified: i64,
+    created: Option<i64>,
+    size: u64,
+    hash: u64,
+    kind: &'static str,
+    /// also index the content as trigrams
+    trigrams: bool,
+    /// store the full text
+    store_text: bool,
+    /// also index content and name with their case kept
+    keep_case: bool,
+}
+
+fn text_doc(fields: &Fields, cfg: &Config, path: &str, file: FileMeta, x: Extracted) -> TantivyDocument {
+    let Extracted { text, encoding, compression, title, author, headings, mail, media, page_count, pages, cells, skipped, extractor, extractor_version } = x;
+    let name = Path::new(path).file_name().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let symbols = extract_symbols(path, &text);
+    let lines = text.lines().count() as u64;
+    let p = Path::new(path);
+    let ext = p.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+    let dir = p.parent().map(|d| d.to_string_lossy().to_string()).unwrap_or_default();
+    let mut d = doc!(
+        fields.path => path,
+        fields.container => file.container,
+        fields.name => name.as_str(),
+        fields.content => text.as_str(),
+        fields.modified => file.modified,
+        fields.encoding => encoding,
+        fields.ext => ext,
+        fields.dir => dir,
+        fields.size => file.size,
+        fields.root => file.root,
+        fields.lines => lines,
+        fields.hash => file.hash,
+        fields.kind => file.kind
+    );
+    if file.trigrams { d.add_text(fields.grams, &text); }
+    if file.keep_case {
+        d.add_text(fields.name_cased, &name);
+        d.add_text(fields.content_cased, &text);
+    }
+    // text that fits in an excerpt costs no more stored whole, and then never has to be read again
+    if file.store_text || text.len() <= cfg.excerpt_bytes {
+        d.add_text(fields.text, &text);
+    } else if cfg.excerpt_bytes > 0 {
+        d.add_text(fields.excerpt, truncate_at_char(&text, cfg.excerpt_bytes));
+    }
+    if let Some(t) = file.created { d.add_i64(fields.created, t); }
+    if let Some(c) = compression { d.add_text(fields.compression, c.name()); }
+    if let Some(t) = title { d.add_text(fields.title, t); }
+    if let Some(a) = author { d.add_text(fields.author, a); }
+    for h in headings { d.add_text(fields.headings, h); }
+    if let Some(m) = mail {
+        d.add_text(fields.from, m.from);
+        d.add_text(fields.to, m.to);
+        d.add_text(fields.subject, m.subject);
+        if let Some(t) = m.date { d.add_date(fields.date, DateTime::from_timestamp_secs(t)); }
+    }
+    if let Some(m) = media {
+        for (f, v) in [(fields.camera, m.camera), (fields.lens, m.lens), (fields.taken, m.taken), (fields.artist, m.artist), (fields.album, m.album)] {
+            if let Some(v) = v { d.add_text(f, v); }
+        }
+        for (f, v) in [(fields.width, m.width), (fields.height, m.height), (fields.duration, m.duration)] {
+            if let Some(v) = v { d.add_u64(f, v); }
+        }
+        d.add_bool(fields.gps, m.gps);
+    }
+    if let Some(n) = page_count { d.add_u64(fields.page_count, n); }
+    for line in pages { d.add_u64(fields.pages, line as u64); }
+    for (line, kind) in cells {
+        d.add_u64(fields.cells, line as u64);
+        d.add_text(fields.cell_types, kind);
+    }
+    if let Some(r) = skipped { d.add_text(fields.skipped, r); }
+    d.add_text(fields.extractor, extractor);
+    d.add_text(fields.extractor_version, extractor_version);
+    for s in symbols {
+        d.add_text(fields.symbol_defs, s.encode());
+        d.add_text(fields.symbol, s.name);
+    }
+    d
+}
+
+/// Longest prefix of `s` that is at most `max` bytes and ends on a char boundary.
+pub fn truncate_at_char(s: &str, max: usize) -> &str {
+    if s.len() <= max { return s; }
+    let mut end = max;
+    while !s.is_char_boundary(end) { end -= 1; }
+    &s[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    fn report(ws: &mut Workspace) -> (usize, usize, usize, usize) {
+        let r = index_all(ws).unwrap();
+        (r.added, r.updated, r.deleted, r.unchanged)
+    }
+
+    #[test]
+    fn index_all_accounts_for_every_file() {
+        let (home, root) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let file = |n: &str| root.path().join(n);
+        fs::write(file("a.txt"), "alpha").unwrap();
+        fs::write(file("b.txt"), "beta").unwrap();
+        fs::write(file("c.txt"), "gamma").unwrap();
+        let mut ws = Workspace::at(home.path()).unwrap();
+        ws.config.roots.push(RootConfig::new(root.path().to_path_buf()));
+
+        assert_eq!(report(&mut ws), (3, 0, 0, 0));
+        assert_eq!(report(&mut ws), (0, 0, 0, 3));
+
+        // a new mtime alone means reading the file again, but its hash shows nothing changed
+        let later = SystemTime::now() + Duration::from_secs(60);
+        fs::File::options().write(true).open(file("a.txt")).unwrap().set_modified(later).unwrap();
+        fs::write(file("b.txt"), "beta, edited").unwrap();
+        fs::remove_file(file("c.txt")).unwrap();
+        fs::write(file("d.txt"), "delta").unwrap();
+        assert_eq!(report(&mut ws), (1, 1, 1, 1));
+
+        let manifest = Manifest::load(&ws.index_dir).unwrap();
+        let names: Vec<&str> = manifest.files.keys().map(|k| k.rsplit('/').next().unwrap()).collect();
+        assert_eq!(names, ["a.txt", "b.txt", "d.txt"]);
+        assert_eq!(manifest.files[&file("b.txt").to_string_lossy().to_string()].size, 12);
+    }
+
+    #[test]
+    fn an_index_from_before_the_schema_grew_is_rebuilt() {
+        let (home, root) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        fs::write(root.path().join("notes.txt"), "migrated content").unwrap();
+        let mut ws = Workspace::at(home.path()).unwrap();
+        ws.config.roots.push(RootConfig::new(root.path().to_path_buf()));
+        // the schema warpseek started out with, and no manifest
+        let mut schema = Schema::builder();
+        let path = schema.add_text_field("path", STRING | STORED);
+        let name = schema.add_text_field("name", TEXT | STORED);
+        let content = schema.add_text_field("content", TEXT);
+        let modified = schema.add_i64_field("modified", FAST | STORED);
+        fs::create_dir_all(&ws.index_dir).unwrap();
+        let old = Index::create_in_dir(&ws.index_dir, schema.build()).unwrap();
+        let mut writer: IndexWriter = old.writer_with_num_threads(1, 15_000_000).unwrap();
+        writer.add_document(doc!(path => "/gone/old.txt", name => "old.txt", content => "stale", modified => 0i64)).unwrap();
+        writer.commit().unwrap();
+        drop(writer);
+
+        assert!(crate::query::open_existing_index(&ws.index_dir).err().unwrap().to_string().contains("run `warpseek index`"));
+        assert_eq!(upgrade_index(&mut ws).unwrap().map(|r| r.added), Some(1));
+        assert_eq!(upgrade_index(&mut ws).unwrap().map(|r| r.added), None);
+        let hits = crate::query::query_index(&ws, "migrated", &Default::default()).unwrap();
+        assert_eq!(hits.iter().map(|h| h.path.rsplit('/').next().unwrap()).collect::<Vec<_>>(), ["notes.txt"]);
+        assert!(crate::query::query_index(&ws, "stale", &Default::default()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn index_all_counts_nothing_for_an_empty_root() {
+        let (home, root) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let mut ws = Workspace::at(home.path()).unwrap();
+        ws.config.roots.push(RootConfig::new(root.path().to_path_buf()));
+        assert_eq!(report(&mut ws), (0, 0, 0, 0));
+        assert!(Manifest::load(&ws.index_dir).unwrap().files.is_empty());
+    }
 }
@@ -0,0 +1,445 @@
+use anyhow::Result;
+use rayon::prelude::*;
+use regex::Regex;
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::OnceLock;
+use tantivy::{ReloadPolicy, TantivyDocument, Term, collector::DocSetCollector, query::{BooleanQuery, Occur, Query, TermQuery}, schema::{IndexRecordOption, Value}};
+
+use crate::fuzzy::fuzzy_match;
+use crate::query::open_existing_index;
+use crate::workspace::Workspace;
+
+/// Lines longer than this are minified or generated; their braces count, but no symbols are matched.
+const MAX_LINE: usize = 1000;
+/// Most symbols kept from one file.
+const MAX_SYMBOLS: usize = 10_000;
+const BONUS_EXACT_NAME: i64 = 64;
+
+/// A definition in a source file.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Symbol {
+    /// `function`, `method`, `struct`, `enum`, `trait`, `interface`, `class`, `type`,
+    /// `constant`, `module` or `macro`
+    pub kind: String,
+    pub name: String,
+    /// 1-based
+    pub line: usize,
+    /// the type, class or module the definition sits in, empty at the top level
+    pub container: String,
+}
+
+impl Symbol {
+    /// Stored form: the fields joined by tabs, which none of them can contain.
+    pub fn encode(&self) -> String {
+        format!("{}\t{}\t{}\t{}", self.kind, self.name, self.line, self.container)
+    }
+
+    pub fn decode(s: &str) -> Option<Symbol> {
+        let mut it = s.splitn(4, '\t');
+        let (kind, name, line) = (it.next()?, it.next()?, it.next()?.parse().ok()?);
+        Some(Symbol { kind: kind.to_string(), name: name.to_string(), line, container: it.next().unwrap_or("").to_string() })
+    }
+}
+
+/// How a language nests definitions: by `{}` blocks or by indentation.
+#[derive(Clone, Copy, PartialEq)]
+enum Scoping { Braces, Indent }
+
+struct Rule {
+    /// empty for things that only contain definitions, like a Rust `impl` block
+    kind: &'static str,
+    /// whether definitions that follow, inside its block, belong to it
+    scope: bool,
+    /// only matches directly inside a class-like container, e.g. Java methods
+    member: bool,
+    /// has a `name` group, and for Go methods a `recv` group naming the container
+    re: Regex,
+}
+
+struct Lang {
+    exts: &'static [&'static str],
+    scoping: Scoping,
+    /// tried in order; the first that matches a line wins
+    rules: Vec<Rule>,
+}
+
+/// Containers whose functions are methods.
+const CLASS_LIKE: &[&str] = &["class", "struct", "enum", "trait", "interface", "impl"];
+/// Words that look like a call or a definition to the C-family patterns but are neither.
+const KEYWORDS: &[&str] = &["if", "else", "for", "foreach", "while", "do", "switch", "case", "catch", "return", "sizeof", "throw", "try", "using", "lock", "synchronized", "await", "yield", "function"];
+
+fn rule(kind: &'static str, scope: bool, re: &str) -> Rule {
+    Rule { kind, scope, member: false, re: Regex::new(re).expect("symbol pattern") }
+}
+
+fn member(kind: &'static str, re: &str) -> Rule {
+    Rule { member: true, ..rule(kind, true, re) }
+}
+
+fn langs() -> &'static [Lang] {
+    static LANGS: OnceLock<Vec<Lang>> = OnceLock::new();
+    LANGS.get_or_init(|| {
+        let vis = r"(?:pub(?:\s*\([^)]*\))?\s+)?";
+        let java_mods = r"(?:(?:public|private|protected|internal|static|final|abstract|sealed|partial|strictfp|synchronized|native|virtual|override|async|default|extern|unsafe|new|readonly)\s+)*";
+        vec![
+            Lang { exts: &["rs"], scoping: Scoping::Braces, rules: vec![
+                rule("function", true, &format!(r"^\s*{vis}(?:(?:const|async|unsafe|default|extern\s*(?:\x22[^\x22]*\x22)?)\s+)*fn\s+(?P<name>\w+)")),
+                rule("struct", true, &format!(r"^\s*{vis}(?:struct|union)\s+(?P<name>\w+)")),
+                rule("enum", true, &format!(r"^\s*{vis}enum\s+(?P<name>\w+)")),
+                rule("trait", true, &format!(r"^\s*{vis}(?:unsafe\s+)?(?:auto\s+)?trait\s+(?P<name>\w+)")),
+                rule("type", false, &format!(r"^\s*{vis}type\s+(?P<name>\w+)")),
+                rule("constant", false, &format!(r"^\s*{vis}(?:const|static(?:\s+mut)?)\s+(?P<name>\w+)\s*:")),
+                rule("module", true, &format!(r"^\s*{vis}mod\s+(?P<name>\w+)")),
+                rule("macro", false, r"^\s*(?:#\[macro_export\]\s*)?macro_rules!\s*(?P<name>\w+)"),
+                rule("", true, r"^\s*(?:unsafe\s+)?impl\b(?:\s*<.*?>)?\s+(?:!?[\w:]+(?:<.*?>)?\s+for\s+)?(?:dyn\s+)?(?P<name>[\w:]+)"),
+            ] },
+            Lang { exts: &["py", "pyi", "pyw"], scoping: Scoping::Indent, rules: vec![
+                rule("function", true, r"^\s*(?:async\s+)?def\s+(?P<name>\w+)"),
+                rule("class", true, r"^\s*class\s+(?P<name>\w+)"),
+                rule("constant", false, r"^(?P<name>[A-Z][A-Z0-9_]*)\s*(?::[^=]*)?=[^=]"),
+            ] },
+            Lang { exts: &["js", "jsx", "mjs", "cjs", "ts", "tsx", "mts", "cts"], scoping: Scoping::Braces, rules: vec![
+                rule("function", true, r"^\s*(?:export\s+)?(?:default\s+)?(?:declare\s+)?(?:async\s+)?function\s*\*?\s*(?P<name>[\w$]+)"),
+                rule("class", true, r"^\s*(?:export\s+)?(?:default\s+)?(?:declare\s+)?(?:abstract\s+)?class\s+(?P<name>[\w$]+)"),
+                rule("interface", true, r"^\s*(?:export\s+)?(?:declare\s+)?interface\s+(?P<name>[\w$]+)"),
+                rule("type", false, r"^\s*(?:export\s+)?(?:declare\s+)?type\s+(?P<name>[\w$]+)\s*(?:<.*>)?\s*="),
+                rule("enum", true, r"^\s*(?:export\s+)?(?:declare\s+)?(?:const\s+)?enum\s+(?P<name>[\w$]+)"),
+                rule("module", true, r"^\s*(?:export\s+)?(?:declare\s+)?(?:namespace|module)\s+(?P<name>[\w$.]+)\s*\{"),
+                rule("function", true, r"^\s*(?:export\s+)?(?:const|let|var)\s+(?P<name>[\w$]+)\s*(?::[^=]+)?=\s*(?:async\s+)?(?:function\b|\([^)]*\)\s*(?::[^=]+)?=>|[\w$]+\s*=>)"),
+                rule("constant", false, r"^\s*(?:export\s+)?const\s+(?P<name>[A-Z][A-Z0-9_$]*)\s*(?::[^=]+)?="),
+                member("method", r"^\s*(?:(?:public|private|protected|static|async|readonly|override|abstract|get|set)\s+)*\*?(?P<name>#?[\w$]+)\s*(?:<[^>]*>)?\s*\([^;]*$"),
+            ] },
+            Lang { exts: &["go"], scoping: Scoping::Braces, rules: vec![
+                rule("method", true, r"^func\s*\(\s*(?:\w+\s+)?\*?\s*(?P<recv>\w+)[^)]*\)\s*(?P<name>\w+)"),
+                rule("function", true, r"^func\s+(?P<name>\w+)"),
+                rule("struct", false, r"^type\s+(?P<name>\w+)\s*(?:\[.*\])?\s+struct\b"),
+                rule("interface", false, r"^type\s+(?P<name>\w+)\s*(?:\[.*\])?\s+interface\b"),
+                rule("type", false, r"^type\s+(?P<name>\w+)"),
+                rule("constant", false, r"^const\s+(?P<name>\w+)"),
+                rule("module", false, r"^package\s+(?P<name>\w+)"),
+            ] },
+            Lang { exts: &["c", "h", "cc", "cpp", "cxx", "c++", "hh", "hpp", "hxx", "h++", "m", "mm"], scoping: Scoping::Braces, rules: vec![
+                rule("macro", false, r"^\s*#\s*define\s+(?P<name>\w+)"),
+                rule("module", true, r"^\s*(?:inline\s+)?namespace\s+(?P<name>[\w:]+)\s*\{?\s*$"),
+                rule("class", true, r"^\s*(?:template\s*<.*>\s*)?class\s+(?:\w+\s+)*?(?P<name>\w+)\s*(?:final\s*)?(?::[^;]*)?\{?\s*$"),
+                rule("struct", true, r"^\s*(?:typedef\s+)?(?:template\s*<.*>\s*)?(?:struct|union)\s+(?P<name>\w+)\s*(?::[^;]*)?\{?\s*$"),
+                rule("enum", true, r"^\s*(?:typedef\s+)?enum\s+(?:class\s+|struct\s+)?(?P<name>\w+)\s*(?::\s*[\w:]+\s*)?\{?\s*$"),
+                rule("function", true, r"^(?:[\w:<>,]+[\s*&]+)+(?P<name>~?[A-Za-z_][\w:~]*)\s*\([^;]*$"),
+                member("method", r"^\s+(?:(?:static|virtual|inline|explicit|constexpr|friend)\s+)*(?:[\w:<>,]+[\s*&]+)*(?P<name>~?[A-Za-z_]\w*)\s*\([^;]*$"),
+            ] },
+            Lang { exts: &["java", "cs", "scala"], scoping: Scoping::Braces, rules: vec![
+                rule("module", false, r"^\s*package\s+(?P<name>[\w.]+)"),
+                rule("module", true, r"^\s*namespace\s+(?P<name>[\w.]+)"),
+                rule("class", true, &format!(r"^\s*(?:@\w+\s+)*{java_mods}(?:case\s+)?(?:class|record|object)\s+(?P<name>\w+)")),
+                rule("interface", true, &format!(r"^\s*(?:@\w+\s+)*{java_mods}(?:interface|@interface|trait)\s+(?P<name>\w+)")),
+                rule("enum", true, &format!(r"^\s*{java_mods}enum\s+(?P<name>\w+)")),
+                rule("struct", true, &format!(r"^\s*{java_mods}struct\s+(?P<name>\w+)")),
+                rule("function", true, r"^\s*(?:(?:private|protected|override|final|implicit)\s+)*def\s+(?P<name>\w+)"),
+                rule("constant", false, r"^\s*(?:(?:public|private|protected|internal)\s+)?(?:static\s+final|final\s+static|const)\s+[\w<>\[\],.?]+\s+(?P<name>\w+)\s*="),
+                member("method", &format!(r"^\s+(?:@\w+\s+)*{java_mods}(?:<[^>]*>\s+)?(?:[\w<>\[\],.?]+\s+)?(?P<name>\w+)\s*\([^;]*$")),
+            ] },
+            Lang { exts: &["kt", "kts"], scoping: Scoping::Braces, rules: vec![
+                rule("function", true, r"^\s*(?:(?:public|private|protected|internal|override|open|abstract|inline|suspend|operator|infix|tailrec)\s+)*fun\s+(?:<[^>]*>\s*)?(?:[\w.]+\.)?(?P<name>\w+)"),
+                rule("interface", true, r"^\s*(?:(?:public|private|protected|internal|sealed|fun)\s+)*interface\s+(?P<name>\w+)"),
+                rule("enum", true, r"^\s*(?:(?:public|private|protected|internal)\s+)*enum\s+class\s+(?P<name>\w+)"),
+                rule("class", true, r"^\s*(?:(?:public|private|protected|internal|data|sealed|abstract|open|inner|value|annotation)\s+)*(?:class|object)\s+(?P<name>\w+)"),
+                rule("type", false, r"^\s*(?:(?:public|private|protected|internal)\s+)*typealias\s+(?P<name>\w+)"),
+                rule("constant", false, r"^\s*(?:(?:public|private|protected|internal)\s+)*const\s+val\s+(?P<name>\w+)"),
+                rule("module", false, r"^\s*package\s+(?P<name>[\w.]+)"),
+            ] },
+            Lang { exts: &["rb", "rake"], scoping: Scoping::Indent, rules: vec![
+                rule("function", true, r"^\s*def\s+(?:self\.)?(?P<name>[\w?!=]+)"),
+                rule("class", true, r"^\s*class\s+(?P<name>[\w:]+)"),
+                rule("module", true, r"^\s*module\s+(?P<name>[\w:]+)"),
+                rule("constant", false, r"^\s*(?P<name>[A-Z][A-Z0-9_]*)\s*=[^=]"),
+            ] },
+        ]
+    })
+}
+
+fn lang_for(path: &str) -> Option<&'static Lang> {
+    let ext = Path::new(path).extension()?.to_string_lossy().to_ascii_lowercase();
+    langs().iter().find(|l| l.exts.contains(&ext.as_str()))
+}
+
+/// The definitions in `text`, read from `path`, whose extension picks the language. Files in
+/// languages that aren't known have none.
+pub fn extract_symbols(path: &str, text: &str) -> Vec<Symbol> {
+    let Some(lang) = lang_for(path) else { return Vec::new() };
+    let mut symbols = Vec::new();
+    // open containers: name, kind, and the brace depth or indentation of their body
+    let mut stack: Vec<(String, &'static str, usize)> = Vec::new();
+    let mut pending: Option<(String, &'static str)> = None;
+    let (mut depth, mut in_comment) = (0usize, false);
+    for (i, line) in text.lines().enumerate() {
+        if symbols.len() >= MAX_SYMBOLS { break; }
+        let long = line.len() > MAX_LINE;
+        let indent = line.len() - line.trim_start().len();
+        if lang.scoping == Scoping::Indent {
+            if line.trim().is_empty() || line.trim_start().starts_with('#') { continue; }
+            while stack.last().is_some_and(|&(_, _, at)| at >= indent) { stack.pop(); }
+        }
+        let starts_in_comment = in_comment;
+        if !starts_in_comment && !long {
+            if let Some((name, kind, scope)) = match_line(lang, line, stack.last()) {
+                let mut container = stack.last().map(|(n, _, _)| n.clone()).unwrap_or_default();
+                let mut qualified = false;
+                let name = if let Some((recv, n)) = name.split_once('\t') {
+                    container = recv.to_string();
+                    n.to_string()
+                } else if let Some((c, n)) = name.rsplit_once("::").filter(|_| !kind.is_empty()) {
+                    // out-of-line C++ definitions like `Widget::draw` name their class
+                    container = c.to_string();
+                    qualified = true;
+                    n.to_string()
+                } else {
+                    name
+                };
+                let parent = stack.last().map_or("", |&(_, k, _)| k);
+                let kind = if kind == "function" && (qualified || CLASS_LIKE.contains(&parent)) { "method" } else { kind };
+                if !kind.is_empty() {
+                    symbols.push(Symbol { kind: kind.to_string(), name: name.clone(), line: i + 1, container });
+                }
+                if scope {
+                    let kind = if kind.is_empty() { "impl" } else { kind };
+                    match lang.scoping {
+                        Scoping::Indent => stack.push((name, kind, indent)),
+                        Scoping::Braces => pending = Some((name, kind)),
+                    }
+                }
+            }
+        }
+        if lang.scoping == Scoping::Braces {
+            for c in code_chars(line, &mut in_comment) {
+                match c {
+                    '{' => {
+                        depth += 1;
+                        if let Some((name, kind)) = pending.take() { stack.push((name, kind, depth)); }
+                    }
+                    '}' => {
+                        if stack.last().is_some_and(|&(_, _, at)| at == depth) { stack.pop(); }
+                        depth = depth.saturating_sub(1);
+                    }
+                    // a declaration without a body, like `struct Unit;` or a trait method
+                    ';' => pending = None,
+                    _ => {}
+                }
+            }
+        }
+    }
+    symbols
+}
+
+/// The name, kind and whether it opens a scope, for the first rule matching `line`. Go method
+/// receivers come back as `recv\tname`.
+fn match_line(lang: &Lang, line: &str, parent: Option<&(String, &'static str, usize)>) -> Option<(String, &'static str, bool)> {
+    let in_class = parent.is_some_and(|&(_, k, _)| CLASS_LIKE.contains(&k));
+    for r in &lang.rules {
+        if r.member && !in_class { continue; }
+        let Some(c) = r.re.captures(line) else { continue };
+        let Some(name) = c.name("name").map(|m| m.as_str()) else { continue };
+        if KEYWORDS.contains(&name.trim_start_matches('~')) { continue; }
+        let name = match c.name("recv") {
+            Some(recv) => format!("{}\t{}", recv.as_str(), name),
+            None => name.to_string(),
+        };
+        return Some((name, r.kind, r.scope));
+    }
+    None
+}
+
+/// The chars of `line` outside comments, string literals and char literals, carrying `/* */`
+/// comments over to the next line through `in_comment`.
+fn code_chars(line: &str, in_comment: &mut bool) -> Vec<char> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let (c, next) = (chars[i], chars.get(i + 1).copied());
+        if *in_comment {
+            if c == '*' && next == Some('/') { *in_comment = false; i += 1; }
+        } else if c == '/' && next == Some('/') {
+            break;
+        } else if c == '/' && next == Some('*') {
+            *in_comment = true;
+            i += 1;
+        } else if c == '"' || c == '`' {
+            i += 1;
+            while i < chars.len() && chars[i] != c {
+                if chars[i] == '\\' { i += 1; }
+                i += 1;
+            }
+        } else if c == '\'' && (next == Some('\\') || chars.get(i + 2) == Some(&'\'')) {
+            // a char literal; a lone `'` is a Rust lifetime
+            i += if next == Some('\\') { 3 } else { 2 };
+            while i < chars.len() && chars[i] != '\'' { i += 1; }
+        } else {
+            out.push(c);
+        }
+        i += 1;
+    }
+    out
+}
+
+/// A symbol matched by `ws sym`. `positions` are char indices into `name` that matched the pattern.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SymbolHit {
+    pub kind: String,
+    pub name: String,
+    pub path: String,
+    pub line: usize,
+    pub container: String,
+    pub score: i64,
+    pub positions: Vec<usize>,
+}
+
+/// Rank the indexed definitions by fuzzy-matching `pattern` against their names, keeping only
+/// those of `kind` if given. Names are matched straight off the `symbol` field's term
+/// dictionary, so only the documents defining the best names are read.
+pub fn find_symbols(ws: &Workspace, pattern: &str, kind: Option<&str>, top: usize) -> Result<Vec<SymbolHit>> {
+    let (index, fields) = open_existing_index(&ws.index_dir)?;
+    let reader = index.reader_builder().reload_policy(ReloadPolicy::Manual).try_into()?;
+    let searcher = reader.searcher();
+    if top == 0 { return Ok(Vec::new()); }
+
+    let mut names = Vec::new();
+    for segment in searcher.segment_readers() {
+        let inverted = segment.inverted_index(fields.symbol)?;
+        let mut terms = inverted.terms().stream()?;
+        while terms.advance() {
+            if let Ok(name) = std::str::from_utf8(terms.key()) { names.push(name.to_string()); }
+        }
+    }
+    names.sort_unstable();
+    names.dedup();
+    let mut matched: Vec<(String, i64, Vec<usize>)> = names.into_par_iter().filter_map(|name| {
+        let (score, positions) = fuzzy_match(pattern, &name)?;
+        let exact = if name.eq_ignore_ascii_case(pattern.trim()) { BONUS_EXACT_NAME } else { 0 };
+        Some((name, score + exact, positions))
+    }).collect();
+    matched.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.len().cmp(&b.0.len())).then_with(|| a.0.cmp(&b.0)));
+    // names of deleted documents linger in the dictionary until a merge, and the kind filter
+    // drops some more, so look at a few more names than needed
+    matched.truncate(top.saturating_mul(4));
+    if matched.is_empty() { return Ok(Vec::new()); }
+
+    let clauses: Vec<(Occur, Box<dyn Query>)> = matched.iter().map(|(name, _, _)| {
+        let q: Box<dyn Query> = Box::new(TermQuery::new(Term::from_field_text(fields.symbol, name), IndexRecordOption::Basic));
+        (Occur::Should, q)
+    }).collect();
+    let by_name: HashMap<&str, (i64, &Vec<usize>)> = matched.iter().map(|(n, s, p)| (n.as_str(), (*s, p))).collect();
+    let mut hits = Vec::new();
+    for addr in searcher.search(&BooleanQuery::new(clauses), &DocSetCollector)? {
+        let doc: TantivyDocument = searcher.doc(addr)?;
+        let path = doc.get_first(fields.path).and_then(|v| v.as_str()).unwrap_or("").to_string();
+        for sym in doc.get_all(fields.symbol_defs).filter_map(|v| v.as_str()).filter_map(Symbol::decode) {
+            let Some(&(score, positions)) = by_name.get(sym.name.as_str()) else { continue };
+            if kind.is_some_and(|k| k != sym.kind) { continue; }
+            hits.push(SymbolHit { kind: sym.kind, name: sym.name, path: path.clone(), line: sym.line, container: sym.container, score, positions: positions.clone() });
+        }
+    }
+    hits.sort_by(|a, b| b.score.cmp(&a.score)
+        .then(a.name.len().cmp(&b.name.len()))
+        .then_with(|| a.name.cmp(&b.name))
+        .then_with(|| a.path.cmp(&b.path))
+        .then(a.line.cmp(&b.line)));
+    hits.truncate(top);
+    Ok(hits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `kind name:line` for each symbol, with `in container` when there is one.
+    fn syms(path: &str, src: &str) -> Vec<String> {
+        extract_symbols(path, src).into_iter().map(|s| {
+            let at = format!("{} {}:{}", s.kind, s.name, s.line);
+            if s.container.is_empty() { at } else { format!("{} in {}", at, s.container) }
+        }).collect()
+    }
+
+    #[test]
+    fn rust() {
+        let src = "pub struct Parser<'a> {\n    src: &'a str,\n}\n\nimpl<'a> Parser<'a> {\n    pub fn new(src: &'a str) -> Self { Parser { src } }\n    // fn commented() {}\n    fn brace(&self) -> char { '{' }\n}\n/*\nfn hidden() {}\n*/\npub trait Visit {\n    fn visit(&self);\n}\nfn free() {}\npub(crate) const MAX: usize = 3;\nmacro_rules! m { () => {} }\nmod inner { pub enum E { A } }\n";
+        assert_eq!(syms("lib.rs", src), [
+            "struct Parser:1",
+            "method new:6 in Parser",
+            "method brace:8 in Parser",
+            "trait Visit:13",
+            "method visit:14 in Visit",
+            "function free:16",
+            "constant MAX:17",
+            "macro m:18",
+            "module inner:19",
+        ]);
+    }
+
+    #[test]
+    fn python() {
+        let src = "MAX_SIZE = 10\nclass Shape:\n    def area(self):\n        pass\n\n    # def commented(self):\n    class Inner:\n        def f(self): pass\nasync def top():\n    if MAX_SIZE == 10:\n        pass\n";
+        assert_eq!(syms("shapes.py", src), [
+            "constant MAX_SIZE:1",
+            "class Shape:2",
+            "method area:3 in Shape",
+            "class Inner:7 in Shape",
+            "method f:8 in Inner",
+            "function top:9",
+        ]);
+    }
+
+    #[test]
+    fn go() {
+        let src = "package main\n\ntype Server struct {\n}\n\nfunc (s *Server) Start() error {\n\treturn nil\n}\n\nfunc main() {\n}\n";
+        assert_eq!(syms("main.go", src), ["module main:1", "struct Server:3", "method Start:6 in Server", "function main:10"]);
+    }
+
+    #[test]
+    fn cpp() {
+        let src = "#define MAX 10\nnamespace app {\nclass Widget : public Base {\n  public:\n    void draw();\n    int size() const {\n        return 1;\n    }\n};\n}\nvoid Widget::draw() {\n    if (x) {\n    }\n}\n";
+        assert_eq!(syms("widget.cpp", src), ["macro MAX:1", "module app:2", "class Widget:3 in app", "method size:6 in Widget", "method draw:11 in Widget"]);
+    }
+
+    #[test]
+    fn typescript() {
+        let src = "export class Api {\n  async get(url: string) {\n    if (url) {\n    }\n  }\n}\nexport const handler = async (req) => {\n};\nconst MAX_RETRIES = 3;\ninterface Opts { a: string }\nconst s = \"function fake() {\";\n";
+        assert_eq!(syms("api.ts", src), ["class Api:1", "method get:2 in Api", "function handler:7", "constant MAX_RETRIES:9", "interface Opts:10"]);
+    }
+
+    #[test]
+    fn java() {
+        let src = "package com.example;\n\npublic class App {\n    private static final int LIMIT = 5;\n    public static void main(String[] args) {\n        for (int i = 0; i < 3; i++) {\n        }\n        while (true) {\n        }\n    }\n}\n";
+        assert_eq!(syms("App.java", src), ["module com.example:1", "class App:3", "constant LIMIT:4 in App", "method main:5 in App"]);
+    }
+
+    #[test]
+    fn ruby() {
+        let src = "module Billing\n  class Invoice\n    TAX = 0.2\n    def total\n    end\n  end\nend\n";
+        assert_eq!(syms("invoice.rb", src), ["module Billing:1", "class Invoice:2 in Billing", "constant TAX:3 in Invoice", "method total:4 in Invoice"]);
+    }
+
+    #[test]
+    fn unknown_empty_and_overlong_input() {
+        assert!(syms("notes.txt", "fn looks_like_rust() {}").is_empty());
+        assert!(syms("Makefile", "fn x() {}").is_empty());
+        assert!(syms("lib.rs", "").is_empty());
+        let long = format!("fn minified() {{ {} }}\nfn kept() {{}}\n", "x;".repeat(MAX_LINE));
+        assert_eq!(syms("lib.rs", &long), ["function kept:2"]);
+        // braces on an overlong line still open and close scopes
+        let generated = format!("impl Outer {{\n    static DATA: Data = Data {{ xs: &[{}\n    ]}};\n    fn method() {{}}\n}}\nfn after() {{}}\n", "0, ".repeat(MAX_LINE));
+        assert_eq!(syms("lib.rs", &generated), ["method method:4 in Outer", "function after:6"]);
+        // an unbalanced closing brace does not underflow
+        assert_eq!(syms("lib.rs", "}\n}\nfn after() {}\n"), ["function after:3"]);
+    }
+
+    #[test]
+    fn stored_form_round_trips() {
+        let s = Symbol { kind: "method".into(), name: "draw".into(), line: 11, container: "Widget".into() };
+        assert_eq!(Symbol::decode(&s.encode()), Some(s));
+        assert_eq!(Symbol::decode("function\tmain\t3").map(|s| s.container), Some(String::new()));
+        assert_eq!(Symbol::decode("function\tmain\tthree\t"), None);
+        assert_eq!(Symbol::decode("function"), None);
+    }
+}
@@ -0,0 +1,367 @@
+use anyhow::{anyhow, Result};
+use ignore::{Match, gitignore::{Gitignore, GitignoreBuilder}, overrides::Override, types::Types};
+use notify::{Event, EventKind, RecursiveMode, Watcher, event::ModifyKind};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::time::{Duration, Instant};
+use tantivy::{IndexWriter, TantivyError, Term};
+
+use crate::extract::Registry;
+use crate::manifest::Manifest;
+use crate::names::write_names;
+use crate::roots::{RootConfig, RootSettings, SKIP_DIRS};
+use crate::search::*;
+use crate::workspace::Workspace;
+
+/// Quiet period after the last event before a batch is applied.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+/// Upper bound on how long a continuous burst of events can delay a commit.
+const MAX_BATCH_DELAY: Duration = Duration::from_secs(5);
+
+/// Applies the same rules as `collect_files` to single paths reported by the watcher,
+/// for one root and its settings.
+pub struct IgnoreFilter {
+    settings: RootSettings,
+    types: Option<Types>,
+    overrides: Override,
+    global: Gitignore,
+    dirs: HashMap<PathBuf, Vec<Gitignore>>,
+    #[cfg(unix)]
+    device: Option<u64>,
+}
+
+impl IgnoreFilter {
+    /// `root` must have an absolute, canonical path, like the paths later passed to `is_included`.
+    pub fn new(root: &RootConfig) -> Result<IgnoreFilter> {
+        let global = if root.settings.honours(".gitignore") { Gitignore::global().0 } else { Gitignore::empty() };
+        Ok(IgnoreFilter {
+            settings: root.settings.clone(),
+            types: root.types()?,
+            overrides: root.overrides()?,
+            global,
+            dirs: HashMap::new(),
+            #[cfg(unix)]
+            device: std::fs::metadata(&root.path).ok().map(|m| std::os::unix::fs::MetadataExt::dev(&m)),
+        })
+    }
+
+    /// Forget cached rules for `dir`, e.g. after one of its ignore files changed.
+    pub fn invalidate(&mut self, dir: &Path) {
+        self.dirs.remove(dir);
+    }
+
+    pub fn is_ignore_file(&self, name: &str) -> bool {
+        self.settings.honours(name) || self.settings.custom_ignore_files().any(|f| f == name)
+    }
+
+    fn rules(&mut self, dir: &Path) -> &[Gitignore] {
+        let settings = &self.settings;
+        self.dirs.entry(dir.to_path_buf()).or_insert_with(|| {
+            // same precedence as the walker: custom ignore files, .ignore, .gitignore, .git/info/exclude
+            let mut names: Vec<&str> = settings.custom_ignore_files().collect();
+            if settings.honours(".ignore") { names.push(".ignore"); }
+            if settings.honours(".gitignore") { names.extend([".gitignore", ".git/info/exclude"]); }
+            names.iter()
+                .map(|n| dir.join(n))
+                .filter(|f| f.is_file())
+                .filter_map(|f| {
+                    let mut b = GitignoreBuilder::new(dir);
+                    b.add(f);
+                    b.build().ok()
+                })
+                .collect()
+        })
+    }
+
+    fn ignored_by_rules(&mut self, path: &Path, is_dir: bool) -> bool {
+        for dir in path.ancestors().skip(1) {
+            for gi in self.rules(dir) {
+                match gi.matched(path, is_dir) {
+                    Match::Ignore(_) => return true,
+                    Match::Whitelist(_) => return false,
+                    Match::None => {}
+                }
+            }
+        }
+        self.global.matched(path, is_dir).is_ignore()
+    }
+
+    #[cfg(unix)]
+    fn on_root_device(&self, path: &Path) -> bool {
+        use std::os::unix::fs::MetadataExt;
+        match (self.device, std::fs::symlink_metadata(path)) {
+            (Some(dev), Ok(m)) => m.dev() == dev,
+            _ => true,
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn on_root_device(&self, _path: &Path) -> bool {
+        true
+    }
+
+    /// Would walking `root` have yielded `path`? Both must be absolute.
+    pub fn is_included(&mut self, root: &Path, path: &Path, is_dir: bool) -> bool {
+        let Ok(rel) = path.strip_prefix(root) else { return false };
+        let comps: Vec<_> = rel.components().collect();
+        let mut cur = root.to_path_buf();
+        // every directory between the root and the path has to survive as well
+        for (i, c) in comps.iter().enumerate() {
+            cur.push(c);
+            let dir = is_dir || i + 1 < comps.len();
+            if dir && SKIP_DIRS.iter().any(|d| c.as_os_str() == *d) { return false; }
+            if !self.settings.hidden && c.as_os_str().to_string_lossy().starts_with('.') { return false; }
+            if self.settings.one_file_system && dir && !self.on_root_device(&cur) { return false; }
+            // overrides decide on their own, ahead of ignore files and file types
+            match self.overrides.matched(&cur, dir) {
+                Match::Ignore(_) => return false,
+                Match::Whitelist(_) => continue,
+                Match::None => {}
+            }
+            if self.ignored_by_rules(&cur, dir) { return false; }
+            if !dir {
+                if let Some(t) = &self.types {
+                    if t.matched(&cur, false).is_ignore() { return false; }
+                }
+            }
+        }
+        true
+    }
+}
+
+struct Root {
+    /// as configured; manifest keys and indexed paths are built from this
+    given: PathBuf,
+    /// the same root with its canonical path, which is what the OS reports events against
+    canon: RootConfig,
+    filter: IgnoreFilter,
+}
+
+impl Root {
+    fn new(config: &RootConfig) -> Result<Root> {
+        let canon = RootConfig { path: config.path.canonicalize().unwrap_or_else(|_| config.path.clone()), settings: config.settings.clone() };
+        Ok(Root { given: config.path.clone(), filter: IgnoreFilter::new(&canon)?, canon })
+    }
+
+    fn rebase(&self, p: &Path) -> Option<(PathBuf, PathBuf)> {
+        let rel = p.strip_prefix(&self.canon.path).or_else(|_| p.strip_prefix(&self.given)).ok()?;
+        Some((self.canon.path.join(rel), self.given.join(rel)))
+    }
+}
+
+/// Keep the index fresh by applying debounced filesystem events as per-path updates.
+/// Runs an incremental `index_all` first to pick up anything changed while not watching,
+/// then calls `on_batch` after every commit, or when the watcher reported errors. The index
+/// writer is only held while a batch is applied, so `warpseek index` can run alongside; a
+/// batch waits for it to finish. Only returns on error.
+pub fn watch(ws: &mut Workspace, mut on_batch: impl FnMut(&IndexReport)) -> Result<()> {
+    if ws.config.roots.is_empty() { return Err(anyhow!("no roots configured; run `warpseek init <paths>`")); }
+    on_batch(&index_all(ws)?);
+
+    let index_dir = ws.index_dir.clone();
+    let (index, fields) = open_index(&index_dir)?;
+    let mut roots = ws.config.roots.iter().map(Root::new).collect::<Result<Vec<_>>>()?;
+    let registry = Registry::new(&ws.config);
+
+    let (tx, rx) = channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    for r in &roots { watcher.watch(&r.given, RecursiveMode::Recursive)?; }
+
+    loop {
+        let mut dirty = HashMap::new();
+        let mut errors = Vec::new();
+        coalesce(rx.recv()?, &mut dirty, &mut errors);
+        let started = Instant::now();
+        while started.elapsed() < MAX_BATCH_DELAY {
+            match rx.recv_timeout(DEBOUNCE) {
+                Ok(ev) => coalesce(ev, &mut dirty, &mut errors),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return Err(anyhow!("file watcher stopped")),
+            }
+        }
+        // someone else (`warpseek index`) has the writer: keep collecting events until it's done
+        let writer: IndexWriter = loop {
+            match index.writer(64 * 1024 * 1024) {
+                Ok(w) => break w,
+                Err(TantivyError::LockFailure(..)) => match rx.recv_timeout(DEBOUNCE) {
+                    Ok(ev) => coalesce(ev, &mut dirty, &mut errors),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => return Err(anyhow!("file watcher stopped")),
+                },
+                Err(e) => return Err(e.into()),
+            }
+        };
+        // the manifest may have been rewritten while we didn't hold the writer
+        let mut manifest = Manifest::load(&index_dir)?;
+        let mut report = apply_batch(&writer, &fields, &ws.config, &registry, &mut manifest, &mut roots, dirty);
+        if report.changes() > 0 {
+            let mut writer = writer;
+            writer.commit()?;
+            manifest.save(&index_dir)?;
+            write_names(&index_dir, &manifest)?;
+        }
+        report.errors = errors;
+        if report.changes() > 0 || !report.errors.is_empty() { on_batch(&report); }
+    }
+}
+
+/// Merge an event into the pending batch. The flag records whether a directory at that
+/// path may have appeared (create or rename) and so needs to be walked.
+fn coalesce(ev: notify::Result<Event>, dirty: &mut HashMap<PathBuf, bool>, errors: &mut Vec<String>) {
+    match ev {
+        Ok(ev) if matches!(ev.kind, EventKind::Access(_)) => {}
+        Ok(ev) => {
+            let appeared = matches!(ev.kind, EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(_)));
+            for p in ev.paths { *dirty.entry(p).or_default() |= appeared; }
+        }
+        Err(e) => errors.push(e.to_string()),
+    }
+}
+
+fn apply_batch(writer: &IndexWriter, fields: &Fields, cfg: &Config, registry: &Registry, manifest: &mut Manifest, roots: &mut [Root], dirty: HashMap<PathBuf, bool>) -> IndexReport {
+    let mut report = IndexReport::default();
+    let mut files = BTreeSet::new();
+    let mut gone = Vec::new();
+    for (p, appeared) in dirty {
+        // nested roots: the most specific one owns the path
+        let Some(root) = roots.iter_mut().filter(|r| r.rebase(&p).is_some()).max_by_key(|r| r.canon.path.as_os_str().len()) else { continue };
+        let Some((abs, key)) = root.rebase(&p) else { continue };
+        if abs.file_name().and_then(|n| n.to_str()).is_some_and(|n| root.filter.is_ignore_file(n)) {
+            if let Some(dir) = abs.parent() { root.filter.invalidate(dir); }
+        }
+        if abs.is_dir() {
+            if !root.filter.is_included(&root.canon.path, &abs, true) { gone.push(key); continue; }
+            // a directory that was only touched says nothing about its contents
+            if !appeared { continue; }
+            let Ok(walker) = root.canon.walk_builder(&abs) else { continue };
+            for entry in walker.build().flatten() {
+                if entry.path().is_file() {
+                    if let Some((_, k)) = root.rebase(entry.path()) { files.insert(k); }
+                }
+            }
+        } else if abs.is_file() && root.filter.is_included(&root.canon.path, &abs, false) {
+            files.insert(key);
+        } else {
+            gone.push(key);
+        }
+    }
+
+    for key in gone {
+        let k = key.to_string_lossy().to_string();
+        let under = format!("{}{}", k, MAIN_SEPARATOR);
+        let victims: Vec<String> = manifest.files.range(k.clone()..)
+            .map(|(x, _)| x)
+            .take_while(|x| x.starts_with(&k))
+            .filter(|x| **x == k || x.starts_with(&under))
+            .cloned()
+            .collect();
+        for v in victims {
+            writer.delete_term(Term::from_field_text(fields.container, &v));
+            manifest.files.remove(&v);
+            report.deleted += 1;
+        }
+    }
+
+    for key in files {
+        match index_file(writer, fields, cfg, registry, manifest, &key) {
+            Some((k, entry, change)) => {
+                report.record(change);
+                manifest.files.insert(k, entry);
+            }
+            None => {
+                let k = key.to_string_lossy().to_string();
+                if manifest.files.remove(&k).is_some() {
+                    writer.delete_term(Term::from_field_text(fields.container, &k));
+                    report.deleted += 1;
+                }
+            }
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// A root directory with `files` in it, by relative path.
+    fn root_with(files: &[(&str, &str)]) -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        for (p, text) in files {
+            let path = root.join(p);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+        (dir, root)
+    }
+
+    #[test]
+    fn ignore_files_rule_out_paths_like_the_walker() {
+        let (_dir, root) = root_with(&[(".gitignore", "target/\n*.log\n!keep.log\n"), ("sub/.ignore", "secret.txt\n"), ("src/a.rs", ""), (".git/config", "")]);
+        let mut filter = IgnoreFilter::new(&RootConfig::new(root.clone())).unwrap();
+        let mut included = |p: &str, is_dir| filter.is_included(&root, &root.join(p), is_dir);
+        assert!(included("src/a.rs", false));
+        assert!(included("src", true));
+        assert!(!included("target", true));
+        assert!(!included("target/debug/a.rs", false));
+        assert!(!included("debug.log", false));
+        assert!(!included("src/deep/debug.log", false));
+        assert!(included("keep.log", false));
+        assert!(!included("sub/secret.txt", false));
+        assert!(included("secret.txt", false), "`.ignore` rules only apply below their directory");
+        assert!(!included(".git/config", false));
+        assert!(!filter.is_included(&root, Path::new("/elsewhere/a.rs"), false));
+
+        // rules are cached per directory until an ignore file there changes
+        fs::write(root.join(".gitignore"), "*.rs\n").unwrap();
+        assert!(filter.is_included(&root, &root.join("src/a.rs"), false));
+        filter.invalidate(&root);
+        assert!(!filter.is_included(&root, &root.join("src/a.rs"), false));
+        assert!(filter.is_included(&root, &root.join("debug.log"), false));
+        assert!(filter.is_ignore_file(".gitignore") && !filter.is_ignore_file("a.rs"));
+    }
+
+    #[test]
+    fn batches_apply_creates_edits_deletes_and_renames() {
+        let (_dir, root) = root_with(&[(".gitignore", "*.log\n"), ("a.txt", "alpha"), ("b.txt", "beta"), ("docs/one.md", "one"), ("docs/two.md", "two")]);
+        let home = tempfile::tempdir().unwrap();
+        let mut ws = Workspace::at(home.path()).unwrap();
+        ws.config.roots.push(RootConfig::new(root.clone()));
+        index_all(&mut ws).unwrap();
+        let (index, fields) = open_index(&ws.index_dir).unwrap();
+        let mut writer: IndexWriter = index.writer(15_000_000).unwrap();
+        let registry = Registry::new(&ws.config);
+        let mut roots = vec![Root::new(&ws.config.roots[0]).unwrap()];
+        let mut manifest = Manifest::load(&ws.index_dir).unwrap();
+        let mut apply = |events: &[(&str, bool)], manifest: &mut Manifest| {
+            let dirty = events.iter().map(|(p, appeared)| (root.join(p), *appeared)).collect();
+            let r = apply_batch(&writer, &fields, &ws.config, &registry, manifest, &mut roots, dirty);
+            (r.added, r.updated, r.deleted)
+        };
+        let keys = |m: &Manifest| m.files.keys().map(|k| k[root.to_string_lossy().len() + 1..].to_string()).collect::<Vec<_>>();
+        assert_eq!(keys(&manifest), ["a.txt", "b.txt", "docs/one.md", "docs/two.md"]);
+
+        fs::write(root.join("c.txt"), "gamma").unwrap();
+        fs::write(root.join("debug.log"), "noise").unwrap();
+        assert_eq!(apply(&[("c.txt", true), ("debug.log", true)], &mut manifest), (1, 0, 0));
+
+        fs::write(root.join("a.txt"), "alpha, edited").unwrap();
+        // a directory that was only touched isn't walked
+        assert_eq!(apply(&[("a.txt", false), ("docs", false)], &mut manifest), (0, 1, 0));
+
+        fs::remove_file(root.join("b.txt")).unwrap();
+        assert_eq!(apply(&[("b.txt", false)], &mut manifest), (0, 0, 1));
+
+        // a renamed directory takes everything under it along
+        fs::rename(root.join("docs"), root.join("notes")).unwrap();
+        assert_eq!(apply(&[("docs", true), ("notes", true)], &mut manifest), (2, 0, 2));
+        assert_eq!(keys(&manifest), ["a.txt", "c.txt", "notes/one.md", "notes/two.md"]);
+
+        writer.commit().unwrap();
+        let searcher = index.reader().unwrap().searcher();
+        assert_eq!(searcher.num_docs() as usize, manifest.files.len());
+    }
+}
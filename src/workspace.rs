@@ -0,0 +1,66 @@
+use anyhow::{anyhow, Result};
+use directories::ProjectDirs;
+use std::{env, fs, path::{Path, PathBuf}};
+
+use crate::search::Config;
+
+/// Environment variable that relocates config and index under a single directory.
+pub const HOME_ENV: &str = "WARPSEEK_HOME";
+
+pub fn project_dirs() -> Result<ProjectDirs> {
+    ProjectDirs::from("io", "frangedev", "warpseek").ok_or_else(|| anyhow!("cannot resolve project dirs"))
+}
+
+/// Everything a command needs to know about where one index lives and how it is configured.
+/// Profiles and `WARPSEEK_HOME` give fully isolated indexes.
+pub struct Workspace {
+    pub index_dir: PathBuf,
+    pub config_path: PathBuf,
+    pub config: Config,
+}
+
+impl Workspace {
+    /// A self-contained workspace rooted at `home`: `home/config.json` and `home/index`.
+    pub fn at(home: &Path) -> Result<Workspace> {
+        Workspace::load(home.join("index"), home.join("config.json"))
+    }
+
+    /// Resolve locations in order of precedence: explicit `index_dir`, then `WARPSEEK_HOME`,
+    /// then the OS data/config dirs. A `profile` gets its own subdirectory under either home.
+    pub fn resolve(index_dir: Option<PathBuf>, profile: Option<&str>) -> Result<Workspace> {
+        if let Some(p) = profile {
+            if p.is_empty() || p.contains(['/', '\\']) || p.starts_with('.') {
+                return Err(anyhow!("invalid profile name: {:?}", p));
+            }
+        }
+        let (data, config) = match env::var_os(HOME_ENV).filter(|v| !v.is_empty()) {
+            Some(home) => (PathBuf::from(&home), PathBuf::from(home)),
+            None => {
+                let dirs = project_dirs()?;
+                (dirs.data_dir().to_path_buf(), dirs.config_dir().to_path_buf())
+            }
+        };
+        let (data, config) = match profile {
+            Some(p) => (data.join("profiles").join(p), config.join("profiles").join(p)),
+            None => (data, config),
+        };
+        let index_dir = index_dir.unwrap_or_else(|| data.join("index"));
+        Workspace::load(index_dir, config.join("config.json"))
+    }
+
+    pub fn load(index_dir: PathBuf, config_path: PathBuf) -> Result<Workspace> {
+        let config = if config_path.exists() {
+            let s = fs::read_to_string(&config_path)?;
+            serde_json::from_str(&s)?
+        } else {
+            Config::default()
+        };
+        Ok(Workspace { index_dir, config_path, config })
+    }
+
+    pub fn save_config(&self) -> Result<()> {
+        if let Some(dir) = self.config_path.parent() { fs::create_dir_all(dir)?; }
+        fs::write(&self.config_path, serde_json::to_string_pretty(&self.config)?)?;
+        Ok(())
+    }
+}
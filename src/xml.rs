@@ -0,0 +1,85 @@
+use quick_xml::{Reader, events::{BytesStart, Event}};
+
+/// What `walk_xml` reports, in document order.
+pub enum Tag<'a> {
+    Open(&'a BytesStart<'a>),
+    Close(&'a [u8]),
+    Text(&'a str),
+}
+
+/// Feed every element and text run of `xml` to `f`, with element names stripped of their
+/// namespace prefix. Self-closing elements produce an `Open` and a `Close`. Stops quietly at
+/// the first syntax error, keeping whatever was seen until then.
+pub fn walk_xml(xml: &[u8], mut f: impl FnMut(Tag)) {
+    let mut reader = Reader::from_reader(xml);
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => f(Tag::Open(&e)),
+            Ok(Event::Empty(e)) => {
+                f(Tag::Open(&e));
+                f(Tag::Close(e.local_name().as_ref()));
+            }
+            Ok(Event::End(e)) => f(Tag::Close(e.local_name().as_ref())),
+            Ok(Event::Text(t)) => if let Ok(s) = t.unescape() { f(Tag::Text(&s)) },
+            Ok(Event::CData(t)) => if let Ok(s) = t.decode() { f(Tag::Text(&s)) },
+            Ok(Event::Eof) | Err(_) => break,
+            Ok(_) => {}
+        }
+        buf.clear();
+    }
+}
+
+/// Value of the attribute whose name, without its namespace prefix, is `local`.
+pub fn attr(e: &BytesStart, local: &[u8]) -> Option<String> {
+    e.attributes().flatten()
+        .find(|a| a.key.local_name().as_ref() == local)
+        .and_then(|a| a.unescape_value().ok().map(|v| v.to_string()))
+}
+
+/// Resolve a link target relative to the directory of the part that references it.
+pub fn resolve(base_dir: &str, target: &str) -> String {
+    if let Some(abs) = target.strip_prefix('/') { return abs.to_string(); }
+    let mut parts: Vec<&str> = base_dir.split('/').filter(|p| !p.is_empty()).collect();
+    for seg in target.split('/') {
+        match seg {
+            ".." => { parts.pop(); }
+            "." | "" => {}
+            s => parts.push(s),
+        }
+    }
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn walks_elements_without_prefixes_and_unescapes_text() {
+        let mut seen = Vec::new();
+        walk_xml(br#"<w:p w:val="1"><w:t>a &amp; b</w:t><w:br/><![CDATA[<raw>]]></w:p><broken"#, |tag| seen.push(match tag {
+            Tag::Open(e) => format!("<{}>", String::from_utf8_lossy(e.local_name().as_ref())),
+            Tag::Close(name) => format!("</{}>", String::from_utf8_lossy(name)),
+            Tag::Text(s) => s.to_string(),
+        }));
+        assert_eq!(seen, ["<p>", "<t>", "a & b", "</t>", "<br>", "</br>", "<raw>", "</p>"]);
+    }
+
+    #[test]
+    fn attributes_by_local_name() {
+        let mut found = Vec::new();
+        walk_xml(br#"<rel r:id="rId7" Target="a&amp;b.xml"/>"#, |tag| if let Tag::Open(e) = tag {
+            found = vec![attr(e, b"id"), attr(e, b"Target"), attr(e, b"Type")];
+        });
+        assert_eq!(found, [Some("rId7".to_string()), Some("a&b.xml".to_string()), None]);
+    }
+
+    #[test]
+    fn targets_resolve_against_the_referring_part() {
+        assert_eq!(resolve("ppt/slides", "../notesSlides/notesSlide1.xml"), "ppt/notesSlides/notesSlide1.xml");
+        assert_eq!(resolve("xl", "worksheets/./sheet1.xml"), "xl/worksheets/sheet1.xml");
+        assert_eq!(resolve("xl", "/xl/worksheets/sheet2.xml"), "xl/worksheets/sheet2.xml");
+        assert_eq!(resolve("", "../../ch1.xhtml"), "ch1.xhtml");
+    }
+}